@@ -0,0 +1,55 @@
+use bigdecimal::BigDecimal;
+use chrono::{Datelike, Duration, NaiveDate};
+use criterion::{criterion_group, criterion_main, Criterion};
+use portfolio_tracker::portfolio::build_porfolio;
+use portfolio_tracker::price::{DailyPrice, PriceMarks};
+use portfolio_tracker::trade::TradeForCalculation;
+
+const YEARS: i64 = 15;
+
+/// Synthetic history for one ticker: a price every weekday and a buy on the
+/// first day of every month.
+fn history(ticker: &str) -> (Vec<DailyPrice>, Vec<TradeForCalculation>) {
+    let start = NaiveDate::from_ymd(2008, 1, 1);
+    let mut prices = Vec::new();
+    let mut trades = Vec::new();
+    for offset in 0..YEARS * 365 {
+        let date = start + Duration::days(offset);
+        if date.weekday().number_from_monday() <= 5 {
+            prices.push(DailyPrice {
+                date,
+                price: BigDecimal::from(50 + offset % 40),
+                ticker: ticker.to_string(),
+                preliminary: false,
+                adjusted_price: None,
+                split_coefficient: None,
+                marks: PriceMarks::default(),
+            });
+        }
+        if date.day() == 1 {
+            trades.push(TradeForCalculation {
+                id: offset,
+                date,
+                amount: BigDecimal::from(10),
+                price: BigDecimal::from(50 + offset % 40),
+                fee: BigDecimal::from(0),
+                ticker: ticker.to_string(),
+            });
+        }
+    }
+    (prices, trades)
+}
+
+fn bench_build_porfolio(c: &mut Criterion) {
+    let (prices, trades) = history("IWDA.AMS");
+
+    c.bench_function("build_porfolio 15y", |b| {
+        b.iter(|| build_porfolio(prices.clone(), trades.clone(), false, None))
+    });
+    c.bench_function("build_porfolio 15y forward fill", |b| {
+        b.iter(|| build_porfolio(prices.clone(), trades.clone(), true, None))
+    });
+}
+
+criterion_group!(benches, bench_build_porfolio);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+DROP TABLE IF EXISTS portfolio_valuations;
+DROP TABLE IF EXISTS operations;
+DROP TABLE IF EXISTS portfolio_snapshots;
+DROP TABLE IF EXISTS ticker_targets;
+DROP TABLE IF EXISTS ticker_aliases;
+DROP TABLE IF EXISTS corporate_actions;
+DROP TABLE IF EXISTS period_locks;
+DROP TABLE IF EXISTS fx_rates;
+DROP TABLE IF EXISTS report_archive;
+DROP FUNCTION IF EXISTS report_archive_immutable;
+DROP TABLE IF EXISTS cash_movements;
+DROP TABLE IF EXISTS trade_tags;
+DROP TABLE IF EXISTS dividends;
+DROP TABLE IF EXISTS journal_entries;
+DROP TABLE IF EXISTS price_quotes;
+DROP TABLE IF EXISTS prices;
+DROP TABLE IF EXISTS trades;
@@ -0,0 +1,156 @@
+-- The schema the SQLite migrations up to 20261015340000 build, in one step.
+-- Dates, decimals and timestamps are TEXT like in SQLite, so they compare the
+-- same way on both databases.
+CREATE TABLE IF NOT EXISTS trades (
+            id      BIGSERIAL PRIMARY KEY,
+            ticker  TEXT NOT NULL,
+            date    TEXT NOT NULL,
+            type    TEXT NOT NULL,
+            amount  TEXT NOT NULL,
+            price   TEXT NOT NULL,
+            fee     TEXT
+);
+
+CREATE TABLE IF NOT EXISTS prices (
+            id                  BIGSERIAL PRIMARY KEY,
+            ticker              TEXT NOT NULL,
+            date                TEXT NOT NULL,
+            price               TEXT NOT NULL,
+            preliminary         BOOLEAN NOT NULL DEFAULT FALSE,
+            source              TEXT,
+            adjusted_price      TEXT,
+            split_coefficient   TEXT,
+            pinned              BOOLEAN NOT NULL DEFAULT FALSE,
+            nav                 TEXT,
+            bid                 TEXT,
+            ask                 TEXT,
+            UNIQUE (ticker, date)
+);
+
+CREATE TABLE IF NOT EXISTS price_quotes (
+            id                  BIGSERIAL PRIMARY KEY,
+            ticker              TEXT NOT NULL,
+            date                TEXT NOT NULL,
+            source              TEXT NOT NULL,
+            price               TEXT NOT NULL,
+            preliminary         BOOLEAN NOT NULL DEFAULT FALSE,
+            adjusted_price      TEXT,
+            split_coefficient   TEXT,
+            UNIQUE (ticker, date, source)
+);
+
+CREATE TABLE IF NOT EXISTS journal_entries (
+            id      BIGSERIAL PRIMARY KEY,
+            ticker  TEXT,
+            date    TEXT NOT NULL,
+            text    TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dividends (
+            id                      BIGSERIAL PRIMARY KEY,
+            ticker                  TEXT NOT NULL,
+            ex_date                 TEXT NOT NULL,
+            pay_date                TEXT NOT NULL,
+            gross_amount            TEXT NOT NULL,
+            tax                     TEXT NOT NULL,
+            reinvested              BOOLEAN NOT NULL DEFAULT FALSE,
+            reinvestment_trade_id   BIGINT REFERENCES trades (id),
+            currency                TEXT
+);
+
+CREATE TABLE IF NOT EXISTS trade_tags (
+            trade_id    BIGINT NOT NULL,
+            tag         TEXT NOT NULL,
+            PRIMARY KEY (trade_id, tag)
+);
+
+CREATE TABLE IF NOT EXISTS cash_movements (
+            id      BIGSERIAL PRIMARY KEY,
+            date    TEXT NOT NULL,
+            type    TEXT NOT NULL,
+            amount  TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS report_archive (
+            id          BIGSERIAL PRIMARY KEY,
+            report      TEXT NOT NULL,
+            created_at  TEXT NOT NULL,
+            inputs_hash TEXT NOT NULL,
+            output      TEXT NOT NULL
+);
+
+CREATE OR REPLACE FUNCTION report_archive_immutable() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'archived reports are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER report_archive_no_update BEFORE UPDATE ON report_archive
+FOR EACH ROW EXECUTE FUNCTION report_archive_immutable();
+
+CREATE TRIGGER report_archive_no_delete BEFORE DELETE ON report_archive
+FOR EACH ROW EXECUTE FUNCTION report_archive_immutable();
+
+CREATE TABLE IF NOT EXISTS fx_rates (
+            id              BIGSERIAL PRIMARY KEY,
+            currency        TEXT NOT NULL,
+            base_currency   TEXT NOT NULL,
+            date            TEXT NOT NULL,
+            rate            TEXT NOT NULL,
+            UNIQUE (currency, base_currency, date)
+);
+
+CREATE TABLE IF NOT EXISTS period_locks (
+            id          BIGSERIAL PRIMARY KEY,
+            until       TEXT NOT NULL,
+            created_at  TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS corporate_actions (
+            id          BIGSERIAL PRIMARY KEY,
+            ticker      TEXT NOT NULL,
+            date        TEXT NOT NULL,
+            type        TEXT NOT NULL,
+            ratio       TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS ticker_aliases (
+            ticker      TEXT PRIMARY KEY,
+            symbol      TEXT NOT NULL,
+            provider    TEXT NOT NULL DEFAULT 'alpha_vantage',
+            currency    TEXT,
+            exchange    TEXT
+);
+
+INSERT INTO ticker_aliases ( ticker, symbol, provider ) VALUES
+    ( 'BTC', 'bitcoin', 'coingecko' ),
+    ( 'ETH', 'ethereum', 'coingecko' )
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS ticker_targets (
+            ticker          TEXT PRIMARY KEY,
+            target_price    TEXT,
+            exit_criteria   TEXT,
+            reached_on      TEXT
+);
+
+CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+            date                    TEXT NOT NULL,
+            ticker                  TEXT NOT NULL,
+            amount_in_base_currency TEXT NOT NULL,
+            preliminary             BOOLEAN NOT NULL DEFAULT FALSE,
+            PRIMARY KEY (ticker, date)
+);
+
+CREATE TABLE IF NOT EXISTS operations (
+            id          BIGSERIAL PRIMARY KEY,
+            kind        TEXT NOT NULL,
+            created_at  TEXT NOT NULL,
+            undone_at   TEXT,
+            payload     TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS portfolio_valuations (
+            portfolio   TEXT PRIMARY KEY,
+            valuation   TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS journal_entries (
+            id      INTEGER PRIMARY KEY,
+            ticker  TEXT,
+            date    TEXT NOT NULL,
+            text    TEXT NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE prices DROP COLUMN preliminary;
\ No newline at end of file
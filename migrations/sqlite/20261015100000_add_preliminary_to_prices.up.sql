@@ -0,0 +1 @@
+ALTER TABLE prices ADD COLUMN preliminary INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS dividends (
+            id              INTEGER PRIMARY KEY,
+            ticker          TEXT NOT NULL,
+            ex_date         TEXT NOT NULL,
+            pay_date        TEXT NOT NULL,
+            gross_amount    TEXT NOT NULL,
+            tax             TEXT NOT NULL
+);
\ No newline at end of file
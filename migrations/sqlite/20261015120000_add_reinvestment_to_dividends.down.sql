@@ -0,0 +1,2 @@
+ALTER TABLE dividends DROP COLUMN reinvestment_trade_id;
+ALTER TABLE dividends DROP COLUMN reinvested;
\ No newline at end of file
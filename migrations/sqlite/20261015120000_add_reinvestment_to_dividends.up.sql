@@ -0,0 +1,2 @@
+ALTER TABLE dividends ADD COLUMN reinvested INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE dividends ADD COLUMN reinvestment_trade_id INTEGER REFERENCES trades (id);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS trade_tags;
\ No newline at end of file
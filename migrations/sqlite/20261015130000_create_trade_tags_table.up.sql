@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS trade_tags (
+            trade_id    INTEGER NOT NULL,
+            tag         TEXT NOT NULL,
+            PRIMARY KEY (trade_id, tag)
+);
\ No newline at end of file
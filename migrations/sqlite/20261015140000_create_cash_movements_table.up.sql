@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS cash_movements (
+            id      INTEGER PRIMARY KEY,
+            date    TEXT NOT NULL,
+            type    TEXT NOT NULL,
+            amount  TEXT NOT NULL
+);
\ No newline at end of file
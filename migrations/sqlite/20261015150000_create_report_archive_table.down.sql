@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS report_archive_no_delete;
+DROP TRIGGER IF EXISTS report_archive_no_update;
+DROP TABLE IF EXISTS report_archive;
\ No newline at end of file
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS report_archive (
+            id          INTEGER PRIMARY KEY,
+            report      TEXT NOT NULL,
+            created_at  TEXT NOT NULL,
+            inputs_hash TEXT NOT NULL,
+            output      TEXT NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS report_archive_no_update BEFORE UPDATE ON report_archive
+BEGIN
+    SELECT RAISE(ABORT, 'archived reports are immutable');
+END;
+
+CREATE TRIGGER IF NOT EXISTS report_archive_no_delete BEFORE DELETE ON report_archive
+BEGIN
+    SELECT RAISE(ABORT, 'archived reports are immutable');
+END;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE trades ADD COLUMN fee TEXT;
\ No newline at end of file
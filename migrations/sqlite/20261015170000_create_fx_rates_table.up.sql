@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS fx_rates (
+            id          INTEGER PRIMARY KEY,
+            currency    TEXT NOT NULL,
+            date        TEXT NOT NULL,
+            rate        TEXT NOT NULL,
+            UNIQUE (currency, date)
+);
\ No newline at end of file
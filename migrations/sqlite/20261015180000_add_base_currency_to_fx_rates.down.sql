@@ -0,0 +1,11 @@
+CREATE TABLE fx_rates_without_base (
+            id          INTEGER PRIMARY KEY,
+            currency    TEXT NOT NULL,
+            date        TEXT NOT NULL,
+            rate        TEXT NOT NULL,
+            UNIQUE (currency, date)
+);
+INSERT INTO fx_rates_without_base ( id, currency, date, rate )
+SELECT id, currency, date, rate FROM fx_rates WHERE base_currency = 'EUR';
+DROP TABLE fx_rates;
+ALTER TABLE fx_rates_without_base RENAME TO fx_rates;
\ No newline at end of file
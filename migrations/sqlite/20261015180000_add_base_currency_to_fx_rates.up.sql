@@ -0,0 +1,12 @@
+CREATE TABLE fx_rates_with_base (
+            id              INTEGER PRIMARY KEY,
+            currency        TEXT NOT NULL,
+            base_currency   TEXT NOT NULL,
+            date            TEXT NOT NULL,
+            rate            TEXT NOT NULL,
+            UNIQUE (currency, base_currency, date)
+);
+INSERT INTO fx_rates_with_base ( id, currency, base_currency, date, rate )
+SELECT id, currency, 'EUR', date, rate FROM fx_rates;
+DROP TABLE fx_rates;
+ALTER TABLE fx_rates_with_base RENAME TO fx_rates;
\ No newline at end of file
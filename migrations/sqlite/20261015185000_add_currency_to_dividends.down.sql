@@ -0,0 +1 @@
+ALTER TABLE dividends DROP COLUMN currency;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE dividends ADD COLUMN currency TEXT;
\ No newline at end of file
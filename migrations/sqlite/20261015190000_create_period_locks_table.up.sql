@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS period_locks (
+            id          INTEGER PRIMARY KEY,
+            until       TEXT NOT NULL,
+            created_at  TEXT NOT NULL
+);
\ No newline at end of file
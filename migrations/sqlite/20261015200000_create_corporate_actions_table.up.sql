@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS corporate_actions (
+            id          INTEGER PRIMARY KEY,
+            ticker      TEXT NOT NULL,
+            date        TEXT NOT NULL,
+            type        TEXT NOT NULL,
+            ratio       TEXT NOT NULL
+);
\ No newline at end of file
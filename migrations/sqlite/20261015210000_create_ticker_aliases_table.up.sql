@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS ticker_aliases (
+            ticker      TEXT PRIMARY KEY,
+            symbol      TEXT NOT NULL
+);
\ No newline at end of file
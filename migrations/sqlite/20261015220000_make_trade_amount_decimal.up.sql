@@ -0,0 +1,30 @@
+-- Dividends reference trades, so they are set aside while trades is rebuilt.
+CREATE TABLE dividends_set_aside AS SELECT * FROM dividends;
+DROP TABLE dividends;
+CREATE TABLE trades_with_decimal_amount (
+            id      INTEGER PRIMARY KEY,
+            ticker  TEXT NOT NULL,
+            date    TEXT NOT NULL,
+            type    TEXT NOT NULL,
+            amount  TEXT NOT NULL,
+            price   TEXT NOT NULL,
+            fee     TEXT
+);
+INSERT INTO trades_with_decimal_amount ( id, ticker, date, type, amount, price, fee )
+SELECT id, ticker, date, type, CAST(amount AS TEXT), price, fee FROM trades;
+DROP TABLE trades;
+ALTER TABLE trades_with_decimal_amount RENAME TO trades;
+CREATE TABLE dividends (
+            id                      INTEGER PRIMARY KEY,
+            ticker                  TEXT NOT NULL,
+            ex_date                 TEXT NOT NULL,
+            pay_date                TEXT NOT NULL,
+            gross_amount            TEXT NOT NULL,
+            tax                     TEXT NOT NULL,
+            reinvested              INTEGER NOT NULL DEFAULT 0,
+            reinvestment_trade_id   INTEGER REFERENCES trades (id),
+            currency                TEXT
+);
+INSERT INTO dividends ( id, ticker, ex_date, pay_date, gross_amount, tax, reinvested, reinvestment_trade_id, currency )
+SELECT id, ticker, ex_date, pay_date, gross_amount, tax, reinvested, reinvestment_trade_id, currency FROM dividends_set_aside;
+DROP TABLE dividends_set_aside;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS ticker_targets;
\ No newline at end of file
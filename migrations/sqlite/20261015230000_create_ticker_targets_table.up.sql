@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS ticker_targets (
+            ticker          TEXT PRIMARY KEY,
+            target_price    TEXT,
+            exit_criteria   TEXT,
+            reached_on      TEXT
+);
\ No newline at end of file
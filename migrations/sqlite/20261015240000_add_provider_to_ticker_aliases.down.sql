@@ -0,0 +1,8 @@
+CREATE TABLE ticker_aliases_without_provider (
+            ticker      TEXT PRIMARY KEY,
+            symbol      TEXT NOT NULL
+);
+INSERT INTO ticker_aliases_without_provider ( ticker, symbol )
+SELECT ticker, symbol FROM ticker_aliases;
+DROP TABLE ticker_aliases;
+ALTER TABLE ticker_aliases_without_provider RENAME TO ticker_aliases;
\ No newline at end of file
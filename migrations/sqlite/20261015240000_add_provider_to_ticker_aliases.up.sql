@@ -0,0 +1 @@
+ALTER TABLE ticker_aliases ADD COLUMN provider TEXT NOT NULL DEFAULT 'alpha_vantage';
\ No newline at end of file
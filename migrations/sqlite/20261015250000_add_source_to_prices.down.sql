@@ -0,0 +1 @@
+ALTER TABLE prices DROP COLUMN source;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE prices ADD COLUMN source TEXT;
\ No newline at end of file
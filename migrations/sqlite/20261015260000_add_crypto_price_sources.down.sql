@@ -0,0 +1 @@
+DELETE FROM ticker_aliases WHERE ticker IN ( 'BTC', 'ETH' ) AND provider = 'coingecko';
\ No newline at end of file
@@ -0,0 +1,3 @@
+INSERT OR IGNORE INTO ticker_aliases ( ticker, symbol, provider ) VALUES
+    ( 'BTC', 'bitcoin', 'coingecko' ),
+    ( 'ETH', 'ethereum', 'coingecko' );
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE prices DROP COLUMN split_coefficient;
+ALTER TABLE prices DROP COLUMN adjusted_price;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE prices ADD COLUMN adjusted_price TEXT;
+ALTER TABLE prices ADD COLUMN split_coefficient TEXT;
\ No newline at end of file
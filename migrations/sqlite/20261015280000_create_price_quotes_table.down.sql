@@ -0,0 +1,2 @@
+ALTER TABLE prices DROP COLUMN pinned;
+DROP TABLE IF EXISTS price_quotes;
\ No newline at end of file
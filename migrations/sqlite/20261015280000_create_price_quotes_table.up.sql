@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS price_quotes (
+            id                  INTEGER PRIMARY KEY,
+            ticker              TEXT NOT NULL,
+            date                TEXT NOT NULL,
+            source              TEXT NOT NULL,
+            price               TEXT NOT NULL,
+            preliminary         INTEGER NOT NULL DEFAULT 0,
+            adjusted_price      TEXT,
+            split_coefficient   TEXT,
+            UNIQUE (ticker, date, source)
+);
+INSERT INTO price_quotes ( ticker, date, source, price, preliminary, adjusted_price, split_coefficient )
+SELECT ticker, date, source, price, preliminary, adjusted_price, split_coefficient FROM prices
+WHERE source IS NOT NULL;
+ALTER TABLE prices ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE ticker_aliases DROP COLUMN exchange;
+ALTER TABLE ticker_aliases DROP COLUMN currency;
\ No newline at end of file
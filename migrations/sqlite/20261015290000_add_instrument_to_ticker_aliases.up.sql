@@ -0,0 +1,2 @@
+ALTER TABLE ticker_aliases ADD COLUMN currency TEXT;
+ALTER TABLE ticker_aliases ADD COLUMN exchange TEXT;
\ No newline at end of file
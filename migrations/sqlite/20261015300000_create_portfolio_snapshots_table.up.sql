@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+            date                    TEXT NOT NULL,
+            ticker                  TEXT NOT NULL,
+            amount_in_base_currency TEXT NOT NULL,
+            preliminary             INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (ticker, date)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS operations;
\ No newline at end of file
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS operations (
+            id          INTEGER PRIMARY KEY,
+            kind        TEXT NOT NULL,
+            created_at  TEXT NOT NULL,
+            undone_at   TEXT,
+            payload     TEXT NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1 @@
+-- The snapshots are computed again on the next price update.
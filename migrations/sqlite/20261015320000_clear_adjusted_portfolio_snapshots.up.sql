@@ -0,0 +1 @@
+DELETE FROM portfolio_snapshots;
@@ -0,0 +1,3 @@
+ALTER TABLE prices DROP COLUMN ask;
+ALTER TABLE prices DROP COLUMN bid;
+ALTER TABLE prices DROP COLUMN nav;
\ No newline at end of file
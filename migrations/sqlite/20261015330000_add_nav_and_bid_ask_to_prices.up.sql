@@ -0,0 +1,3 @@
+ALTER TABLE prices ADD COLUMN nav TEXT;
+ALTER TABLE prices ADD COLUMN bid TEXT;
+ALTER TABLE prices ADD COLUMN ask TEXT;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS portfolio_valuations;
\ No newline at end of file
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS portfolio_valuations (
+            portfolio   TEXT PRIMARY KEY,
+            valuation   TEXT NOT NULL
+);
\ No newline at end of file
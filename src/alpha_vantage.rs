@@ -0,0 +1,176 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Calls the free tier allows per minute.
+const CALLS_PER_MINUTE: usize = 5;
+
+/// Attempts at a call before giving up, the first one included.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Wait before the first retry, doubled before every following one.
+const INITIAL_RETRY_DELAY_SECONDS: u64 = 15;
+
+pub enum AlphaVantageError {
+    Request(reqwest::Error),
+    /// The call frequency or the daily quota was exceeded.
+    RateLimited(String),
+    /// The call was refused, like one for an unknown symbol.
+    Rejected(String),
+    InvalidResponse(String),
+}
+
+impl From<reqwest::Error> for AlphaVantageError {
+    fn from(e: reqwest::Error) -> Self {
+        // The URL carries the API key.
+        AlphaVantageError::Request(e.without_url())
+    }
+}
+
+impl fmt::Display for AlphaVantageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlphaVantageError::Request(e) => write!(f, "request failed: {}", e),
+            AlphaVantageError::RateLimited(message) => write!(f, "rate limited: {}", message),
+            AlphaVantageError::Rejected(message) => write!(f, "rejected: {}", message),
+            AlphaVantageError::InvalidResponse(message) => {
+                write!(f, "invalid response: {}", message)
+            }
+        }
+    }
+}
+
+impl AlphaVantageError {
+    /// Whether the same call may succeed later.
+    fn is_transient(&self) -> bool {
+        match self {
+            AlphaVantageError::Request(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.status().is_some_and(|status| status.is_server_error())
+            }
+            AlphaVantageError::RateLimited(_) => true,
+            AlphaVantageError::Rejected(_) | AlphaVantageError::InvalidResponse(_) => false,
+        }
+    }
+}
+
+/// Start times of the calls made in the last minute, shared by every call to
+/// Alpha Vantage in the process.
+fn recent_calls() -> &'static Mutex<VecDeque<Instant>> {
+    static RECENT_CALLS: OnceLock<Mutex<VecDeque<Instant>>> = OnceLock::new();
+    RECENT_CALLS.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+/// Waits until a call fits within `CALLS_PER_MINUTE` and records it.
+async fn wait_for_slot() {
+    let period = Duration::from_secs(60);
+    loop {
+        let mut calls = recent_calls().lock().await;
+        let now = Instant::now();
+        while calls
+            .front()
+            .is_some_and(|call| now.duration_since(*call) >= period)
+        {
+            calls.pop_front();
+        }
+        let oldest = match calls.front() {
+            Some(oldest) if calls.len() >= CALLS_PER_MINUTE => *oldest,
+            _ => {
+                calls.push_back(now);
+                return;
+            }
+        };
+        drop(calls);
+        tokio::time::sleep(period - now.duration_since(oldest)).await;
+    }
+}
+
+async fn call<T: DeserializeOwned>(url: &str) -> Result<T, AlphaVantageError> {
+    let body = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| AlphaVantageError::InvalidResponse(e.to_string()))?;
+    // Exceeded limits and refused calls are answered with a 200 and a message
+    // instead of the data.
+    let message = |key: &str| {
+        body.get(key)
+            .map(|message| message.as_str().unwrap_or_default().to_string())
+    };
+    if let Some(note) = message("Note").or_else(|| message("Information")) {
+        return Err(AlphaVantageError::RateLimited(note));
+    }
+    if let Some(error) = message("Error Message") {
+        return Err(AlphaVantageError::Rejected(error));
+    }
+    serde_json::from_value(body).map_err(|e| AlphaVantageError::InvalidResponse(e.to_string()))
+}
+
+/// Symbol found by `search_symbols`.
+#[derive(Deserialize)]
+pub struct SymbolMatch {
+    #[serde(rename(deserialize = "1. symbol"))]
+    pub symbol: String,
+    #[serde(rename(deserialize = "2. name"))]
+    pub name: String,
+    #[serde(rename(deserialize = "3. type"))]
+    pub kind: String,
+    #[serde(rename(deserialize = "4. region"))]
+    pub region: String,
+    #[serde(rename(deserialize = "8. currency"))]
+    pub currency: String,
+    /// From 0 to 1.
+    #[serde(rename(deserialize = "9. matchScore"))]
+    pub match_score: String,
+}
+
+#[derive(Deserialize)]
+struct SymbolSearchResponse {
+    #[serde(rename(deserialize = "bestMatches"))]
+    best_matches: Vec<SymbolMatch>,
+}
+
+/// Symbols matching `keywords`, best match first, with SYMBOL_SEARCH.
+pub async fn search_symbols(
+    api_key: &str,
+    keywords: &str,
+) -> Result<Vec<SymbolMatch>, AlphaVantageError> {
+    let url = reqwest::Url::parse_with_params(
+        "https://www.alphavantage.co/query?function=SYMBOL_SEARCH",
+        &[("keywords", keywords), ("apikey", api_key)],
+    )
+    .map_err(|e| AlphaVantageError::InvalidResponse(e.to_string()))?;
+    Ok(query::<SymbolSearchResponse>(url.as_str())
+        .await?
+        .best_matches)
+}
+
+/// Calls the Alpha Vantage `url` and deserializes its body. Calls are spaced
+/// out to stay within the free tier limit, and transient failures, like an
+/// exceeded limit, are retried with exponential backoff.
+pub async fn query<T: DeserializeOwned>(url: &str) -> Result<T, AlphaVantageError> {
+    let mut delay = Duration::from_secs(INITIAL_RETRY_DELAY_SECONDS);
+    let mut attempt = 1;
+    loop {
+        wait_for_slot().await;
+        match call(url).await {
+            Err(e) if attempt < MAX_ATTEMPTS && e.is_transient() => {
+                println!(
+                    "Alpha Vantage call failed, retrying in {}s: {}",
+                    delay.as_secs(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
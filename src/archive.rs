@@ -0,0 +1,85 @@
+use crate::db::DbPool;
+use crate::dividend::DividendForCalculation;
+use crate::trade::TradeForCalculation;
+use sha2::{Digest, Sha256};
+
+/// SHA-256 of the trades and dividends a report was computed from, so an
+/// archived report can be checked against the current data.
+pub fn inputs_hash(trades: &[TradeForCalculation], dividends: &[DividendForCalculation]) -> String {
+    let mut hasher = Sha256::new();
+    for trade in trades {
+        hasher.update(format!(
+            "trade|{}|{}|{}|{}|{}|{}\n",
+            trade.id, trade.ticker, trade.date, trade.amount, trade.price, trade.fee
+        ));
+    }
+    for dividend in dividends {
+        hasher.update(format!(
+            "dividend|{}|{}|{}\n",
+            dividend.ticker, dividend.pay_date, dividend.net_amount
+        ));
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub struct ArchiveReport {
+    pub report: String,
+    pub created_at: String,
+    pub inputs_hash: String,
+    pub output: String,
+}
+
+/// Stores a rendered report. The table only accepts inserts.
+pub async fn archive_report(pool: &DbPool, report: ArchiveReport) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO report_archive ( report, created_at, inputs_hash, output )
+        VALUES ( $1, $2, $3, $4 )
+        RETURNING id as "id!"
+        "#,
+        report.report,
+        report.created_at,
+        report.inputs_hash,
+        report.output
+    )
+    .fetch_one(pool)
+    .await?
+    .id)
+}
+
+pub struct ArchivedReport {
+    pub id: i64,
+    pub report: String,
+    pub created_at: String,
+    pub inputs_hash: String,
+}
+
+pub async fn list_archived_reports(pool: &DbPool) -> Result<Vec<ArchivedReport>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedReport,
+        r#"
+        SELECT id as "id!", report, created_at, inputs_hash FROM report_archive ORDER BY id asc
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_archived_report_output(
+    pool: &DbPool,
+    archive_id: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT output FROM report_archive WHERE id = $1
+        "#,
+        archive_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| row.output))
+}
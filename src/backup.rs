@@ -0,0 +1,82 @@
+use crate::db::DbPool;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+pub enum BackupError {
+    Database(sqlx::Error),
+    File(io::Error),
+    /// Only SQLite databases are copied; Postgres has `pg_dump` for that.
+    Unsupported,
+}
+
+impl From<sqlx::Error> for BackupError {
+    fn from(e: sqlx::Error) -> Self {
+        BackupError::Database(e)
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::File(e)
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupError::Database(e) => write!(f, "database: {}", e),
+            BackupError::File(e) => write!(f, "file: {}", e),
+            BackupError::Unsupported => {
+                write!(f, "only SQLite databases are backed up, use pg_dump")
+            }
+        }
+    }
+}
+
+/// A copy of the database, ready to be restored by putting it in place of the
+/// database file while the server is stopped.
+pub struct Backup {
+    pub created_at: DateTime<Utc>,
+    pub bytes: u64,
+}
+
+/// Copies the database to `path`, replacing the copy there only once the new
+/// one is complete, so a failure midway leaves the previous copy intact. The
+/// copy is consistent even while requests write to the database.
+#[cfg(feature = "sqlite")]
+pub async fn backup_to(pool: &DbPool, path: &Path) -> Result<Backup, BackupError> {
+    let partial = path.with_extension("partial");
+    match tokio::fs::remove_file(&partial).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+    sqlx::query("VACUUM INTO $1")
+        .bind(partial.to_string_lossy())
+        .execute(pool)
+        .await?;
+    tokio::fs::rename(&partial, path).await?;
+    Ok(last_backup(path).await?.unwrap_or(Backup {
+        created_at: Utc::now(),
+        bytes: 0,
+    }))
+}
+
+#[cfg(feature = "postgres")]
+pub async fn backup_to(_pool: &DbPool, _path: &Path) -> Result<Backup, BackupError> {
+    Err(BackupError::Unsupported)
+}
+
+/// The copy at `path`, if there is one.
+pub async fn last_backup(path: &Path) -> Result<Option<Backup>, io::Error> {
+    let metadata = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(Some(Backup {
+        created_at: metadata.modified()?.into(),
+        bytes: metadata.len(),
+    }))
+}
@@ -0,0 +1,140 @@
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Store of serialized response payloads. Every mutation of trades or prices
+/// must call `clear` once it is done; the generation counter it bumps keeps a
+/// computation that started before the mutation from storing a stale payload.
+#[async_trait]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<String>;
+
+    async fn generation(&self) -> u64;
+
+    /// Stores `payload` unless the cache was cleared since `generation` was read.
+    async fn insert(&self, key: String, payload: String, generation: u64);
+
+    async fn clear(&self);
+}
+
+/// Cache local to the process.
+#[derive(Default)]
+pub struct InMemoryCache {
+    entries: RwLock<HashMap<String, String>>,
+    generation: AtomicU64,
+}
+
+#[async_trait]
+impl ResponseCache for InMemoryCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        self.entries.read().unwrap().get(key).cloned()
+    }
+
+    async fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    async fn insert(&self, key: String, payload: String, generation: u64) {
+        let mut entries = self.entries.write().unwrap();
+        if self.generation.load(Ordering::SeqCst) == generation {
+            entries.insert(key, payload);
+        }
+    }
+
+    async fn clear(&self) {
+        let mut entries = self.entries.write().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+    }
+}
+
+const REDIS_KEY_PREFIX: &str = "portfolio-tracker";
+const REDIS_ENTRY_TTL_SECONDS: usize = 24 * 60 * 60;
+
+/// Cache shared by every instance pointing at the same Redis. Entries are
+/// namespaced by generation, so `clear` only has to bump the generation:
+/// payloads of older generations are never read again and expire on their own.
+/// Redis errors are logged and treated as misses.
+pub struct RedisCache {
+    connection: ConnectionManager,
+}
+
+impl RedisCache {
+    pub async fn connect(url: &str) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            connection: ConnectionManager::new(client).await?,
+        })
+    }
+
+    fn generation_key() -> String {
+        format!("{}:generation", REDIS_KEY_PREFIX)
+    }
+
+    fn entry_key(key: &str, generation: u64) -> String {
+        format!("{}:{}:{}", REDIS_KEY_PREFIX, generation, key)
+    }
+}
+
+#[async_trait]
+impl ResponseCache for RedisCache {
+    async fn get(&self, key: &str) -> Option<String> {
+        let generation = self.generation().await;
+        match self
+            .connection
+            .clone()
+            .get(Self::entry_key(key, generation))
+            .await
+        {
+            Ok(payload) => payload,
+            Err(e) => {
+                println!("Error reading {} from Redis {}", key, e);
+                None
+            }
+        }
+    }
+
+    async fn generation(&self) -> u64 {
+        match self
+            .connection
+            .clone()
+            .get::<_, Option<u64>>(Self::generation_key())
+            .await
+        {
+            Ok(generation) => generation.unwrap_or_default(),
+            Err(e) => {
+                println!("Error reading the cache generation from Redis {}", e);
+                0
+            }
+        }
+    }
+
+    async fn insert(&self, key: String, payload: String, generation: u64) {
+        let stored: redis::RedisResult<()> = self
+            .connection
+            .clone()
+            .set_ex(
+                Self::entry_key(&key, generation),
+                payload,
+                REDIS_ENTRY_TTL_SECONDS,
+            )
+            .await;
+        if let Err(e) = stored {
+            println!("Error storing {} in Redis {}", key, e);
+        }
+    }
+
+    async fn clear(&self) {
+        let cleared: redis::RedisResult<u64> = self
+            .connection
+            .clone()
+            .incr(Self::generation_key(), 1)
+            .await;
+        if let Err(e) = cleared {
+            println!("Error clearing the Redis cache {}", e);
+        }
+    }
+}
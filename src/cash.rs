@@ -0,0 +1,160 @@
+use crate::db::DbPool;
+use crate::dividend::DividendForCalculation;
+use crate::trade::TradeForCalculation;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use std::str::FromStr;
+
+pub struct CreateCashMovement {
+    pub date: String,
+    /// `DEPOSIT` or `WITHDRAWAL`.
+    pub r#type: String,
+    pub amount: String,
+}
+
+pub async fn create_cash_movement(
+    pool: &DbPool,
+    movement: CreateCashMovement,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO cash_movements ( date, type, amount )
+        VALUES ( $1, $2, $3 )
+        RETURNING id as "id!"
+        "#,
+        movement.date,
+        movement.r#type,
+        movement.amount
+    )
+    .fetch_one(pool)
+    .await?
+    .id)
+}
+
+pub struct CashMovement {
+    pub id: i64,
+    pub date: String,
+    pub r#type: String,
+    pub amount: String,
+}
+
+pub async fn list_cash_movements(pool: &DbPool) -> Result<Vec<CashMovement>, sqlx::Error> {
+    sqlx::query_as!(
+        CashMovement,
+        r#"
+        SELECT id as "id!", date, type, amount FROM cash_movements ORDER BY date asc, id asc
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_cash_movement(pool: &DbPool, movement_id: i64) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        DELETE FROM cash_movements WHERE id = $1
+        "#,
+        movement_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+/// Changes of the cash balance sorted by date: deposits, sells and dividends
+/// add cash, withdrawals and buys take it.
+pub fn cash_flows(
+    movements: &[CashMovement],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+) -> Vec<(NaiveDate, BigDecimal)> {
+    let mut flows: Vec<(NaiveDate, BigDecimal)> = movements
+        .iter()
+        .map(|movement| {
+            let amount = BigDecimal::from_str(&movement.amount).unwrap();
+            (
+                NaiveDate::parse_from_str(&movement.date, "%Y-%m-%d").unwrap(),
+                if movement.r#type == "WITHDRAWAL" {
+                    -amount
+                } else {
+                    amount
+                },
+            )
+        })
+        .chain(trades.iter().map(|trade| (trade.date, -trade.cash_flow())))
+        .chain(
+            dividends
+                .iter()
+                .map(|dividend| (dividend.pay_date, dividend.net_amount.clone())),
+        )
+        .collect();
+    flows.sort_by_key(|(date, _)| *date);
+    flows
+}
+
+/// Cash balance after every flow up to `date`.
+pub fn cash_balance(flows: &[(NaiveDate, BigDecimal)], date: NaiveDate) -> BigDecimal {
+    flows
+        .iter()
+        .take_while(|(flow_date, _)| *flow_date <= date)
+        .map(|(_, amount)| amount)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal, trade};
+
+    fn movement(id: i64, date: &str, r#type: &str, amount: &str) -> CashMovement {
+        CashMovement {
+            id,
+            date: date.to_string(),
+            r#type: r#type.to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    #[test]
+    fn cash_flows_add_deposits_sells_and_dividends_and_take_the_rest() {
+        let movements = vec![
+            movement(1, "2024-05-01", "DEPOSIT", "5000"),
+            movement(2, "2024-05-10", "WITHDRAWAL", "1000"),
+        ];
+        let trades = vec![
+            trade(1, "2024-05-02", "IWDA.AMS", "10", "100", "1"),
+            trade(2, "2024-05-05", "IWDA.AMS", "-5", "110", "1"),
+        ];
+        let dividends = vec![DividendForCalculation {
+            ex_date: date("2024-05-06"),
+            pay_date: date("2024-05-08"),
+            net_amount: decimal("30"),
+            currency: None,
+            ticker: "IWDA.AMS".to_string(),
+        }];
+
+        assert_eq!(
+            cash_flows(&movements, &trades, &dividends),
+            vec![
+                (date("2024-05-01"), decimal("5000")),
+                (date("2024-05-02"), decimal("-1001")),
+                (date("2024-05-05"), decimal("549")),
+                (date("2024-05-08"), decimal("30")),
+                (date("2024-05-10"), decimal("-1000")),
+            ]
+        );
+    }
+
+    #[test]
+    fn cash_balance_sums_the_flows_up_to_the_date() {
+        let flows = vec![
+            (date("2024-05-01"), decimal("5000")),
+            (date("2024-05-02"), decimal("-1001")),
+            (date("2024-05-10"), decimal("-1000")),
+        ];
+
+        assert_eq!(cash_balance(&flows, date("2024-04-30")), decimal("0"));
+        assert_eq!(cash_balance(&flows, date("2024-05-02")), decimal("3999"));
+        assert_eq!(cash_balance(&flows, date("2024-06-01")), decimal("2999"));
+    }
+}
@@ -0,0 +1,603 @@
+use crate::db::{self, DbOptions};
+use crate::fx;
+use bigdecimal::{BigDecimal, Signed};
+use chrono::NaiveTime;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// File read at startup unless `CONFIG_PATH` tells another one.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 3000;
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Time of day, in UTC, prices are updated at unless configured: after the
+/// European markets close.
+pub const DEFAULT_PRICE_UPDATE_TIME: &str = "18:30";
+
+pub const DEFAULT_BACKUP_INTERVAL_MINUTES: u64 = 60;
+pub const DEFAULT_OUTLIER_THRESHOLD_PERCENT: u32 = 20;
+
+/// Tickers tracked unless the configuration file lists others, with the
+/// currency they are quoted in and whether they are cryptocurrencies, whose
+/// prices come from CoinGecko, see the `add_crypto_price_sources` migration.
+const DEFAULT_TICKERS: &[(&str, &str, bool)] = &[
+    ("IWDA.AMS", "EUR", false),
+    ("NQSE.DEX", "EUR", false),
+    ("BTC", "EUR", true),
+    ("ETH", "EUR", true),
+];
+
+pub enum ConfigError {
+    Read(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "reading: {}", e),
+            ConfigError::Parse(e) => write!(f, "parsing: {}", e),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// A tracked ticker.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TickerConfig {
+    pub ticker: String,
+    /// Currency the ticker is quoted in, the default quote currency when
+    /// missing.
+    pub currency: Option<String>,
+    /// Cryptocurrencies trade every day, so weekends are not price gaps.
+    #[serde(default)]
+    pub crypto: bool,
+    /// Accumulating funds reinvest their income, so it is in their price and
+    /// not paid out as dividends.
+    #[serde(default)]
+    pub accumulating: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvidersConfig {
+    pub alpha_vantage_api_key: Option<String>,
+    pub alpha_vantage_adjusted: Option<bool>,
+    pub mock: Option<bool>,
+    pub trust_order: Option<Vec<String>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// `HH:MM` in UTC, or `off`.
+    pub price_update_time: Option<String>,
+    pub backup_path: Option<String>,
+    pub backup_interval_minutes: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    pub wal: Option<bool>,
+    pub busy_timeout_ms: Option<u64>,
+    pub max_connections: Option<u32>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub max_body_bytes: Option<usize>,
+    pub redis_url: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TradesConfig {
+    /// `reject`, `warn` or `off`.
+    pub outlier_policy: Option<String>,
+    pub outlier_threshold_percent: Option<String>,
+}
+
+/// Settings of the configuration file. Every setting but the tickers has an
+/// environment variable, which takes precedence over the file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub base_currency: Option<String>,
+    /// Replace the built-in tickers when given.
+    pub tickers: Option<Vec<TickerConfig>>,
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub trades: TradesConfig,
+}
+
+impl ConfigFile {
+    /// Reads the file at `path`. Returns `None` when there is none.
+    pub fn load(path: &Path) -> Result<Option<ConfigFile>, ConfigError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigError::Read(e)),
+        };
+        let file: ConfigFile = toml::from_str(&contents).map_err(ConfigError::Parse)?;
+        if let Some(tickers) = &file.tickers {
+            if tickers.is_empty() {
+                return Err(ConfigError::Invalid("tickers is empty".to_string()));
+            }
+            for (i, ticker) in tickers.iter().enumerate() {
+                if tickers[..i]
+                    .iter()
+                    .any(|other| other.ticker == ticker.ticker)
+                {
+                    return Err(ConfigError::Invalid(format!(
+                        "ticker {} is listed twice",
+                        ticker.ticker
+                    )));
+                }
+            }
+        }
+        Ok(Some(file))
+    }
+
+    /// The settings of the file as the environment variables standing for
+    /// them, with the values written the way the variables are.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        let mut set = |key: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.push((key, value));
+            }
+        };
+        set("BASE_CURRENCY", self.base_currency.clone());
+
+        let providers = &self.providers;
+        set(
+            "ALPHA_VANTAGE_API_KEY",
+            providers.alpha_vantage_api_key.clone(),
+        );
+        set(
+            "ALPHA_VANTAGE_ADJUSTED",
+            providers
+                .alpha_vantage_adjusted
+                .map(|adjusted| adjusted.to_string()),
+        );
+        set(
+            "MOCK_PROVIDERS",
+            providers.mock.map(|mock| mock.to_string()),
+        );
+        set(
+            "PRICE_TRUST_ORDER",
+            providers.trust_order.as_ref().map(|order| order.join(",")),
+        );
+
+        let schedule = &self.schedule;
+        set("PRICE_UPDATE_TIME", schedule.price_update_time.clone());
+        set("BACKUP_PATH", schedule.backup_path.clone());
+        set(
+            "BACKUP_INTERVAL_MINUTES",
+            schedule
+                .backup_interval_minutes
+                .map(|minutes| minutes.to_string()),
+        );
+
+        let database = &self.database;
+        set("DATABASE_URL", database.url.clone());
+        set("SQLITE_WAL", database.wal.map(|wal| wal.to_string()));
+        set(
+            "SQLITE_BUSY_TIMEOUT_MS",
+            database.busy_timeout_ms.map(|millis| millis.to_string()),
+        );
+        set(
+            "SQLITE_MAX_CONNECTIONS",
+            database
+                .max_connections
+                .map(|connections| connections.to_string()),
+        );
+
+        let server = &self.server;
+        set("HOST", server.host.clone());
+        set("PORT", server.port.map(|port| port.to_string()));
+        set(
+            "MAX_BODY_BYTES",
+            server.max_body_bytes.map(|bytes| bytes.to_string()),
+        );
+        set("REDIS_URL", server.redis_url.clone());
+
+        let trades = &self.trades;
+        set("TRADE_OUTLIER_POLICY", trades.outlier_policy.clone());
+        set(
+            "TRADE_OUTLIER_THRESHOLD_PERCENT",
+            trades.outlier_threshold_percent.clone(),
+        );
+        vars
+    }
+}
+
+/// What to do with a trade whose price deviates from the stored close of its
+/// date by more than the outlier threshold.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutlierPolicy {
+    Off,
+    /// Stores the trade and returns a `Warning` header.
+    Warn,
+    /// Rejects the trade with 422 unless it is sent with `force`.
+    Reject,
+}
+
+/// Settings of the server, read once at startup from the environment over the
+/// configuration file. Every value is checked then, so a malformed one stops
+/// the server instead of being ignored.
+pub struct Config {
+    /// Currency every amount is converted to and reported in.
+    pub base_currency: String,
+    pub tickers: Vec<TickerConfig>,
+    pub alpha_vantage_api_key: Option<String>,
+    /// Whether Alpha Vantage prices come with adjusted closes.
+    pub alpha_vantage_adjusted: bool,
+    /// Whether every price and FX rate comes from the mock providers.
+    pub mock_providers: bool,
+    /// Providers whose price is stored when several fetched one for a date,
+    /// most trusted first. Every provider in its default order when `None`.
+    pub price_trust_order: Option<Vec<String>>,
+    /// Time of day, in UTC, of the scheduled price update, `None` when off.
+    pub price_update_time: Option<NaiveTime>,
+    /// Where the database is copied to. Backups are off without it.
+    pub backup_path: Option<PathBuf>,
+    pub backup_interval_minutes: u64,
+    pub database: DbOptions,
+    pub listen_addr: SocketAddr,
+    pub max_body_bytes: usize,
+    /// Responses are cached in memory without it.
+    pub redis_url: Option<String>,
+    pub outlier_policy: OutlierPolicy,
+    pub outlier_threshold_percent: BigDecimal,
+}
+
+fn invalid(key: &str, value: &str, expected: &str) -> ConfigError {
+    ConfigError::Invalid(format!("{} {} is not {}", key, value, expected))
+}
+
+fn parse<T: FromStr>(key: &str, value: &str, expected: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| invalid(key, value, expected))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, ConfigError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(invalid(key, value, "true or false")),
+    }
+}
+
+fn parse_positive<T: FromStr + PartialOrd + Default>(
+    key: &str,
+    value: &str,
+) -> Result<T, ConfigError> {
+    parse(key, value, "a positive number").and_then(|number: T| {
+        if number > T::default() {
+            Ok(number)
+        } else {
+            Err(invalid(key, value, "a positive number"))
+        }
+    })
+}
+
+impl Config {
+    /// Reads the configuration file at `CONFIG_PATH`, `config.toml` by
+    /// default, and takes every setting from its environment variable when
+    /// set, from the file otherwise. A missing file is fine unless
+    /// `CONFIG_PATH` names it.
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match env::var("CONFIG_PATH") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+        let in_file = |e: ConfigError| ConfigError::Invalid(format!("{}: {}", path.display(), e));
+        let file = match ConfigFile::load(&path).map_err(in_file)? {
+            Some(file) => file,
+            None if required => {
+                return Err(ConfigError::Invalid(format!(
+                    "{} does not exist",
+                    path.display()
+                )))
+            }
+            None => ConfigFile::default(),
+        };
+        let file_vars: HashMap<&str, String> = file.env_vars().into_iter().collect();
+        let var = |key: &str| env::var(key).ok().or_else(|| file_vars.get(key).cloned());
+
+        let base_currency =
+            var("BASE_CURRENCY").unwrap_or_else(|| fx::DEFAULT_BASE_CURRENCY.to_string());
+        if base_currency.len() != 3 || !base_currency.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(invalid(
+                "BASE_CURRENCY",
+                &base_currency,
+                "a three letter currency code",
+            ));
+        }
+
+        let price_update_time = match var("PRICE_UPDATE_TIME").as_deref() {
+            Some("off") => None,
+            Some(time) => Some(
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| invalid("PRICE_UPDATE_TIME", time, "HH:MM or off"))?,
+            ),
+            None => NaiveTime::parse_from_str(DEFAULT_PRICE_UPDATE_TIME, "%H:%M").ok(),
+        };
+
+        let defaults = DbOptions::default();
+        let database = DbOptions {
+            url: var("DATABASE_URL").unwrap_or_else(|| db::DEFAULT_DATABASE_URL.to_string()),
+            wal: match var("SQLITE_WAL") {
+                Some(wal) => parse_bool("SQLITE_WAL", &wal)?,
+                None => defaults.wal,
+            },
+            busy_timeout: match var("SQLITE_BUSY_TIMEOUT_MS") {
+                Some(millis) => Duration::from_millis(parse(
+                    "SQLITE_BUSY_TIMEOUT_MS",
+                    &millis,
+                    "a number of milliseconds",
+                )?),
+                None => defaults.busy_timeout,
+            },
+            max_connections: match var("SQLITE_MAX_CONNECTIONS") {
+                Some(connections) => parse_positive("SQLITE_MAX_CONNECTIONS", &connections)?,
+                None => defaults.max_connections,
+            },
+        };
+        let backup_path = var("BACKUP_PATH").map(PathBuf::from);
+        if backup_path.is_some() && db::Backend::from_url(&database.url) == db::Backend::Postgres {
+            return Err(ConfigError::Invalid(
+                "BACKUP_PATH only copies SQLite databases, back up Postgres with pg_dump"
+                    .to_string(),
+            ));
+        }
+
+        let host: IpAddr = match var("HOST") {
+            Some(host) => parse("HOST", &host, "an IP address")?,
+            None => DEFAULT_HOST.parse().unwrap(),
+        };
+        let port = match var("PORT") {
+            Some(port) => parse("PORT", &port, "a port")?,
+            None => DEFAULT_PORT,
+        };
+
+        let outlier_threshold_percent = match var("TRADE_OUTLIER_THRESHOLD_PERCENT") {
+            Some(threshold) => {
+                let percent: BigDecimal = parse(
+                    "TRADE_OUTLIER_THRESHOLD_PERCENT",
+                    &threshold,
+                    "a percentage",
+                )?;
+                if percent.is_negative() {
+                    return Err(invalid(
+                        "TRADE_OUTLIER_THRESHOLD_PERCENT",
+                        &threshold,
+                        "a percentage",
+                    ));
+                }
+                percent
+            }
+            None => BigDecimal::from(DEFAULT_OUTLIER_THRESHOLD_PERCENT),
+        };
+
+        Ok(Config {
+            base_currency,
+            tickers: file.tickers.unwrap_or_else(|| {
+                DEFAULT_TICKERS
+                    .iter()
+                    .map(|(ticker, currency, crypto)| TickerConfig {
+                        ticker: ticker.to_string(),
+                        currency: Some(currency.to_string()),
+                        crypto: *crypto,
+                        accumulating: false,
+                    })
+                    .collect()
+            }),
+            alpha_vantage_api_key: var("ALPHA_VANTAGE_API_KEY").filter(|key| !key.is_empty()),
+            alpha_vantage_adjusted: match var("ALPHA_VANTAGE_ADJUSTED") {
+                Some(adjusted) => parse_bool("ALPHA_VANTAGE_ADJUSTED", &adjusted)?,
+                None => true,
+            },
+            mock_providers: match var("MOCK_PROVIDERS") {
+                Some(mock) => parse_bool("MOCK_PROVIDERS", &mock)?,
+                None => false,
+            },
+            price_trust_order: var("PRICE_TRUST_ORDER").map(|order| {
+                order
+                    .split(',')
+                    .map(|provider| provider.trim().to_string())
+                    .filter(|provider| !provider.is_empty())
+                    .collect()
+            }),
+            price_update_time,
+            backup_path,
+            backup_interval_minutes: match var("BACKUP_INTERVAL_MINUTES") {
+                Some(minutes) => parse_positive("BACKUP_INTERVAL_MINUTES", &minutes)?,
+                None => DEFAULT_BACKUP_INTERVAL_MINUTES,
+            },
+            database,
+            listen_addr: SocketAddr::new(host, port),
+            max_body_bytes: match var("MAX_BODY_BYTES") {
+                Some(bytes) => parse_positive("MAX_BODY_BYTES", &bytes)?,
+                None => DEFAULT_MAX_BODY_BYTES,
+            },
+            redis_url: var("REDIS_URL"),
+            outlier_policy: match var("TRADE_OUTLIER_POLICY").as_deref() {
+                Some("off") => OutlierPolicy::Off,
+                Some("warn") => OutlierPolicy::Warn,
+                Some("reject") | None => OutlierPolicy::Reject,
+                Some(policy) => {
+                    return Err(invalid(
+                        "TRADE_OUTLIER_POLICY",
+                        policy,
+                        "reject, warn or off",
+                    ))
+                }
+            },
+            outlier_threshold_percent,
+        })
+    }
+
+    /// File of the database, its URL without the scheme and parameters.
+    pub fn database_path(&self) -> &str {
+        let url = &self.database.url;
+        let path = url
+            .strip_prefix("sqlite://")
+            .or_else(|| url.strip_prefix("sqlite:"))
+            .unwrap_or(url);
+        path.split('?').next().unwrap_or(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `contents` as the configuration file `name`.
+    fn load(name: &str, contents: &str) -> Result<Option<ConfigFile>, ConfigError> {
+        let path = env::temp_dir().join(format!("portfolio-tracker-{}.toml", name));
+        std::fs::write(&path, contents).unwrap();
+        let file = ConfigFile::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn example_file_parses() {
+        let file: ConfigFile = toml::from_str(include_str!("../config.example.toml")).unwrap();
+
+        let tickers = file.tickers.unwrap();
+        assert_eq!(
+            tickers
+                .iter()
+                .map(|ticker| (ticker.ticker.as_str(), ticker.crypto))
+                .collect::<Vec<_>>(),
+            vec![
+                ("IWDA.AMS", false),
+                ("NQSE.DEX", false),
+                ("BTC", true),
+                ("ETH", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn file_settings_stand_for_their_environment_variables() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            base_currency = "USD"
+
+            [providers]
+            trust_order = ["stooq", "alpha_vantage"]
+
+            [database]
+            wal = false
+
+            [server]
+            port = 8080
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.env_vars(),
+            vec![
+                ("BASE_CURRENCY", "USD".to_string()),
+                ("PRICE_TRUST_ORDER", "stooq,alpha_vantage".to_string()),
+                ("SQLITE_WAL", "false".to_string()),
+                ("PORT", "8080".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tickers_are_accumulating_only_when_flagged() {
+        let file: ConfigFile = toml::from_str(
+            "[[tickers]]\nticker = \"IWDA.AMS\"\naccumulating = true\n\n[[tickers]]\nticker = \"NQSE.DEX\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file.tickers
+                .unwrap()
+                .iter()
+                .map(|ticker| ticker.accumulating)
+                .collect::<Vec<_>>(),
+            vec![true, false]
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<ConfigFile>("[server]\nportt = 8080").is_err());
+    }
+
+    #[test]
+    fn missing_files_load_as_none() {
+        let path = env::temp_dir().join("portfolio-tracker-missing.toml");
+
+        assert!(matches!(ConfigFile::load(&path), Ok(None)));
+    }
+
+    #[test]
+    fn tickers_must_be_listed_once() {
+        let duplicated = "[[tickers]]\nticker = \"BTC\"\n\n[[tickers]]\nticker = \"BTC\"\n";
+
+        assert!(matches!(
+            load("duplicated", duplicated),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            load("empty", "tickers = []"),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            load("malformed", "tickers = ["),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn booleans_are_true_or_false() {
+        assert!(matches!(parse_bool("SQLITE_WAL", "true"), Ok(true)));
+        assert!(matches!(parse_bool("SQLITE_WAL", "false"), Ok(false)));
+        assert!(matches!(
+            parse_bool("SQLITE_WAL", "yes"),
+            Err(ConfigError::Invalid(message)) if message == "SQLITE_WAL yes is not true or false"
+        ));
+    }
+
+    #[test]
+    fn positive_numbers_exclude_zero() {
+        assert!(matches!(
+            parse_positive::<u64>("BACKUP_INTERVAL_MINUTES", "15"),
+            Ok(15)
+        ));
+        assert!(parse_positive::<u64>("BACKUP_INTERVAL_MINUTES", "0").is_err());
+        assert!(parse_positive::<u64>("BACKUP_INTERVAL_MINUTES", "-5").is_err());
+        assert!(parse_positive::<u64>("BACKUP_INTERVAL_MINUTES", "soon").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::db::{Db, DbPool};
+use crate::decimal::round_half_up;
+use bigdecimal::{BigDecimal, One};
+use chrono::NaiveDate;
+use sqlx::Executor;
+use std::str::FromStr;
+
+const ADJUSTED_PRICE_SCALE: i64 = 6;
+
+pub struct CreateSplit {
+    pub ticker: String,
+    pub date: String,
+    /// Units held after the split for every unit held before it.
+    pub ratio: String,
+}
+
+/// Runs on a pool or within a transaction.
+pub async fn create_split(
+    executor: impl Executor<'_, Database = Db>,
+    split: CreateSplit,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO corporate_actions ( ticker, date, type, ratio )
+        VALUES ( $1, $2, 'SPLIT', $3 )
+        RETURNING id as "id!"
+        "#,
+        split.ticker,
+        split.date,
+        split.ratio
+    )
+    .fetch_one(executor)
+    .await?
+    .id)
+}
+
+pub struct CorporateAction {
+    pub id: i64,
+    pub ticker: String,
+    pub date: String,
+    pub r#type: String,
+    pub ratio: String,
+}
+
+pub async fn list_corporate_actions(pool: &DbPool) -> Result<Vec<CorporateAction>, sqlx::Error> {
+    sqlx::query_as!(
+        CorporateAction,
+        r#"
+        SELECT id as "id!", ticker, date, type, ratio FROM corporate_actions
+        ORDER BY date asc, id asc
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub struct Split {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub ratio: BigDecimal,
+}
+
+/// Splits sorted by date, optionally only those of `ticker`.
+pub async fn list_splits(
+    executor: impl Executor<'_, Database = Db>,
+    ticker: Option<&str>,
+) -> Result<Vec<Split>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT ticker, date, ratio FROM corporate_actions
+        WHERE type = 'SPLIT' AND (CAST($1 AS TEXT) IS NULL OR ticker = $1)
+        ORDER BY date asc, id asc
+        "#,
+        ticker
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|row| Split {
+        ticker: row.ticker,
+        date: NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap(),
+        ratio: BigDecimal::from_str(&row.ratio).unwrap(),
+    })
+    .collect())
+}
+
+/// Units a unit of `ticker` held on `date` became through the splits after
+/// it. A split takes effect on its date, so trades and prices of that date
+/// are already split.
+pub fn split_factor(splits: &[Split], ticker: &str, date: NaiveDate) -> BigDecimal {
+    splits
+        .iter()
+        .filter(|split| split.ticker == ticker && split.date > date)
+        .fold(BigDecimal::one(), |factor, split| factor * &split.ratio)
+}
+
+/// Expresses `amount` units bought or sold before splits with `factor` in
+/// units of today.
+pub fn adjust_units(amount: BigDecimal, factor: &BigDecimal) -> BigDecimal {
+    if factor.is_one() {
+        return amount;
+    }
+    (amount * factor).normalized()
+}
+
+/// Expresses a price quoted before splits with `factor` in units of today.
+pub fn adjust_price(price: BigDecimal, factor: &BigDecimal) -> BigDecimal {
+    if factor.is_one() {
+        return price;
+    }
+    round_half_up(price / factor, ADJUSTED_PRICE_SCALE).normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal};
+
+    fn split(ticker: &str, day: &str, ratio: &str) -> Split {
+        Split {
+            ticker: ticker.to_string(),
+            date: date(day),
+            ratio: decimal(ratio),
+        }
+    }
+
+    #[test]
+    fn split_factor_multiplies_the_later_splits_of_the_ticker() {
+        let splits = vec![
+            split("AAPL", "2014-06-09", "7"),
+            split("TSLA", "2020-08-31", "5"),
+            split("AAPL", "2020-08-31", "4"),
+        ];
+
+        assert_eq!(
+            split_factor(&splits, "AAPL", date("2014-01-02")),
+            decimal("28")
+        );
+        assert_eq!(
+            split_factor(&splits, "AAPL", date("2018-01-02")),
+            decimal("4")
+        );
+        assert_eq!(
+            split_factor(&splits, "AAPL", date("2021-01-04")),
+            decimal("1")
+        );
+    }
+
+    #[test]
+    fn split_factor_treats_the_split_date_as_already_split() {
+        let splits = vec![split("AAPL", "2020-08-31", "4")];
+
+        assert_eq!(
+            split_factor(&splits, "AAPL", date("2020-08-28")),
+            decimal("4")
+        );
+        assert_eq!(
+            split_factor(&splits, "AAPL", date("2020-08-31")),
+            decimal("1")
+        );
+    }
+
+    #[test]
+    fn split_adjustment_keeps_the_value_of_a_holding() {
+        let factor = decimal("4");
+        let units = adjust_units(decimal("10"), &factor);
+        let price = adjust_price(decimal("499.23"), &factor);
+
+        assert_eq!(units, decimal("40"));
+        assert_eq!(price, decimal("124.8075"));
+        assert_eq!(units * price, decimal("4992.3"));
+    }
+
+    #[test]
+    fn adjust_price_rounds_to_the_adjusted_price_scale() {
+        assert_eq!(
+            adjust_price(decimal("100"), &decimal("3")),
+            decimal("33.333333")
+        );
+    }
+
+    #[test]
+    fn reverse_split_adjusts_units_down() {
+        let factor = decimal("0.1");
+
+        assert_eq!(adjust_units(decimal("25"), &factor), decimal("2.5"));
+        assert_eq!(adjust_price(decimal("2"), &factor), decimal("20"));
+    }
+}
@@ -1,8 +1,136 @@
-use anyhow::Result;
-use sqlx::SqlitePool;
+use anyhow::{bail, Result};
+use sqlx::migrate::Migrator;
 use std::sync::Arc;
+use std::time::Duration;
 
-pub async fn prepare_db_and_get_connection() -> Result<Arc<SqlitePool>> {
-    let pool = SqlitePool::connect("porfolio-tracker.db").await?;
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("the sqlite and postgres features can't be enabled together");
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("enable either the sqlite or the postgres feature");
+
+/// The database the binary was built for, with the `sqlite` or `postgres`
+/// feature. Queries are checked against it when building, so it can't change
+/// at runtime.
+#[cfg(feature = "sqlite")]
+pub type Db = sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+pub type Db = sqlx::Postgres;
+
+pub type DbPool = sqlx::Pool<Db>;
+
+/// Migrations of the database the binary was built for, embedded from
+/// `migrations/sqlite` or `migrations/postgres`.
+#[cfg(feature = "sqlite")]
+pub static MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+#[cfg(feature = "postgres")]
+pub static MIGRATOR: Migrator = sqlx::migrate!("migrations/postgres");
+
+/// Database opened unless `DATABASE_URL` tells another one, either a path or
+/// a `sqlite:` URL, or a `postgres:` URL.
+pub const DEFAULT_DATABASE_URL: &str = "sqlite:porfolio-tracker.db";
+
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    /// The backend the binary was built for.
+    #[cfg(feature = "sqlite")]
+    pub const BUILT: Backend = Backend::Sqlite;
+    #[cfg(feature = "postgres")]
+    pub const BUILT: Backend = Backend::Postgres;
+
+    /// Tells the backend from the scheme of `url`. A URL without one is the
+    /// path of an SQLite file.
+    pub fn from_url(url: &str) -> Backend {
+        if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Backend::Postgres
+        } else {
+            Backend::Sqlite
+        }
+    }
+
+    fn feature(&self) -> &'static str {
+        match self {
+            Backend::Sqlite => "sqlite",
+            Backend::Postgres => "postgres",
+        }
+    }
+}
+
+/// How the connections to the database are opened.
+pub struct DbOptions {
+    pub url: String,
+    /// Journals writes ahead of the database file, so reads don't wait for a
+    /// write to finish and a write doesn't wait for the reads. SQLite only.
+    pub wal: bool,
+    /// How long a statement waits for another connection's write lock before
+    /// failing with "database is locked". SQLite only.
+    pub busy_timeout: Duration,
+    pub max_connections: u32,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_DATABASE_URL.to_string(),
+            wal: true,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+/// Opens the database, creating the SQLite file when missing, and applies the
+/// migrations it hasn't run yet, so a fresh checkout starts with the whole
+/// schema. Fails when the scheme of the URL is not of the backend the binary
+/// was built for.
+pub async fn prepare_db_and_get_connection(options: &DbOptions) -> Result<Arc<DbPool>> {
+    let backend = Backend::from_url(&options.url);
+    if backend != Backend::BUILT {
+        bail!(
+            "DATABASE_URL is a {} database but this binary was built for {}, build it with `--no-default-features --features {}`",
+            backend.feature(),
+            Backend::BUILT.feature(),
+            backend.feature()
+        );
+    }
+    let pool = connect(options).await?;
+    MIGRATOR.run(&pool).await?;
     Ok(Arc::new(pool))
 }
+
+#[cfg(feature = "sqlite")]
+async fn connect(options: &DbOptions) -> Result<DbPool> {
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+    use std::str::FromStr;
+
+    let journal_mode = if options.wal {
+        SqliteJournalMode::Wal
+    } else {
+        SqliteJournalMode::Delete
+    };
+    let connect_options = SqliteConnectOptions::from_str(&options.url)?
+        .journal_mode(journal_mode)
+        .busy_timeout(options.busy_timeout)
+        .create_if_missing(true);
+    Ok(SqlitePoolOptions::new()
+        .max_connections(options.max_connections)
+        .connect_with(connect_options)
+        .await?)
+}
+
+#[cfg(feature = "postgres")]
+async fn connect(options: &DbOptions) -> Result<DbPool> {
+    use sqlx::postgres::PgPoolOptions;
+
+    Ok(PgPoolOptions::new()
+        .max_connections(options.max_connections)
+        .connect(&options.url)
+        .await?)
+}
@@ -0,0 +1,20 @@
+use bigdecimal::{BigDecimal, Signed};
+
+/// Number of decimal places money amounts are reported with.
+pub const AMOUNT_SCALE: i64 = 2;
+
+/// Number of decimal places units bought for an amount of cash are kept
+/// with, truncated so that they never cost more than the cash.
+pub const UNITS_SCALE: i64 = 8;
+
+/// Rounds half away from zero to `scale` decimal places. `BigDecimal::round`
+/// panics on values with long expansions (e.g. results of `inverse`), so the
+/// rounding is done by hand on top of `with_scale`, which truncates.
+pub fn round_half_up(value: BigDecimal, scale: i64) -> BigDecimal {
+    let half_unit = BigDecimal::new(5.into(), scale + 1);
+    if value.is_negative() {
+        -(-value + half_unit).with_scale(scale)
+    } else {
+        (value + half_unit).with_scale(scale)
+    }
+}
@@ -0,0 +1,555 @@
+use crate::corporate_action::{self, adjust_price, split_factor};
+use crate::db::{Db, DbPool};
+use crate::decimal::UNITS_SCALE;
+use crate::fx;
+use crate::trade::MissingFxRate;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use sqlx::Transaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub struct SaveDividend {
+    pub ticker: String,
+    pub ex_date: String,
+    pub pay_date: String,
+    pub gross_amount: String,
+    pub tax: String,
+    /// Currency the amounts are paid in, `None` for the base currency.
+    pub currency: Option<String>,
+    /// Buy as many units as the net amount pays for on the pay date.
+    pub reinvested: bool,
+}
+
+pub enum SaveDividendError {
+    /// A reinvested dividend needs a price on or before its pay date.
+    NoPriceToReinvest,
+    /// A field that should hold a decimal or a date doesn't.
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    /// The price a dividend would be reinvested at is not positive.
+    InvalidPrice {
+        date: String,
+        price: String,
+    },
+    Database(sqlx::Error),
+}
+
+fn parse_decimal(field: &'static str, value: &str) -> Result<BigDecimal, SaveDividendError> {
+    BigDecimal::from_str(value).map_err(|_| SaveDividendError::InvalidField {
+        field,
+        value: value.to_string(),
+    })
+}
+
+fn parse_date(field: &'static str, value: &str) -> Result<NaiveDate, SaveDividendError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| SaveDividendError::InvalidField {
+        field,
+        value: value.to_string(),
+    })
+}
+
+impl From<sqlx::Error> for SaveDividendError {
+    fn from(e: sqlx::Error) -> Self {
+        SaveDividendError::Database(e)
+    }
+}
+
+/// Creates the buy trade of a reinvested dividend and returns its id. The
+/// whole net amount is reinvested in fractional units, at the last close on or
+/// before the pay date, expressed in units of the pay date when a split took
+/// effect in between. There is no trade when the net amount is not positive.
+async fn create_reinvestment_trade(
+    tx: &mut Transaction<'_, Db>,
+    dividend: &SaveDividend,
+) -> Result<Option<i64>, SaveDividendError> {
+    if !dividend.reinvested {
+        return Ok(None);
+    }
+
+    let row = sqlx::query!(
+        r#"
+        SELECT date, price FROM prices WHERE ticker = $1 AND date <= $2 ORDER BY date desc LIMIT 1
+        "#,
+        dividend.ticker,
+        dividend.pay_date
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(SaveDividendError::NoPriceToReinvest)?;
+
+    let invalid_price = || SaveDividendError::InvalidPrice {
+        date: row.date.clone(),
+        price: row.price.clone(),
+    };
+    let price = BigDecimal::from_str(&row.price).map_err(|_| invalid_price())?;
+    if price <= BigDecimal::from(0) {
+        return Err(invalid_price());
+    }
+    let price_date = parse_date("date", &row.date)?;
+    let pay_date = parse_date("pay_date", &dividend.pay_date)?;
+    let splits = corporate_action::list_splits(&mut *tx, Some(&dividend.ticker)).await?;
+    let factor = split_factor(&splits, &dividend.ticker, price_date)
+        / split_factor(&splits, &dividend.ticker, pay_date);
+    let price = adjust_price(price, &factor);
+
+    let net_amount = parse_decimal("gross_amount", &dividend.gross_amount)?
+        - parse_decimal("tax", &dividend.tax)?;
+    let units = (net_amount / &price).with_scale(UNITS_SCALE).normalized();
+    if units <= BigDecimal::from(0) {
+        return Ok(None);
+    }
+    let units = units.to_string();
+    let price = price.to_string();
+
+    Ok(Some(
+        sqlx::query!(
+            r#"
+            INSERT INTO trades ( ticker, date, type, amount, price )
+            VALUES ( $1, $2, 'BUY', $3, $4 )
+            RETURNING id as "id!"
+            "#,
+            dividend.ticker,
+            dividend.pay_date,
+            units,
+            price
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .id,
+    ))
+}
+
+/// Unlinks the reinvestment trade of the dividend and deletes it with its tags.
+async fn delete_reinvestment_trade(
+    tx: &mut Transaction<'_, Db>,
+    dividend_id: i64,
+) -> Result<(), sqlx::Error> {
+    let trade_id = match sqlx::query!(
+        r#"
+        SELECT reinvestment_trade_id FROM dividends WHERE id = $1
+        "#,
+        dividend_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .and_then(|row| row.reinvestment_trade_id)
+    {
+        Some(trade_id) => trade_id,
+        None => return Ok(()),
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE dividends SET reinvestment_trade_id = NULL WHERE id = $1
+        "#,
+        dividend_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM trade_tags WHERE trade_id = $1
+        "#,
+        trade_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM trades WHERE id = $1
+        "#,
+        trade_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn create_dividend(
+    pool: &DbPool,
+    dividend: SaveDividend,
+) -> Result<i64, SaveDividendError> {
+    let mut tx = pool.begin().await?;
+    let reinvestment_trade_id = create_reinvestment_trade(&mut tx, &dividend).await?;
+    let id = sqlx::query!(
+        r#"
+        INSERT INTO dividends ( ticker, ex_date, pay_date, gross_amount, tax, currency, reinvested, reinvestment_trade_id )
+        VALUES ( $1, $2, $3, $4, $5, $6, $7, $8 )
+        RETURNING id as "id!"
+        "#,
+        dividend.ticker,
+        dividend.ex_date,
+        dividend.pay_date,
+        dividend.gross_amount,
+        dividend.tax,
+        dividend.currency,
+        dividend.reinvested,
+        reinvestment_trade_id
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .id;
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub struct Dividend {
+    pub id: i64,
+    pub ticker: String,
+    pub ex_date: String,
+    pub pay_date: String,
+    pub gross_amount: String,
+    pub tax: String,
+    pub currency: Option<String>,
+    pub reinvested: bool,
+    pub reinvestment_trade_id: Option<i64>,
+}
+
+pub async fn list_dividends(
+    pool: &DbPool,
+    ticker: Option<String>,
+) -> Result<Vec<Dividend>, sqlx::Error> {
+    sqlx::query_as!(
+        Dividend,
+        r#"
+        SELECT id as "id!", ticker, ex_date, pay_date, gross_amount, tax, currency,
+               reinvested as "reinvested: bool", reinvestment_trade_id
+        FROM dividends
+        WHERE CAST($1 AS TEXT) IS NULL OR ticker = $1
+        ORDER BY pay_date asc, id asc
+        "#,
+        ticker
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_dividend(
+    pool: &DbPool,
+    dividend_id: i64,
+) -> Result<Option<Dividend>, sqlx::Error> {
+    sqlx::query_as!(
+        Dividend,
+        r#"
+        SELECT id, ticker, ex_date, pay_date, gross_amount, tax, currency,
+               reinvested as "reinvested: bool", reinvestment_trade_id
+        FROM dividends WHERE id = $1
+        "#,
+        dividend_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Replaces the dividend and regenerates its reinvestment trade.
+pub async fn update_dividend(
+    pool: &DbPool,
+    dividend_id: i64,
+    dividend: SaveDividend,
+) -> Result<u64, SaveDividendError> {
+    let mut tx = pool.begin().await?;
+    delete_reinvestment_trade(&mut tx, dividend_id).await?;
+    let reinvestment_trade_id = create_reinvestment_trade(&mut tx, &dividend).await?;
+    let updated_count = sqlx::query!(
+        r#"
+        UPDATE dividends
+        SET ticker = $1, ex_date = $2, pay_date = $3, gross_amount = $4, tax = $5,
+            currency = $6, reinvested = $7, reinvestment_trade_id = $8
+        WHERE id = $9
+        "#,
+        dividend.ticker,
+        dividend.ex_date,
+        dividend.pay_date,
+        dividend.gross_amount,
+        dividend.tax,
+        dividend.currency,
+        dividend.reinvested,
+        reinvestment_trade_id,
+        dividend_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+    if updated_count == 1 {
+        tx.commit().await?;
+    }
+    Ok(updated_count)
+}
+
+/// Deletes the dividend together with its reinvestment trade.
+pub async fn delete_dividend(pool: &DbPool, dividend_id: i64) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    delete_reinvestment_trade(&mut tx, dividend_id).await?;
+    let deleted_count = sqlx::query!(
+        r#"
+        DELETE FROM dividends WHERE id = $1
+        "#,
+        dividend_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+    Ok(deleted_count)
+}
+
+/// Currencies dividends are paid in, other than the base currency.
+pub async fn list_currencies(pool: &DbPool) -> Result<Vec<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT DISTINCT currency as "currency!" FROM dividends
+        WHERE currency IS NOT NULL
+        ORDER BY currency asc
+        "#
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.currency)
+    .collect())
+}
+
+#[derive(Clone)]
+pub struct DividendForCalculation {
+    /// Units held before this date are paid the dividend.
+    pub ex_date: NaiveDate,
+    pub pay_date: NaiveDate,
+    /// Gross amount minus the tax withheld.
+    pub net_amount: BigDecimal,
+    /// Currency of the net amount, `None` for the base currency.
+    pub currency: Option<String>,
+    pub ticker: String,
+}
+
+/// Dividends paid up to `until`, sorted by pay date.
+pub async fn list_dividends_for_calculation(
+    pool: &DbPool,
+    until: NaiveDate,
+) -> Result<Vec<DividendForCalculation>, sqlx::Error> {
+    let until = until.to_string();
+    Ok(sqlx::query!(
+        r#"
+        SELECT ex_date, pay_date, gross_amount, tax, currency, ticker FROM dividends
+        WHERE pay_date <= $1
+        ORDER BY pay_date asc, id asc
+        "#,
+        until
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| DividendForCalculation {
+        ex_date: NaiveDate::parse_from_str(&row.ex_date, "%Y-%m-%d").unwrap(),
+        pay_date: NaiveDate::parse_from_str(&row.pay_date, "%Y-%m-%d").unwrap(),
+        net_amount: BigDecimal::from_str(&row.gross_amount).unwrap()
+            - BigDecimal::from_str(&row.tax).unwrap(),
+        currency: row.currency.clone(),
+        ticker: row.ticker.clone(),
+    })
+    .collect())
+}
+
+/// Expresses the net amounts of `dividends` in `base_currency` at the rate of
+/// their pay date. `fx_rates` holds the rates, sorted by date, of the other
+/// currencies they are paid in.
+pub fn in_base_currency(
+    dividends: Vec<DividendForCalculation>,
+    base_currency: &str,
+    fx_rates: &HashMap<String, Vec<(NaiveDate, BigDecimal)>>,
+) -> Result<Vec<DividendForCalculation>, MissingFxRate> {
+    dividends
+        .into_iter()
+        .map(|dividend| {
+            let currency = match dividend.currency.as_deref() {
+                Some(currency) if currency != base_currency => currency,
+                _ => {
+                    return Ok(DividendForCalculation {
+                        currency: None,
+                        ..dividend
+                    })
+                }
+            };
+            let rate = fx_rates
+                .get(currency)
+                .and_then(|fx_rates| fx::rate_on(fx_rates, dividend.pay_date))
+                .ok_or_else(|| MissingFxRate {
+                    ticker: dividend.ticker.clone(),
+                    currency: currency.to_string(),
+                    date: dividend.pay_date,
+                })?;
+            Ok(DividendForCalculation {
+                net_amount: &dividend.net_amount * rate,
+                currency: None,
+                ..dividend
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal};
+
+    fn dividend(
+        pay_date: &str,
+        net_amount: &str,
+        currency: Option<&str>,
+    ) -> DividendForCalculation {
+        DividendForCalculation {
+            ex_date: date(pay_date),
+            pay_date: date(pay_date),
+            net_amount: decimal(net_amount),
+            currency: currency.map(str::to_string),
+            ticker: "VOO".to_string(),
+        }
+    }
+
+    fn usd_rates() -> HashMap<String, Vec<(NaiveDate, BigDecimal)>> {
+        HashMap::from([(
+            "USD".to_string(),
+            vec![
+                (date("2024-05-01"), decimal("0.9")),
+                (date("2024-05-03"), decimal("0.8")),
+            ],
+        )])
+    }
+
+    #[test]
+    fn in_base_currency_converts_at_the_rate_of_the_pay_date() {
+        let dividends = vec![
+            dividend("2024-05-02", "10", Some("USD")),
+            dividend("2024-05-03", "10", Some("USD")),
+            dividend("2024-05-03", "10", Some("EUR")),
+            dividend("2024-05-03", "10", None),
+        ];
+
+        let converted: Vec<BigDecimal> = in_base_currency(dividends, "EUR", &usd_rates())
+            .unwrap()
+            .into_iter()
+            .map(|dividend| dividend.net_amount)
+            .collect();
+
+        assert_eq!(
+            converted,
+            vec![decimal("9"), decimal("8"), decimal("10"), decimal("10")]
+        );
+    }
+
+    #[test]
+    fn in_base_currency_needs_a_rate_on_or_before_the_pay_date() {
+        let dividends = vec![dividend("2024-04-30", "10", Some("USD"))];
+
+        let missing = match in_base_currency(dividends, "EUR", &usd_rates()) {
+            Ok(_) => panic!("converted without a rate"),
+            Err(missing) => missing,
+        };
+
+        assert_eq!(missing.currency, "USD");
+        assert_eq!(missing.date, date("2024-04-30"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn reinvested(pay_date: &str, gross_amount: &str, tax: &str) -> SaveDividend {
+        SaveDividend {
+            ticker: "IWDA.AMS".to_string(),
+            ex_date: "2024-04-25".to_string(),
+            pay_date: pay_date.to_string(),
+            gross_amount: gross_amount.to_string(),
+            tax: tax.to_string(),
+            currency: None,
+            reinvested: true,
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn insert_price(pool: &DbPool, date: &str, price: &str) {
+        sqlx::query!(
+            r#"
+            INSERT INTO prices ( ticker, date, price ) VALUES ( 'IWDA.AMS', $1, $2 )
+            "#,
+            date,
+            price
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn reinvested_dividends_buy_their_net_amount_at_the_last_close() {
+        let pool = crate::test_util::pool().await;
+        insert_price(&pool, "2024-05-01", "80").await;
+        insert_price(&pool, "2024-05-06", "90").await;
+
+        let id = create_dividend(&pool, reinvested("2024-05-03", "50", "10"))
+            .await
+            .ok()
+            .unwrap();
+
+        let dividend = get_dividend(&pool, id).await.unwrap().unwrap();
+        let trades = crate::trade::list_trades(&pool).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(dividend.reinvestment_trade_id, Some(trades[0].id));
+        assert_eq!(trades[0].date, "2024-05-03");
+        assert_eq!(decimal(&trades[0].amount), decimal("0.5"));
+        assert_eq!(decimal(&trades[0].price), decimal("80"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn reinvested_dividends_buy_at_the_close_adjusted_for_a_split_since() {
+        let pool = crate::test_util::pool().await;
+        insert_price(&pool, "2024-05-01", "80").await;
+        corporate_action::create_split(
+            &pool,
+            corporate_action::CreateSplit {
+                ticker: "IWDA.AMS".to_string(),
+                date: "2024-05-02".to_string(),
+                ratio: "2".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        create_dividend(&pool, reinvested("2024-05-03", "50", "10"))
+            .await
+            .ok()
+            .unwrap();
+
+        let trades = crate::trade::list_trades(&pool).await.unwrap();
+        assert_eq!(decimal(&trades[0].amount), decimal("1"));
+        assert_eq!(decimal(&trades[0].price), decimal("40"));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn reinvested_dividends_need_a_price() {
+        let pool = crate::test_util::pool().await;
+        insert_price(&pool, "2024-05-06", "90").await;
+
+        let created = create_dividend(&pool, reinvested("2024-05-03", "50", "10")).await;
+
+        assert!(matches!(created, Err(SaveDividendError::NoPriceToReinvest)));
+        assert!(list_dividends(&pool, None).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn deleted_dividends_take_their_reinvestment_trade_along() {
+        let pool = crate::test_util::pool().await;
+        insert_price(&pool, "2024-05-01", "80").await;
+        let id = create_dividend(&pool, reinvested("2024-05-03", "50", "10"))
+            .await
+            .ok()
+            .unwrap();
+
+        assert_eq!(delete_dividend(&pool, id).await.unwrap(), 1);
+
+        assert!(crate::trade::list_trades(&pool).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,369 @@
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Excel rejects worksheet names longer than this.
+const MAX_SHEET_NAME_LENGTH: usize = 31;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn exporter(self) -> Box<dyn Exporter> {
+        match self {
+            ExportFormat::Json => Box::new(JsonExporter),
+            ExportFormat::Csv => Box::new(CsvExporter),
+            ExportFormat::Xlsx => Box::new(XlsxExporter),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Json(serde_json::Error),
+    Xlsx(XlsxError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Json(e) => write!(f, "json: {}", e),
+            ExportError::Xlsx(e) => write!(f, "xlsx: {}", e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        ExportError::Json(e)
+    }
+}
+
+impl From<XlsxError> for ExportError {
+    fn from(e: XlsxError) -> Self {
+        ExportError::Xlsx(e)
+    }
+}
+
+/// Renders a report, given as the JSON it is served as, into a file format.
+pub trait Exporter: Send + Sync {
+    fn content_type(&self) -> &'static str;
+
+    fn file_extension(&self) -> &'static str;
+
+    fn export(&self, report: &str, value: &Value) -> Result<Vec<u8>, ExportError>;
+}
+
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "json"
+    }
+
+    fn export(&self, _report: &str, value: &Value) -> Result<Vec<u8>, ExportError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+}
+
+/// Writes every table one after the other, each under a line with its name
+/// when there is more than one.
+pub struct CsvExporter;
+
+impl Exporter for CsvExporter {
+    fn content_type(&self) -> &'static str {
+        "text/csv"
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "csv"
+    }
+
+    fn export(&self, report: &str, value: &Value) -> Result<Vec<u8>, ExportError> {
+        let tables = tables(report, value);
+        let mut output = String::new();
+        for (index, table) in tables.iter().enumerate() {
+            if index > 0 {
+                output.push('\n');
+            }
+            if tables.len() > 1 {
+                output.push_str(&csv_field(&table.name));
+                output.push('\n');
+            }
+            csv_line(
+                &mut output,
+                table.columns.iter().map(|column| csv_field(column)),
+            );
+            for row in &table.rows {
+                csv_line(
+                    &mut output,
+                    row.iter().map(|cell| csv_field(&cell_text(cell))),
+                );
+            }
+        }
+        Ok(output.into_bytes())
+    }
+}
+
+fn csv_line(output: &mut String, fields: impl Iterator<Item = String>) {
+    output.push_str(&fields.collect::<Vec<_>>().join(","));
+    output.push('\n');
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Writes every table to a worksheet of its own. Decimals, which reports
+/// serialize as strings, are written as numbers so they can be summed.
+pub struct XlsxExporter;
+
+impl Exporter for XlsxExporter {
+    fn content_type(&self) -> &'static str {
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "xlsx"
+    }
+
+    fn export(&self, report: &str, value: &Value) -> Result<Vec<u8>, ExportError> {
+        let mut workbook = Workbook::new();
+        let header = Format::new().set_bold();
+        for table in tables(report, value) {
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(
+                table
+                    .name
+                    .chars()
+                    .take(MAX_SHEET_NAME_LENGTH)
+                    .collect::<String>(),
+            )?;
+            for (col, column) in table.columns.iter().enumerate() {
+                worksheet.write_string_with_format(0, col as u16, column, &header)?;
+            }
+            for (row, cells) in table.rows.iter().enumerate() {
+                let row = row as u32 + 1;
+                for (col, cell) in cells.iter().enumerate() {
+                    let col = col as u16;
+                    match cell {
+                        Value::Null => (),
+                        Value::Bool(value) => {
+                            worksheet.write_boolean(row, col, *value)?;
+                        }
+                        Value::Number(number) => {
+                            worksheet.write_number(
+                                row,
+                                col,
+                                number.as_f64().unwrap_or_default(),
+                            )?;
+                        }
+                        Value::String(text) => {
+                            match text.parse::<f64>() {
+                                Ok(number) => worksheet.write_number(row, col, number)?,
+                                Err(_) => worksheet.write_string(row, col, text)?,
+                            };
+                        }
+                        _ => {
+                            worksheet.write_string(row, col, cell.to_string())?;
+                        }
+                    };
+                }
+            }
+            worksheet.autofit();
+        }
+        Ok(workbook.save_to_buffer()?)
+    }
+}
+
+/// Part of a report laid out as rows under named columns.
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Lays a report out as tables: its single values as a one-row table named
+/// after the report, and each of its lists as a table named after the field.
+/// Nested objects become columns named `field.nested`.
+pub fn tables(report: &str, value: &Value) -> Vec<Table> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        Value::Array(records) => return vec![table(report, records)],
+        _ => return vec![table(report, std::slice::from_ref(value))],
+    };
+
+    let mut summary = Map::new();
+    let mut lists = Vec::new();
+    for (name, field) in fields {
+        match field {
+            Value::Array(records) => lists.push(table(name, records)),
+            _ => {
+                summary.insert(name.clone(), field.clone());
+            }
+        }
+    }
+
+    let mut tables = Vec::new();
+    if !summary.is_empty() {
+        tables.push(table(report, &[Value::Object(summary)]));
+    }
+    tables.extend(lists);
+    tables
+}
+
+fn table(name: &str, records: &[Value]) -> Table {
+    let records: Vec<Map<String, Value>> = records
+        .iter()
+        .map(|record| {
+            let mut row = Map::new();
+            flatten("", record, &mut row);
+            row
+        })
+        .collect();
+
+    let mut columns: Vec<String> = Vec::new();
+    for record in &records {
+        for column in record.keys() {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+    }
+    let rows = records
+        .into_iter()
+        .map(|mut record| {
+            columns
+                .iter()
+                .map(|column| record.remove(column).unwrap_or(Value::Null))
+                .collect()
+        })
+        .collect();
+
+    Table {
+        name: name.to_string(),
+        columns,
+        rows,
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, row: &mut Map<String, Value>) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields {
+                let column = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                flatten(&column, field, row);
+            }
+        }
+        _ => {
+            let column = if prefix.is_empty() { "value" } else { prefix };
+            row.insert(column.to_string(), value.clone());
+        }
+    }
+}
+
+fn cell_text(cell: &Value) -> String {
+    match cell {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        _ => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tables_split_single_values_from_lists() {
+        let report = json!({
+            "total": "120.50",
+            "currency": "EUR",
+            "tickers": [
+                {"ticker": "IWDA.AMS", "gain": {"amount": "100", "percent": 5.5}},
+                {"ticker": "BTC", "note": "x"},
+            ],
+        });
+
+        let tables = tables("realized-gains", &report);
+
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[0].name, "realized-gains");
+        assert_eq!(tables[0].columns, vec!["total", "currency"]);
+        assert_eq!(tables[0].rows, vec![vec![json!("120.50"), json!("EUR")]]);
+        assert_eq!(tables[1].name, "tickers");
+        assert_eq!(
+            tables[1].columns,
+            vec!["ticker", "gain.amount", "gain.percent", "note"]
+        );
+        assert_eq!(
+            tables[1].rows,
+            vec![
+                vec![json!("IWDA.AMS"), json!("100"), json!(5.5), Value::Null],
+                vec![json!("BTC"), Value::Null, Value::Null, json!("x")],
+            ]
+        );
+    }
+
+    #[test]
+    fn tables_of_a_list_or_a_value_are_named_after_the_report() {
+        let list = tables(
+            "xirr",
+            &json!([{"date": "2024-05-02"}, {"date": "2024-05-03"}]),
+        );
+        let value = tables("xirr", &json!(0.07));
+
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "xirr");
+        assert_eq!(list[0].rows.len(), 2);
+        assert_eq!(value[0].columns, vec!["value"]);
+        assert_eq!(value[0].rows, vec![vec![json!(0.07)]]);
+    }
+
+    #[test]
+    fn csv_quotes_fields_and_names_tables_when_there_are_several() {
+        let report = json!({
+            "total": "1,5",
+            "tickers": [{"ticker": "IWDA.AMS", "note": "say \"hi\"", "tag": null}],
+        });
+
+        let csv = CsvExporter.export("summary", &report).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "summary\ntotal\n\"1,5\"\n\ntickers\nticker,note,tag\nIWDA.AMS,\"say \"\"hi\"\"\",\n"
+        );
+    }
+
+    #[test]
+    fn csv_of_a_single_table_has_no_name_line() {
+        let csv = CsvExporter
+            .export("xirr", &json!([{"date": "2024-05-02", "rate": 0.07}]))
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "date,rate\n2024-05-02,0.07\n"
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use crate::alpha_vantage::{self, AlphaVantageError};
+use crate::db::DbPool;
+use crate::decimal::round_half_up;
+use crate::mock;
+use async_trait::async_trait;
+use bigdecimal::{BigDecimal, One};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Currency amounts are reported in unless another one is configured.
+pub const DEFAULT_BASE_CURRENCY: &str = "EUR";
+
+pub struct FxRate {
+    pub id: i64,
+    pub currency: String,
+    pub base_currency: String,
+    pub date: String,
+    pub rate: String,
+}
+
+pub async fn list_fx_rates(
+    pool: &DbPool,
+    currency: Option<&str>,
+) -> Result<Vec<FxRate>, sqlx::Error> {
+    sqlx::query_as!(
+        FxRate,
+        r#"
+        SELECT id as "id!", currency, base_currency, date, rate FROM fx_rates
+        WHERE CAST($1 AS TEXT) IS NULL OR currency = $1
+        ORDER BY date asc, currency asc, base_currency asc
+        "#,
+        currency
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Daily rates of `currency`, as units of `base_currency` per unit of
+/// `currency`, sorted by date.
+pub async fn list_fx_rates_for_calculation(
+    pool: &DbPool,
+    currency: &str,
+    base_currency: &str,
+) -> Result<Vec<(NaiveDate, BigDecimal)>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT date, rate FROM fx_rates
+        WHERE currency = $1 AND base_currency = $2
+        ORDER BY date asc
+        "#,
+        currency,
+        base_currency
+    )
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| {
+        (
+            NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap(),
+            BigDecimal::from_str(&row.rate).unwrap(),
+        )
+    })
+    .collect())
+}
+
+/// Rate in effect on `date`: the last one published on or before it.
+pub fn rate_on(fx_rates: &[(NaiveDate, BigDecimal)], date: NaiveDate) -> Option<&BigDecimal> {
+    fx_rates
+        .iter()
+        .take_while(|(rate_date, _)| *rate_date <= date)
+        .last()
+        .map(|(_, rate)| rate)
+}
+
+pub async fn get_last_fx_rate_date(
+    pool: &DbPool,
+    currency: &str,
+    base_currency: &str,
+) -> Result<Option<NaiveDate>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT date FROM fx_rates
+        WHERE currency = $1 AND base_currency = $2
+        ORDER BY date desc LIMIT 1
+        "#,
+        currency,
+        base_currency
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap()))
+}
+
+pub async fn get_first_fx_rate_date(
+    pool: &DbPool,
+    currency: &str,
+    base_currency: &str,
+) -> Result<Option<NaiveDate>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT date FROM fx_rates
+        WHERE currency = $1 AND base_currency = $2
+        ORDER BY date asc LIMIT 1
+        "#,
+        currency,
+        base_currency
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap()))
+}
+
+/// Stores `rates` of `currency` in `base_currency` all together or not at all,
+/// replacing the ones already stored.
+pub async fn upsert_fx_rates(
+    pool: &DbPool,
+    currency: &str,
+    base_currency: &str,
+    rates: &[(NaiveDate, BigDecimal)],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (date, rate) in rates {
+        let date = date.to_string();
+        let rate = rate.to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO fx_rates ( currency, base_currency, date, rate )
+            VALUES ( $1, $2, $3, $4 )
+            ON CONFLICT ( currency, base_currency, date ) DO UPDATE SET rate = excluded.rate
+            "#,
+            currency,
+            base_currency,
+            date,
+            rate
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Stores the rate of `currency` in `base_currency` on `date`, replacing the
+/// one already stored.
+pub async fn upsert_fx_rate(
+    pool: &DbPool,
+    currency: &str,
+    base_currency: &str,
+    date: &str,
+    rate: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO fx_rates ( currency, base_currency, date, rate )
+        VALUES ( $1, $2, $3, $4 )
+        ON CONFLICT ( currency, base_currency, date ) DO UPDATE SET rate = excluded.rate
+        "#,
+        currency,
+        base_currency,
+        date,
+        rate
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+const FX_RATE_SCALE: i64 = 10;
+
+pub enum FxProviderError {
+    /// The provider doesn't publish rates of the currency.
+    UnsupportedCurrency,
+    Request(reqwest::Error),
+    InvalidResponse(String),
+}
+
+impl From<reqwest::Error> for FxProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        // The URL carries the API key of some providers.
+        FxProviderError::Request(e.without_url())
+    }
+}
+
+impl From<AlphaVantageError> for FxProviderError {
+    fn from(e: AlphaVantageError) -> Self {
+        match e {
+            AlphaVantageError::Request(e) => FxProviderError::Request(e),
+            e => FxProviderError::InvalidResponse(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for FxProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FxProviderError::UnsupportedCurrency => write!(f, "unsupported currency"),
+            FxProviderError::Request(e) => write!(f, "request failed: {}", e),
+            FxProviderError::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+/// Source of daily FX rates.
+#[async_trait]
+pub trait FxProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Rates of `currency` published after `since`, as units of
+    /// `base_currency` per unit of `currency`.
+    async fn fetch_rates(
+        &self,
+        currency: &str,
+        base_currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError>;
+}
+
+/// Currencies of the ECB euro foreign exchange reference rates.
+const ECB_CURRENCIES: &[&str] = &[
+    "AUD", "BGN", "BRL", "CAD", "CHF", "CNY", "CZK", "DKK", "GBP", "HKD", "HUF", "IDR", "ILS",
+    "INR", "ISK", "JPY", "KRW", "MXN", "MYR", "NOK", "NZD", "PHP", "PLN", "RON", "SEK", "SGD",
+    "THB", "TRY", "USD", "ZAR",
+];
+
+/// Date of the first ECB euro reference rates.
+fn ecb_first_rate_date() -> NaiveDate {
+    NaiveDate::from_ymd(1999, 1, 4)
+}
+
+/// ECB euro reference rates. They are published as units of a currency per
+/// euro, so pairs without the euro are crossed through it. Needs no API key.
+pub struct EcbFxProvider;
+
+impl EcbFxProvider {
+    /// Units of `currency` per euro published after `since`.
+    async fn fetch_rates_per_euro(
+        &self,
+        currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+        if !ECB_CURRENCIES.contains(&currency) {
+            return Err(FxProviderError::UnsupportedCurrency);
+        }
+
+        let url = format!("https://data-api.ecb.europa.eu/service/data/EXR/D.{}.EUR.SP00.A?format=csvdata&detail=dataonly&startPeriod={}", currency, since.succ().max(ecb_first_rate_date()));
+        let resp = reqwest::get(url).await?;
+        // The ECB answers a query without observations, like one for today
+        // before the rates are published, with a 404.
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let body = resp.error_for_status()?.text().await?;
+
+        let mut lines = body.lines();
+        let header: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|column| *column == name)
+                .ok_or_else(|| FxProviderError::InvalidResponse(format!("no {} column", name)))
+        };
+        let date_column = column("TIME_PERIOD")?;
+        let value_column = column("OBS_VALUE")?;
+
+        let mut rates = Vec::new();
+        for line in lines.filter(|line| !line.is_empty()) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let (date, value) = match (fields.get(date_column), fields.get(value_column)) {
+                (Some(date), Some(value)) => (date, value),
+                _ => return Err(FxProviderError::InvalidResponse(line.to_string())),
+            };
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| FxProviderError::InvalidResponse(line.to_string()))?;
+            let value = BigDecimal::from_str(value)
+                .map_err(|_| FxProviderError::InvalidResponse(line.to_string()))?;
+            rates.push((date, value));
+        }
+        Ok(rates)
+    }
+}
+
+#[async_trait]
+impl FxProvider for EcbFxProvider {
+    fn name(&self) -> &'static str {
+        "ecb"
+    }
+
+    async fn fetch_rates(
+        &self,
+        currency: &str,
+        base_currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+        let rates = if base_currency == "EUR" {
+            self.fetch_rates_per_euro(currency, since)
+                .await?
+                .into_iter()
+                .map(|(date, per_euro)| (date, BigDecimal::one() / per_euro))
+                .collect()
+        } else if currency == "EUR" {
+            self.fetch_rates_per_euro(base_currency, since).await?
+        } else {
+            let base_per_euro: HashMap<NaiveDate, BigDecimal> = self
+                .fetch_rates_per_euro(base_currency, since)
+                .await?
+                .into_iter()
+                .collect();
+            self.fetch_rates_per_euro(currency, since)
+                .await?
+                .into_iter()
+                .filter_map(|(date, per_euro)| {
+                    base_per_euro
+                        .get(&date)
+                        .map(|base_per_euro| (date, base_per_euro / per_euro))
+                })
+                .collect::<Vec<_>>()
+        };
+        Ok(rates
+            .into_iter()
+            .map(|(date, rate)| (date, round_half_up(rate, FX_RATE_SCALE).normalized()))
+            .collect())
+    }
+}
+
+/// Deterministic synthetic rates, for development and demos without network
+/// access. Every currency walks on its own against the euro, and pairs are
+/// crossed through it, so the rates of a pair and its inverse agree.
+pub struct MockFxProvider;
+
+impl MockFxProvider {
+    /// Units of `currency` per euro on every weekday up to today.
+    fn rates_per_euro(currency: &str) -> Vec<(NaiveDate, f64)> {
+        let today = Utc::today().naive_utc();
+        let walk = mock::random_walk(currency, today, false, (0.5, 2.0), 0.005);
+        if currency == "EUR" {
+            walk.into_iter().map(|(date, _)| (date, 1.0)).collect()
+        } else {
+            walk
+        }
+    }
+}
+
+#[async_trait]
+impl FxProvider for MockFxProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn fetch_rates(
+        &self,
+        currency: &str,
+        base_currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+        let base_per_euro = Self::rates_per_euro(base_currency);
+        Self::rates_per_euro(currency)
+            .into_iter()
+            .zip(base_per_euro)
+            .filter(|((date, _), _)| *date > since)
+            .map(|((date, per_euro), (_, base_per_euro))| {
+                let rate = format!("{:.6}", base_per_euro / per_euro);
+                BigDecimal::from_str(&rate)
+                    .map(|rate| (date, rate))
+                    .map_err(|_| FxProviderError::InvalidResponse(rate))
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct AlphaVantageDailyRateResponse {
+    #[serde(rename(deserialize = "4. close"))]
+    rate: String,
+}
+
+#[derive(Deserialize)]
+struct AlphaVantageFxApiResponse {
+    #[serde(rename(deserialize = "Time Series FX (Daily)"))]
+    time_series: HashMap<String, AlphaVantageDailyRateResponse>,
+}
+
+/// Alpha Vantage FX_DAILY, which covers most currency pairs.
+pub struct AlphaVantageFxProvider {
+    api_key: String,
+}
+
+impl AlphaVantageFxProvider {
+    pub fn new(api_key: String) -> Self {
+        AlphaVantageFxProvider { api_key }
+    }
+}
+
+#[async_trait]
+impl FxProvider for AlphaVantageFxProvider {
+    fn name(&self) -> &'static str {
+        "alpha_vantage"
+    }
+
+    async fn fetch_rates(
+        &self,
+        currency: &str,
+        base_currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+        let output_size = if since > Utc::today().naive_utc() + Duration::days(-100) {
+            "compact"
+        } else {
+            "full"
+        };
+        let url = format!("https://www.alphavantage.co/query?function=FX_DAILY&from_symbol={}&to_symbol={}&apikey={}&outputsize={}", currency, base_currency, self.api_key, output_size);
+        let resp = alpha_vantage::query::<AlphaVantageFxApiResponse>(&url).await?;
+
+        let mut rates = Vec::new();
+        for (date, rate) in resp.time_series {
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| FxProviderError::InvalidResponse(date.clone()))?;
+            if date > since {
+                let rate = BigDecimal::from_str(&rate.rate)
+                    .map_err(|_| FxProviderError::InvalidResponse(rate.rate.clone()))?;
+                rates.push((date, rate));
+            }
+        }
+        rates.sort_by_key(|(date, _)| *date);
+        Ok(rates)
+    }
+}
+
+/// Providers in their default order, plus the order to try them in for
+/// currencies some provider covers better.
+pub struct FxProviders {
+    providers: Vec<Box<dyn FxProvider>>,
+    preferences: HashMap<String, Vec<String>>,
+}
+
+impl FxProviders {
+    pub fn new(providers: Vec<Box<dyn FxProvider>>) -> Self {
+        FxProviders {
+            providers,
+            preferences: HashMap::new(),
+        }
+    }
+
+    /// Tries the providers named in `provider_names` first for `currency`,
+    /// in that order, before the remaining ones.
+    pub fn prefer(mut self, currency: &str, provider_names: &[&str]) -> Self {
+        self.preferences.insert(
+            currency.to_string(),
+            provider_names.iter().map(|name| name.to_string()).collect(),
+        );
+        self
+    }
+
+    fn for_currency(&self, currency: &str) -> Vec<&dyn FxProvider> {
+        let preferred = self
+            .preferences
+            .get(currency)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let mut providers: Vec<&dyn FxProvider> = preferred
+            .iter()
+            .filter_map(|name| self.providers.iter().find(|p| p.name() == name))
+            .map(|p| p.as_ref())
+            .collect();
+        providers.extend(
+            self.providers
+                .iter()
+                .filter(|p| !preferred.iter().any(|name| name == p.name()))
+                .map(|p| p.as_ref()),
+        );
+        providers
+    }
+
+    /// Rates of `currency` in `base_currency` after `since` from the first
+    /// provider that returns them. Failures are logged before falling back to
+    /// the next provider; the last one is returned when every provider fails.
+    pub async fn fetch_rates(
+        &self,
+        currency: &str,
+        base_currency: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+        let mut last_error = FxProviderError::UnsupportedCurrency;
+        for provider in self.for_currency(currency) {
+            match provider.fetch_rates(currency, base_currency, since).await {
+                Ok(rates) => return Ok(rates),
+                Err(e) => {
+                    println!("{} rates of {} failed: {}", provider.name(), currency, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal};
+
+    #[test]
+    fn rate_on_takes_the_last_rate_published_on_or_before_the_date() {
+        let fx_rates = vec![
+            (date("2024-05-02"), decimal("0.93")),
+            (date("2024-05-03"), decimal("0.92")),
+            (date("2024-05-06"), decimal("0.94")),
+        ];
+
+        assert_eq!(
+            rate_on(&fx_rates, date("2024-05-03")),
+            Some(&decimal("0.92"))
+        );
+        assert_eq!(
+            rate_on(&fx_rates, date("2024-05-05")),
+            Some(&decimal("0.92"))
+        );
+        assert_eq!(
+            rate_on(&fx_rates, date("2024-06-01")),
+            Some(&decimal("0.94"))
+        );
+    }
+
+    #[test]
+    fn rate_on_is_none_before_the_first_rate() {
+        let fx_rates = vec![(date("2024-05-02"), decimal("0.93"))];
+
+        assert_eq!(rate_on(&fx_rates, date("2024-05-01")), None);
+        assert_eq!(rate_on(&[], date("2024-05-01")), None);
+    }
+
+    /// Answers every currency with a single rate, or fails when it has none.
+    struct FixedFxProvider {
+        name: &'static str,
+        rate: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl FxProvider for FixedFxProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn fetch_rates(
+            &self,
+            _currency: &str,
+            _base_currency: &str,
+            _since: NaiveDate,
+        ) -> Result<Vec<(NaiveDate, BigDecimal)>, FxProviderError> {
+            match self.rate {
+                Some(rate) => Ok(vec![(date("2024-05-02"), decimal(rate))]),
+                None => Err(FxProviderError::InvalidResponse(self.name.to_string())),
+            }
+        }
+    }
+
+    fn providers(rates: &[(&'static str, Option<&'static str>)]) -> FxProviders {
+        FxProviders::new(
+            rates
+                .iter()
+                .map(|(name, rate)| {
+                    Box::new(FixedFxProvider { name, rate: *rate }) as Box<dyn FxProvider>
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn providers_fall_back_to_the_next_one_on_failure() {
+        let providers = providers(&[("ecb", None), ("alpha_vantage", Some("0.92"))]);
+
+        let rates = providers
+            .fetch_rates("USD", "EUR", date("2024-05-01"))
+            .await
+            .ok()
+            .unwrap();
+
+        assert_eq!(rates, vec![(date("2024-05-02"), decimal("0.92"))]);
+    }
+
+    #[tokio::test]
+    async fn preferred_providers_go_first_for_their_currency_only() {
+        let providers = providers(&[("ecb", Some("0.93")), ("alpha_vantage", Some("0.92"))])
+            .prefer("USD", &["alpha_vantage"]);
+
+        let usd = providers
+            .fetch_rates("USD", "EUR", date("2024-05-01"))
+            .await;
+        let gbp = providers
+            .fetch_rates("GBP", "EUR", date("2024-05-01"))
+            .await;
+
+        assert_eq!(usd.ok().unwrap()[0].1, decimal("0.92"));
+        assert_eq!(gbp.ok().unwrap()[0].1, decimal("0.93"));
+    }
+
+    #[tokio::test]
+    async fn providers_return_the_last_failure() {
+        let providers = providers(&[("ecb", None), ("alpha_vantage", None)]);
+
+        let rates = providers
+            .fetch_rates("USD", "EUR", date("2024-05-01"))
+            .await;
+
+        assert!(matches!(
+            rates,
+            Err(FxProviderError::InvalidResponse(name)) if name == "alpha_vantage"
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Finished jobs kept for their status to be polled; older ones are dropped.
+const MAX_FINISHED_JOBS: usize = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Finished,
+}
+
+/// Progress of work running in the background. Jobs live in memory and are
+/// lost on restart.
+#[derive(Clone)]
+pub struct Job {
+    pub id: u64,
+    pub kind: &'static str,
+    pub state: JobState,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Steps the job is made of, like the tickers of a price update.
+    pub total: usize,
+    pub done: usize,
+    /// Rows the job stored.
+    pub inserted: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<(u64, VecDeque<Job>)>,
+}
+
+impl JobRegistry {
+    /// Registers a running job of `kind` made of `total` steps and returns its
+    /// id, or the id of the job of `kind` already running, as an error.
+    pub fn start(&self, kind: &'static str, total: usize) -> Result<u64, u64> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let (last_id, jobs) = &mut *jobs;
+        if let Some(running) = jobs
+            .iter()
+            .find(|job| job.kind == kind && job.state == JobState::Running)
+        {
+            return Err(running.id);
+        }
+        *last_id += 1;
+        jobs.push_back(Job {
+            id: *last_id,
+            kind,
+            state: JobState::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            total,
+            done: 0,
+            inserted: 0,
+            errors: Vec::new(),
+        });
+        Ok(*last_id)
+    }
+
+    pub fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.1.iter_mut().find(|job| job.id == id) {
+            f(job);
+        }
+    }
+
+    pub fn finish(&self, id: u64) {
+        self.update(id, |job| {
+            job.state = JobState::Finished;
+            job.finished_at = Some(Utc::now());
+        });
+        let mut jobs = self.jobs.lock().unwrap();
+        let jobs = &mut jobs.1;
+        while jobs
+            .iter()
+            .filter(|job| job.state == JobState::Finished)
+            .count()
+            > MAX_FINISHED_JOBS
+        {
+            match jobs.iter().position(|job| job.state == JobState::Finished) {
+                Some(oldest) => jobs.remove(oldest),
+                None => break,
+            };
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.1.iter().find(|job| job.id == id).cloned()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::db::DbPool;
+
+pub struct SaveJournalEntry {
+    pub ticker: Option<String>,
+    pub date: String,
+    pub text: String,
+}
+
+pub async fn create_journal_entry(
+    pool: &DbPool,
+    entry: SaveJournalEntry,
+) -> Result<i64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO journal_entries ( ticker, date, text )
+        VALUES ( $1, $2, $3 )
+        RETURNING id as "id!"
+        "#,
+        entry.ticker,
+        entry.date,
+        entry.text
+    )
+    .fetch_one(pool)
+    .await?
+    .id)
+}
+
+pub struct JournalEntry {
+    pub id: i64,
+    pub ticker: Option<String>,
+    pub date: String,
+    pub text: String,
+}
+
+pub async fn list_journal_entries(
+    pool: &DbPool,
+    ticker: Option<String>,
+) -> Result<Vec<JournalEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        JournalEntry,
+        r#"
+        SELECT id as "id!", ticker, date, text FROM journal_entries
+        WHERE CAST($1 AS TEXT) IS NULL OR ticker = $1
+        ORDER BY date asc, id asc
+        "#,
+        ticker
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_journal_entry(
+    pool: &DbPool,
+    entry_id: i64,
+) -> Result<Option<JournalEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        JournalEntry,
+        r#"
+        SELECT id, ticker, date, text FROM journal_entries WHERE id = $1
+        "#,
+        entry_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_journal_entry(
+    pool: &DbPool,
+    entry_id: i64,
+    entry: SaveJournalEntry,
+) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        UPDATE journal_entries SET ticker = $1, date = $2, text = $3 WHERE id = $4
+        "#,
+        entry.ticker,
+        entry.date,
+        entry.text,
+        entry_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+pub async fn delete_journal_entry(pool: &DbPool, entry_id: i64) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        DELETE FROM journal_entries WHERE id = $1
+        "#,
+        entry_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
@@ -0,0 +1,30 @@
+pub mod alpha_vantage;
+pub mod archive;
+pub mod backup;
+pub mod cache;
+pub mod cash;
+pub mod config;
+pub mod corporate_action;
+pub mod db;
+pub mod decimal;
+pub mod dividend;
+pub mod export;
+pub mod fx;
+pub mod job;
+pub mod journal;
+pub mod mock;
+pub mod operation;
+pub mod period_lock;
+pub mod portfolio;
+pub mod position;
+pub mod price;
+pub mod report;
+pub mod selfcheck;
+pub mod snapshot;
+pub mod target;
+#[cfg(test)]
+mod test_util;
+pub mod ticker;
+pub mod trade;
+pub mod valuation;
+pub mod xirr;
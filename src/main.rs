@@ -1,31 +1,139 @@
-mod db;
-mod trade;
+use portfolio_tracker::cache::{InMemoryCache, RedisCache, ResponseCache};
+use portfolio_tracker::config::OutlierPolicy;
+use portfolio_tracker::portfolio::{self, Portfolio};
+use portfolio_tracker::{
+    alpha_vantage, archive, backup, cash, config, corporate_action, db, decimal, dividend, export,
+    fx, job, journal, operation, period_lock, position, price, report, selfcheck, snapshot, target,
+    ticker, trade, valuation, xirr,
+};
 
 use anyhow::Result;
 use axum::{
-    extract::{Extension, Path},
-    http::StatusCode,
-    routing::{delete, get, post},
+    body::{Body, HttpBody, StreamBody},
+    extract::{Extension, Path, Query},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
-use bigdecimal::BigDecimal;
-use chrono::{Duration, NaiveDate, Utc};
+use bigdecimal::{BigDecimal, FromPrimitive, Signed, ToPrimitive, Zero};
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Utc};
 use dotenv::dotenv;
+use futures::{SinkExt, TryStreamExt};
+use portfolio_tracker::db::{Db, DbPool};
 use serde::Deserialize;
-use sqlx::SqlitePool;
-use std::collections::HashMap;
-use std::env;
-use std::net::SocketAddr;
+use sqlx::Transaction;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+/// Settings loaded at startup.
+static CONFIG: OnceLock<config::Config> = OnceLock::new();
+
+fn config() -> &'static config::Config {
+    CONFIG
+        .get()
+        .expect("the configuration is loaded at startup")
+}
+
+struct TrackedTickers {
+    tickers: Vec<&'static str>,
+    currencies: Vec<(&'static str, &'static str)>,
+    crypto: Vec<&'static str>,
+    accumulating: Vec<&'static str>,
+}
+
+static TRACKED_TICKERS: OnceLock<TrackedTickers> = OnceLock::new();
+
+fn ticker_config() -> &'static TrackedTickers {
+    TRACKED_TICKERS.get_or_init(|| {
+        let configured = &config().tickers;
+        TrackedTickers {
+            tickers: configured
+                .iter()
+                .map(|ticker| ticker.ticker.as_str())
+                .collect(),
+            currencies: configured
+                .iter()
+                .filter_map(|ticker| Some((ticker.ticker.as_str(), ticker.currency.as_deref()?)))
+                .collect(),
+            crypto: configured
+                .iter()
+                .filter(|ticker| ticker.crypto)
+                .map(|ticker| ticker.ticker.as_str())
+                .collect(),
+            accumulating: configured
+                .iter()
+                .filter(|ticker| ticker.accumulating)
+                .map(|ticker| ticker.ticker.as_str())
+                .collect(),
+        }
+    })
+}
+
+fn tracked_tickers() -> &'static [&'static str] {
+    &ticker_config().tickers
+}
+
+/// Currency each ticker is quoted in.
+fn ticker_currencies() -> &'static [(&'static str, &'static str)] {
+    &ticker_config().currencies
+}
+
+/// Tickers that are cryptocurrencies. Their prices come from CoinGecko, see
+/// the `add_crypto_price_sources` migration.
+fn crypto_tickers() -> &'static [&'static str] {
+    &ticker_config().crypto
+}
+
+/// Tickers of accumulating funds, whose income is reinvested into their price
+/// instead of paid out.
+fn accumulating_tickers() -> &'static [&'static str] {
+    &ticker_config().accumulating
+}
+
+/// Currency tickers missing from `ticker_currencies` are quoted in.
+const DEFAULT_QUOTE_CURRENCY: &str = "EUR";
 
-const TICKERS: &'static [&'static str] = &["IWDA.AMS", "NQSE.DEX"];
+fn ticker_currency(ticker: &str) -> &'static str {
+    ticker_currencies()
+        .iter()
+        .find(|(t, _)| *t == ticker)
+        .map_or(DEFAULT_QUOTE_CURRENCY, |(_, currency)| currency)
+}
+
+/// Currency every amount is converted to and reported in.
+fn base_currency() -> String {
+    config().base_currency.clone()
+}
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
+    let config = match config::Config::load() {
+        Ok(config) => CONFIG.get_or_init(|| config),
+        Err(e) => {
+            println!("Error loading the configuration {}", e);
+            return;
+        }
+    };
+    if let Some(unknown) = config
+        .price_trust_order
+        .iter()
+        .flatten()
+        .find(|provider| !PRICE_PROVIDERS.contains(&provider.as_str()))
+    {
+        println!(
+            "Error loading the configuration PRICE_TRUST_ORDER names unknown provider {}",
+            unknown
+        );
+        return;
+    }
 
-    let pool = match db::prepare_db_and_get_connection().await {
+    let pool = match db::prepare_db_and_get_connection(&config.database).await {
         Ok(pool) => pool,
         Err(e) => {
             println!("Error creating preparing database connection {}", e);
@@ -33,30 +141,532 @@ async fn main() {
         }
     };
 
+    let cache: Arc<dyn ResponseCache> = match &config.redis_url {
+        Some(url) => match RedisCache::connect(url).await {
+            Ok(cache) => Arc::new(cache),
+            Err(e) => {
+                println!("Error connecting to Redis {}", e);
+                return;
+            }
+        },
+        None => Arc::new(InMemoryCache::default()),
+    };
+
+    for check in run_self_check(&pool).await.checks {
+        println!(
+            "Self-check {}: {:?} ({})",
+            check.name, check.status, check.detail
+        );
+    }
+
+    tokio::spawn(run_price_verification(pool.clone()));
+    if let Some(path) = &config.backup_path {
+        tokio::spawn(run_backups(pool.clone(), path.clone()));
+    }
+    if let Some(at) = config.price_update_time {
+        tokio::spawn(run_price_updates(pool.clone(), cache.clone(), at));
+    }
+
+    let max_body_bytes = config.max_body_bytes;
+
     let app = Router::new()
         .route("/trades", post(create_trade))
         .route("/trades", get(list_trades))
+        .route("/trades/quick", post(quick_add_trade))
+        .route("/trades/stats", get(trade_stats))
         .route("/trades/:trade_id", delete(delete_trade))
+        .route("/trades/merge", post(merge_trades))
+        .route("/trades/:trade_id/tags", put(set_trade_tags))
+        .route("/trades/:trade_id/split", post(split_trade))
+        .route("/trades/:trade_id/date", patch(amend_trade_date))
+        .route("/journal", post(create_journal_entry))
+        .route("/journal", get(list_journal_entries))
+        .route("/journal/:entry_id", get(get_journal_entry))
+        .route("/journal/:entry_id", put(update_journal_entry))
+        .route("/journal/:entry_id", delete(delete_journal_entry))
+        .route("/dividends", post(create_dividend))
+        .route("/dividends", get(list_dividends))
+        .route("/dividends/:dividend_id", get(get_dividend))
+        .route("/dividends/:dividend_id", put(update_dividend))
+        .route("/dividends/:dividend_id", delete(delete_dividend))
+        .route("/cash", post(create_cash_movement))
+        .route("/cash", get(list_cash_movements))
+        .route("/cash/balance", get(get_cash_balance))
+        .route("/cash/:movement_id", delete(delete_cash_movement))
+        .route("/corporate-actions", get(list_corporate_actions))
+        .route("/corporate-actions/split", post(create_split))
+        .route("/tickers/rename", post(rename_ticker))
+        .route("/tickers/search", get(search_tickers))
+        .route("/tickers/targets", get(list_targets))
+        .route("/tickers/:ticker/provider", patch(update_price_source))
+        .route("/tickers/:ticker/quality", get(ticker_quality))
+        .route("/tickers/:ticker/target", put(set_target))
+        .route("/tickers/:ticker/target", delete(delete_target))
         .route("/prices", get(list_prices))
+        .route("/prices", post(create_price))
         .route("/prices", delete(delete_prices))
-        .route("/prices/update", get(update_prices))
+        .route("/prices/:price_id", patch(correct_price))
+        .route("/prices/:price_id/marks", put(set_price_marks))
+        .route("/prices/update", post(update_prices))
+        .route("/prices/gaps", get(list_price_gaps))
+        .route("/prices/backfill", post(backfill_price_gaps))
+        .route("/prices/conflicts", get(list_price_conflicts))
+        .route("/prices/conflicts/resolve", post(resolve_price_conflict))
+        .route("/jobs/:job_id", get(get_job))
+        .route("/admin/prices/normalize", post(normalize_prices))
+        .route("/admin/prices/verify", get(verify_prices_report))
+        .route("/admin/prices/anomalies", get(price_anomalies_report))
+        .route("/admin/selfcheck", get(self_check_report))
+        .route("/admin/backup", get(get_backup))
+        .route("/admin/backup", post(create_backup))
+        .route("/admin/lock-period", get(get_period_lock))
+        .route("/admin/lock-period", post(lock_period))
+        .route("/admin/operations", get(list_operations))
+        .route("/admin/undo/:operation_id", post(undo_operation))
+        .route("/fx", get(list_fx_rates))
+        .route("/fx/update", get(update_fx))
+        .route("/admin/fx/backfill", post(backfill_fx_rates))
         .route("/portfolio", get(generate_portfolio))
-        .layer(Extension(pool));
+        .route("/portfolio/total", get(generate_total_portfolio))
+        .route("/portfolio/positions", get(list_positions))
+        .route("/portfolio/allocation", get(list_allocation))
+        .route("/portfolio/invested", get(generate_invested_capital))
+        .route("/portfolio/value", get(portfolio_value_on_date))
+        .route("/portfolio/valuations", get(list_portfolio_valuations))
+        .route("/portfolio/valuation", put(set_portfolio_valuation))
+        .route("/portfolio/waterfall", get(generate_waterfall))
+        .route("/portfolio/:ticker", get(generate_ticker_portfolio))
+        .route("/reports/realized-gains", get(realized_gains_report))
+        .route(
+            "/reports/realized-gains/archive",
+            post(archive_realized_gains_report),
+        )
+        .route("/reports/archive", get(list_archived_reports))
+        .route("/reports/archive/:archive_id", get(get_archived_report))
+        .route("/reports/xirr", get(xirr_report))
+        .route("/reports/twr", get(twr_report))
+        .route("/reports/cashflows", get(cash_flow_statement_report))
+        .route("/reports/summary", get(summary_report))
+        .route("/reports/risk", get(risk_report))
+        .route("/reports/contributions", get(contributions_report))
+        .route("/reports/income", get(income_report))
+        .route("/widget", get(widget))
+        .layer(Extension(pool))
+        .layer(Extension(cache))
+        .layer(Extension(Arc::new(job::JobRegistry::default())))
+        .layer(middleware::from_fn(move |req, next| {
+            limit_body_size(req, next, max_body_bytes)
+        }));
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    let addr = config.listen_addr;
+    println!("Listening on {}", addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
         .await
         .unwrap();
 }
 
+/// Failure answered by a handler: its status and a JSON body with a stable
+/// `code`, a `message` for people and, for some errors, fields telling what
+/// was wrong.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            details: serde_json::Map::new(),
+        }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    /// A field of the request body that does not hold a valid value.
+    fn invalid_field(field: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_field", message).with_detail("field", field)
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    fn conflict(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    /// A provider the request depends on failed or answered nonsense.
+    fn bad_gateway(message: impl Into<String>) -> Self {
+        ApiError::new(StatusCode::BAD_GATEWAY, "upstream_error", message)
+    }
+
+    /// Logs `error`, which the client can do nothing about, and hides it.
+    fn internal(error: impl std::fmt::Display) -> Self {
+        println!("Internal error: {}", error);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "internal error, see the server log",
+        )
+    }
+
+    fn with_detail(mut self, name: &str, value: impl serde::Serialize) -> Self {
+        self.details.insert(
+            name.to_string(),
+            serde_json::to_value(value).unwrap_or_default(),
+        );
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::Map::new();
+        body.insert("code".to_string(), self.code.into());
+        body.insert("message".to_string(), self.message.into());
+        body.extend(self.details);
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Starts the transaction a handler runs its steps in when it makes more than
+/// one change, so a failure in any of them leaves none applied: a transaction
+/// dropped before `commit` is rolled back. The storage functions taking an
+/// executor run within it.
+async fn begin(pool: &DbPool) -> Result<Transaction<'static, Db>, ApiError> {
+    pool.begin().await.map_err(ApiError::internal)
+}
+
+async fn commit(tx: Transaction<'_, Db>) -> Result<(), ApiError> {
+    tx.commit().await.map_err(ApiError::internal)
+}
+
+/// Rejects request bodies larger than `max_body_bytes` with 413 before any
+/// handler parses them or holds the database, whether or not they declare
+/// their length.
+async fn limit_body_size(
+    req: Request<Body>,
+    next: Next<Body>,
+    max_body_bytes: usize,
+) -> Result<Response, ApiError> {
+    let (parts, mut body) = req.into_parts();
+    let declared_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<usize>().ok());
+    let too_large = || {
+        ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("request bodies are limited to {} bytes", max_body_bytes),
+        )
+    };
+    if declared_length.is_some_and(|length| length > max_body_bytes) {
+        return Err(too_large());
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| ApiError::bad_request(e.to_string()))?;
+        if bytes.len() + chunk.len() > max_body_bytes {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await)
+}
+
+#[derive(Deserialize)]
+struct LockOverride {
+    /// Lets an admin change trades inside the locked period.
+    #[serde(default)]
+    admin_override: bool,
+}
+
+/// Rejects with 423 a change to trades on any of `dates` when one falls in the
+/// locked period, unless the request carries the admin override.
+async fn check_period_lock(
+    pool: &DbPool,
+    dates: &[NaiveDate],
+    lock: &LockOverride,
+) -> Result<(), ApiError> {
+    if lock.admin_override {
+        return Ok(());
+    }
+    let locked_until = period_lock::get_locked_until(pool)
+        .await
+        .map_err(ApiError::internal)?;
+    if let Some(locked_until) = period_lock::lock_covering(dates, locked_until) {
+        return Err(ApiError::new(
+            StatusCode::LOCKED,
+            "period_locked",
+            format!("trades up to {} are locked", locked_until),
+        )
+        .with_detail("locked_until", locked_until));
+    }
+    Ok(())
+}
+
+/// Like `check_period_lock` for stored trades. Unknown ids are skipped, so
+/// the caller can still answer them with 404.
+async fn check_trades_period_lock(
+    pool: &DbPool,
+    trade_ids: &[i64],
+    lock: &LockOverride,
+) -> Result<(), ApiError> {
+    let mut dates = Vec::with_capacity(trade_ids.len());
+    for trade_id in trade_ids {
+        match trade::get_trade(pool, *trade_id).await {
+            Ok(Some(trade)) => dates.push(
+                NaiveDate::parse_from_str(&trade.date, "%Y-%m-%d").map_err(ApiError::internal)?,
+            ),
+            Ok(None) => {}
+            Err(e) => return Err(ApiError::internal(e)),
+        }
+    }
+    check_period_lock(pool, &dates, lock).await
+}
+
+/// Date of the reinvestment trade of a stored dividend, if it was reinvested.
+async fn reinvestment_trade_date(
+    pool: &DbPool,
+    dividend_id: i64,
+) -> Result<Option<NaiveDate>, ApiError> {
+    match dividend::get_dividend(pool, dividend_id).await {
+        Ok(Some(dividend)) if dividend.reinvested => {
+            NaiveDate::parse_from_str(&dividend.pay_date, "%Y-%m-%d")
+                .map(Some)
+                .map_err(ApiError::internal)
+        }
+        Ok(_) => Ok(None),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PeriodLockResponse {
+    locked_until: Option<NaiveDate>,
+}
+
+async fn get_period_lock(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<PeriodLockResponse>, ApiError> {
+    match period_lock::get_locked_until(&pool).await {
+        Ok(locked_until) => Ok(Json(PeriodLockResponse { locked_until })),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct LockPeriodQuery {
+    until: NaiveDate,
+}
+
+/// Locks trades dated on or before `until`. Moving the lock back to reopen a
+/// period needs the admin override.
+async fn lock_period(
+    Query(query): Query<LockPeriodQuery>,
+    Query(lock): Query<LockOverride>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<StatusCode, ApiError> {
+    check_period_lock(&pool, &[query.until], &lock).await?;
+    match period_lock::lock_period(&pool, query.until).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct OperationSummaryResponse {
+    id: i64,
+    kind: &'static str,
+    created_at: chrono::DateTime<Utc>,
+    undone_at: Option<chrono::DateTime<Utc>>,
+    /// Rows the operation removed.
+    rows: usize,
+}
+
+/// Destructive operations that can still be undone, or were, the last one
+/// first.
+async fn list_operations(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<OperationSummaryResponse>>, ApiError> {
+    match operation::list_operations(&pool).await {
+        Ok(operations) => Ok(Json(
+            operations
+                .into_iter()
+                .filter(|recorded| !recorded.is_expired())
+                .map(|recorded| OperationSummaryResponse {
+                    id: recorded.id,
+                    kind: recorded.operation.kind(),
+                    created_at: recorded.created_at,
+                    undone_at: recorded.undone_at,
+                    rows: recorded.operation.rows(),
+                })
+                .collect(),
+        )),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct UndoResponse {
+    restored: usize,
+    /// Rows left out because another one was stored in their place since.
+    skipped: usize,
+}
+
+/// Puts back what a destructive operation removed, within
+/// `operation::RETENTION_DAYS` days of it. Operations can be undone in any
+/// order, each once.
+async fn undo_operation(
+    Query(lock): Query<LockOverride>,
+    Path(operation_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Json<UndoResponse>, ApiError> {
+    let recorded = match operation::get_operation(&pool, operation_id).await {
+        Ok(Some(recorded)) => recorded,
+        Ok(None) => {
+            return Err(ApiError::not_found(format!(
+                "operation {} not found",
+                operation_id
+            )))
+        }
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    if recorded.is_expired() {
+        return Err(ApiError::new(
+            StatusCode::GONE,
+            "expired",
+            format!(
+                "operation {} is older than {} days",
+                operation_id,
+                operation::RETENTION_DAYS
+            ),
+        ));
+    }
+    let already_undone =
+        || ApiError::conflict(format!("operation {} was already undone", operation_id));
+    if recorded.undone_at.is_some() {
+        return Err(already_undone());
+    }
+    let restored_trade = match &recorded.operation {
+        operation::Operation::DeleteTrade { trade }
+        | operation::Operation::SplitTrade { trade, .. } => {
+            let date =
+                NaiveDate::parse_from_str(&trade.date, "%Y-%m-%d").map_err(ApiError::internal)?;
+            check_period_lock(&pool, &[date], &lock).await?;
+            Some((trade.ticker.clone(), date))
+        }
+        // Merged trades share the ticker and the date.
+        operation::Operation::MergeTrades { trades } => match trades.first() {
+            Some(trade) => {
+                let date = NaiveDate::parse_from_str(&trade.date, "%Y-%m-%d")
+                    .map_err(ApiError::internal)?;
+                check_period_lock(&pool, &[date], &lock).await?;
+                Some((trade.ticker.clone(), date))
+            }
+            None => None,
+        },
+        operation::Operation::DeletePrices { .. }
+        | operation::Operation::NormalizePrices { .. } => None,
+    };
+
+    let outcome = match operation::undo_operation(&pool, operation_id, &recorded.operation).await {
+        Ok(Some(outcome)) => outcome,
+        Ok(None) => return Err(already_undone()),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    match restored_trade {
+        Some((ticker, date)) => {
+            invalidate_portfolio_from(&pool, cache.as_ref(), &ticker, date).await
+        }
+        None => invalidate_portfolio(&pool, cache.as_ref()).await,
+    }
+    Ok(Json(UndoResponse {
+        restored: outcome.restored,
+        skipped: outcome.skipped,
+    }))
+}
+
 #[derive(serde::Deserialize)]
 struct CreateTrade {
     ticker: String,
     date: String,
     r#type: String,
-    amount: u32,
+    amount: BigDecimal,
     price: String,
+    fee: Option<BigDecimal>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Accepts a price the outlier policy would reject.
+    #[serde(default)]
+    force: bool,
+}
+
+impl CreateTrade {
+    /// Checks the fields that are stored as given and only parsed when the
+    /// trade is used in a calculation. Returns the date of the trade.
+    fn validate(&self) -> Result<NaiveDate, ApiError> {
+        let date = NaiveDate::parse_from_str(&self.date, "%Y-%m-%d").map_err(|_| {
+            ApiError::invalid_field("date", format!("{} is not a YYYY-MM-DD date", self.date))
+        })?;
+        if self.r#type != "BUY" && self.r#type != "SELL" {
+            return Err(ApiError::invalid_field(
+                "type",
+                format!("{} is not BUY or SELL", self.r#type),
+            ));
+        }
+        if self.amount <= BigDecimal::from(0) {
+            return Err(ApiError::invalid_field("amount", "must be positive"));
+        }
+        match BigDecimal::from_str(&self.price) {
+            Ok(price) if price > BigDecimal::from(0) => {}
+            _ => {
+                return Err(ApiError::invalid_field(
+                    "price",
+                    format!("{} is not a positive decimal", self.price),
+                ))
+            }
+        }
+        Ok(date)
+    }
+
+    /// Checks that a SELL does not sell more units than are held on its date.
+    async fn check_holding(&self, pool: &DbPool, date: NaiveDate) -> Result<(), ApiError> {
+        if self.r#type != "SELL" {
+            return Ok(());
+        }
+        let holding = match trade::holding_on(pool, &self.ticker, date).await {
+            Ok(holding) => holding,
+            Err(e) => return Err(ApiError::internal(e)),
+        };
+        if holding < self.amount {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "insufficient_holding",
+                format!(
+                    "cannot sell {} units of {}, {} are held on {}",
+                    self.amount, self.ticker, holding, self.date
+                ),
+            )
+            .with_detail("holding", holding));
+        }
+        Ok(())
+    }
 }
 
 impl From<CreateTrade> for trade::CreateTrade {
@@ -65,22 +675,96 @@ impl From<CreateTrade> for trade::CreateTrade {
             ticker: create_trade.ticker,
             date: create_trade.date,
             r#type: create_trade.r#type,
-            amount: create_trade.amount,
+            amount: create_trade.amount.to_string(),
             price: create_trade.price,
+            fee: create_trade.fee.map(|fee| fee.to_string()),
+            tags: create_trade.tags,
         }
     }
 }
 
+/// What to do with a trade whose price deviates from the stored close of its
+/// date by more than `outlier_threshold_percent`, set with
+/// `TRADE_OUTLIER_POLICY`.
+fn outlier_policy() -> OutlierPolicy {
+    config().outlier_policy
+}
+
+fn outlier_threshold_percent() -> BigDecimal {
+    config().outlier_threshold_percent.clone()
+}
+
+/// Describes how far the trade price is from the stored close of its date,
+/// when it is further than the threshold.
+async fn price_outlier_warning(
+    pool: &DbPool,
+    payload: &CreateTrade,
+) -> Result<Option<String>, ApiError> {
+    let close = match price::get_price(pool, &payload.ticker, &payload.date).await {
+        Ok(Some(close)) => BigDecimal::from_str(&close).map_err(ApiError::internal)?,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    if close == BigDecimal::from(0) {
+        return Ok(None);
+    }
+    let price = BigDecimal::from_str(&payload.price)
+        .map_err(|_| ApiError::invalid_field("price", "not a decimal"))?;
+
+    let deviation = price::deviation_percent(&price, &close);
+    if deviation <= outlier_threshold_percent() {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "price {} deviates {}% from the close of {}",
+        payload.price,
+        decimal::round_half_up(deviation, decimal::AMOUNT_SCALE),
+        close
+    )))
+}
+
 async fn create_trade(
-    pool: Extension<Arc<SqlitePool>>,
+    Query(lock): Query<LockOverride>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
     Json(payload): Json<CreateTrade>,
-) -> Result<Json<i64>, StatusCode> {
-    let id = match trade::create_trade(&*pool, payload.into()).await {
+) -> Result<Response, ApiError> {
+    let date = payload.validate()?;
+    check_period_lock(&pool, &[date], &lock).await?;
+
+    payload.check_holding(&pool, date).await?;
+
+    let policy = outlier_policy();
+    let warning = if policy == OutlierPolicy::Off {
+        None
+    } else {
+        price_outlier_warning(&pool, &payload).await?
+    };
+    if let Some(warning) = warning.as_ref() {
+        if policy == OutlierPolicy::Reject && !payload.force {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "price_outlier",
+                format!("{}, send force to store it anyway", warning),
+            ));
+        }
+    }
+
+    let ticker = payload.ticker.clone();
+    let id = match trade::create_trade(&pool, payload.into()).await {
         Ok(res) => res,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => return Err(ApiError::internal(e)),
     };
+    invalidate_portfolio_from(&pool, cache.as_ref(), &ticker, date).await;
 
-    Ok(Json(id))
+    Ok(match warning {
+        Some(warning) => (
+            [(header::WARNING, format!("199 - \"{}\"", warning))],
+            Json(id),
+        )
+            .into_response(),
+        None => Json(id).into_response(),
+    })
 }
 
 #[derive(serde::Serialize)]
@@ -89,8 +773,11 @@ struct ListTradesResponse {
     ticker: String,
     date: String,
     r#type: String,
-    amount: i64,
+    amount: String,
     price: String,
+    fee: Option<String>,
+    base_currency_cost: Option<BigDecimal>,
+    tags: Vec<String>,
 }
 
 impl From<trade::ListTrade> for ListTradesResponse {
@@ -102,242 +789,4513 @@ impl From<trade::ListTrade> for ListTradesResponse {
             r#type: list_trade.r#type,
             amount: list_trade.amount,
             price: list_trade.price,
+            fee: list_trade.fee,
+            base_currency_cost: None,
+            tags: Vec::new(),
         }
     }
 }
 
-async fn list_trades(
-    pool: Extension<Arc<SqlitePool>>,
-) -> Result<Json<Vec<ListTradesResponse>>, StatusCode> {
-    let list_of_trades: Vec<ListTradesResponse> = match trade::list_trades(&*pool).await {
-        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-    };
+impl From<trade::MissingFxRate> for ApiError {
+    fn from(e: trade::MissingFxRate) -> Self {
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "missing_fx_rate",
+            format!(
+                "no {} rate on or before {} to convert {}, fetch it with /admin/fx/backfill",
+                e.currency, e.date, e.ticker
+            ),
+        )
+        .with_detail("currency", e.currency)
+        .with_detail("date", e.date)
+    }
+}
 
-    Ok(Json(list_of_trades))
+async fn trade_cost_converter(pool: &DbPool) -> Result<trade::TradeCostConverter, ApiError> {
+    trade::TradeCostConverter::load(
+        pool,
+        &base_currency(),
+        ticker_currencies(),
+        DEFAULT_QUOTE_CURRENCY,
+    )
+    .await
+    .map_err(ApiError::internal)
 }
 
-async fn delete_trade(Path(trade_id): Path<i64>, pool: Extension<Arc<SqlitePool>>) -> StatusCode {
-    match trade::delete_trade(&*pool, trade_id).await {
-        Ok(deleted_count) => {
-            if deleted_count == 1 {
-                StatusCode::OK
-            } else {
-                StatusCode::NOT_FOUND
-            }
-        }
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
-    }
+/// Trades of the portfolio of the trades carrying `tag`, or of every trade,
+/// with their prices and fees in the base currency the portfolio is valued in.
+async fn trades_in_base_currency(
+    pool: &DbPool,
+    tag: Option<&str>,
+) -> Result<Vec<trade::TradeForCalculation>, ApiError> {
+    let trades = trade::list_trades_for_calculation(pool, None, tag)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(trade_cost_converter(pool).await?.convert(trades)?)
 }
 
-#[derive(Deserialize)]
-struct AlphaVantageDailyPriceResponse {
-    #[serde(rename(deserialize = "4. close"))]
-    price: String,
+async fn list_trades(
+    Query(filter): Query<TagFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<ListTradesResponse>>, ApiError> {
+    Ok(Json(list_trade_responses(&pool, &filter).await?))
 }
 
-#[derive(Deserialize)]
-struct AlphaVantagePriceApiResponse {
-    #[serde(rename(deserialize = "Time Series (Daily)"))]
-    time_series: HashMap<String, AlphaVantageDailyPriceResponse>,
+async fn list_trade_responses(
+    pool: &DbPool,
+    filter: &TagFilter,
+) -> Result<Vec<ListTradesResponse>, ApiError> {
+    let mut tags = match trade::list_trade_tags(pool).await {
+        Ok(res) => res,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let converter = trade_cost_converter(pool).await?;
+    let list_of_trades: Vec<ListTradesResponse> = match trade::list_trades(pool).await {
+        Ok(res) => res
+            .into_iter()
+            .map(|list_trade| {
+                let base_currency_cost = converter.cost(&list_trade);
+                let mut response = ListTradesResponse::from(list_trade);
+                response.base_currency_cost = base_currency_cost;
+                response.tags = tags.remove(&response.id).unwrap_or_default();
+                response
+            })
+            .filter(|response| {
+                filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| response.tags.contains(tag))
+            })
+            .collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(list_of_trades)
 }
 
 #[derive(serde::Serialize)]
-struct LastPriceDate {
-    date: String,
+struct TradeStatsResponse {
+    base_currency: String,
+    trades: usize,
+    total_buy_cost: BigDecimal,
+    total_sell_cost: BigDecimal,
+    trades_without_fx_rate: usize,
 }
 
-async fn update_prices(pool: Extension<Arc<SqlitePool>>) -> StatusCode {
-    for ticker in TICKERS {
-        let mut api_output_size = "full";
-        let last_ticker_date = sqlx::query_as!(
-            LastPriceDate,
-            r#"
-        SELECT date from prices where ticker = ?1 ORDER BY date desc limit 1
-        "#,
-            ticker,
-        )
-        .fetch_optional(&*pool.0)
-        .await
-        .unwrap();
-        let last_ticker_date = match last_ticker_date {
-            Some(last_price_date) => {
-                NaiveDate::parse_from_str(&last_price_date.date, "%Y-%m-%d").unwrap()
-            }
-            None => chrono::naive::MIN_DATE,
-        };
-
-        if last_ticker_date > Utc::today().naive_utc() + Duration::days(-100) {
-            api_output_size = "compact";
+/// Totals of the trade list in the base currency. Trades without a rate for
+/// their date are counted but left out of the totals.
+async fn trade_stats(
+    Query(filter): Query<TagFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<TradeStatsResponse>, ApiError> {
+    let list_of_trades = list_trade_responses(&pool, &filter).await?;
+    let mut stats = TradeStatsResponse {
+        base_currency: base_currency(),
+        trades: list_of_trades.len(),
+        total_buy_cost: BigDecimal::from(0),
+        total_sell_cost: BigDecimal::from(0),
+        trades_without_fx_rate: 0,
+    };
+    for list_trade in list_of_trades {
+        match (list_trade.r#type.as_str(), list_trade.base_currency_cost) {
+            ("BUY", Some(cost)) => stats.total_buy_cost += cost,
+            (_, Some(cost)) => stats.total_sell_cost += cost,
+            (_, None) => stats.trades_without_fx_rate += 1,
         }
+    }
+    stats.total_buy_cost = decimal::round_half_up(stats.total_buy_cost, decimal::AMOUNT_SCALE);
+    stats.total_sell_cost = decimal::round_half_up(stats.total_sell_cost, decimal::AMOUNT_SCALE);
+    Ok(Json(stats))
+}
 
-        let alpha_adavantage_key = match env::var("ALPHA_VANTAGE_API_KEY") {
-            Ok(key) => key,
-            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-        };
-        let url = format!("https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&symbol={}&apikey={}&outputsize={}", ticker, alpha_adavantage_key, api_output_size);
-        let resp = reqwest::get(url)
-            .await
-            .unwrap()
-            .json::<AlphaVantagePriceApiResponse>()
-            .await
-            .unwrap();
-        let prices_to_insert = resp.time_series.iter().filter(|price| {
-            let date = NaiveDate::parse_from_str(price.0, "%Y-%m-%d").unwrap();
-            date > last_ticker_date
-        });
-        for (key, val) in prices_to_insert {
-            sqlx::query!(
-                r#"
-            INSERT INTO prices ( ticker, date, price )
-            VALUES ( ?1, ?2, ?3 )
-            "#,
-                ticker,
-                key,
-                val.price
-            )
-            .execute(&*pool.0)
-            .await
-            .unwrap();
+async fn set_trade_tags(
+    Path(trade_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(tags): Json<Vec<String>>,
+) -> Result<StatusCode, ApiError> {
+    let updated = trade::set_trade_tags(&pool, trade_id, &tags).await;
+    cache.clear().await;
+    match updated {
+        Ok(true) => Ok(StatusCode::OK),
+        Ok(false) => Err(ApiError::not_found(format!("trade {} not found", trade_id))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+impl From<trade::EditTradeError> for ApiError {
+    fn from(e: trade::EditTradeError) -> Self {
+        match e {
+            trade::EditTradeError::NotFound => ApiError::not_found("trade not found"),
+            trade::EditTradeError::Invalid => {
+                ApiError::bad_request("the lots or trades do not describe a valid split or merge")
+            }
+            trade::EditTradeError::Reinvestment => {
+                ApiError::conflict("the trade reinvests a dividend, change the dividend instead")
+            }
+            trade::EditTradeError::Database(e) => ApiError::internal(e),
         }
     }
-    StatusCode::OK
 }
 
-#[derive(serde::Serialize)]
-struct ListPricesResponse {
-    id: i64,
-    ticker: String,
-    date: String,
-    price: String,
+#[derive(Deserialize)]
+struct Lot {
+    amount: BigDecimal,
+    price: BigDecimal,
 }
 
-async fn list_prices(
-    pool: Extension<Arc<SqlitePool>>,
-) -> Result<Json<Vec<ListPricesResponse>>, StatusCode> {
-    let list_of_prices = match sqlx::query_as!(
-        ListPricesResponse,
-        r#"
-        SELECT id as "id!", ticker, date, price FROM prices ORDER by date asc
-        "#,
-    )
-    .fetch_all(&*pool.0)
-    .await
-    {
+async fn split_trade(
+    Query(lock): Query<LockOverride>,
+    Path(trade_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(lots): Json<Vec<Lot>>,
+) -> Result<Json<Vec<i64>>, ApiError> {
+    check_trades_period_lock(&pool, &[trade_id], &lock).await?;
+    let lots: Vec<trade::Lot> = lots
+        .into_iter()
+        .map(|lot| trade::Lot {
+            amount: lot.amount,
+            price: lot.price.to_string(),
+        })
+        .collect();
+    let ids = match trade::split_trade(&pool, trade_id, &lots).await {
         Ok(res) => res,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => return Err(e.into()),
     };
+    invalidate_portfolio(&pool, cache.as_ref()).await;
 
-    Ok(Json(list_of_prices))
+    Ok(Json(ids))
 }
 
-async fn delete_prices(pool: Extension<Arc<SqlitePool>>) -> StatusCode {
-    return match sqlx::query!(
-        r#"
-        DELETE FROM prices
-        "#
-    )
-    .execute(&*pool.0)
-    .await
-    {
-        Ok(_) => StatusCode::OK,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
-    };
-}
-
-#[derive(Clone)]
-pub struct DailyPrice {
-    date: NaiveDate,
-    price: BigDecimal,
-    ticker: String,
+#[derive(Deserialize)]
+struct AmendTradeDate {
+    date: String,
 }
 
 #[derive(serde::Serialize)]
-pub struct Portfolio {
-    date: NaiveDate,
-    amount_in_euros: BigDecimal,
+struct AmendedTradeDateResponse {
+    trade_id: i64,
+    old_date: NaiveDate,
+    new_date: NaiveDate,
+    /// Valuations and reports from this date on, the earlier of both dates,
+    /// change.
+    affected_from: NaiveDate,
+    /// Archived reports that matched the data before the amendment and no
+    /// longer do.
+    stale_archived_reports: Vec<i64>,
 }
 
-async fn build_porfolio(
-    prices: Vec<DailyPrice>,
-    trades: Vec<trade::TradeForCalculation>,
-) -> Vec<Portfolio> {
-    let mut portfolio: Vec<Portfolio> = Vec::new();
-    let mut portfolio_boot_date = trades[0].date;
-    let last_price_date = prices[prices.len() - 1].date;
-    let mut portfolio_amount_in_units = 0;
+/// Moves a trade to another date. Portfolio series and reports are computed
+/// from the trades on every request, so dropping the cached responses is what
+/// recalculates them.
+async fn amend_trade_date(
+    Query(lock): Query<LockOverride>,
+    Path(trade_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<AmendTradeDate>,
+) -> Result<Json<AmendedTradeDateResponse>, ApiError> {
+    let new_date = NaiveDate::parse_from_str(&payload.date, "%Y-%m-%d").map_err(|_| {
+        ApiError::invalid_field("date", format!("{} is not a YYYY-MM-DD date", payload.date))
+    })?;
+    check_trades_period_lock(&pool, &[trade_id], &lock).await?;
+    check_period_lock(&pool, &[new_date], &lock).await?;
 
-    while portfolio_boot_date <= last_price_date {
-        portfolio_amount_in_units += match trades
-            .iter()
-            .filter(|trade| trade.date == portfolio_boot_date)
-            .next()
-        {
-            Some(trade) => trade.amount,
-            None => 0,
-        };
-        let price_of_the_day = prices
-            .iter()
-            .filter(|price| price.date == portfolio_boot_date)
-            .next();
-        match price_of_the_day {
-            Some(price) => portfolio.push(Portfolio {
-                date: portfolio_boot_date.clone(),
-                amount_in_euros: price.price.clone() * BigDecimal::from(portfolio_amount_in_units),
-            }),
-            None => (),
-        }
-        portfolio_boot_date = portfolio_boot_date.succ();
-    }
-    portfolio
+    let inputs_hash = || async {
+        let trades = trade::list_trades_for_calculation(&pool, None, None)
+            .await
+            .map_err(ApiError::internal)?;
+        let dividends = received_dividends(&pool, None).await?;
+        Ok::<_, ApiError>(archive::inputs_hash(&trades, &dividends))
+    };
+    let hash_before = inputs_hash().await?;
+    let old_date = trade::amend_trade_date(&pool, trade_id, new_date).await?;
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    let hash_after = inputs_hash().await?;
+
+    let stale_archived_reports = match archive::list_archived_reports(&pool).await {
+        Ok(archived_reports) => archived_reports
+            .into_iter()
+            .filter(|archived_report| {
+                archived_report.inputs_hash == hash_before && hash_before != hash_after
+            })
+            .map(|archived_report| archived_report.id)
+            .collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(AmendedTradeDateResponse {
+        trade_id,
+        old_date,
+        new_date,
+        affected_from: old_date.min(new_date),
+        stale_archived_reports,
+    }))
 }
 
-async fn generate_portfolio(
-    pool: Extension<Arc<SqlitePool>>,
-) -> Result<Json<HashMap<String, Vec<Portfolio>>>, StatusCode> {
-    let trades = match trade::list_trades_for_calculation(&*pool).await {
-        Ok(trades) => trades,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+#[derive(Deserialize)]
+struct MergeTrades {
+    trade_ids: Vec<i64>,
+}
+
+async fn merge_trades(
+    Query(lock): Query<LockOverride>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<MergeTrades>,
+) -> Result<Json<i64>, ApiError> {
+    check_trades_period_lock(&pool, &payload.trade_ids, &lock).await?;
+    let id = match trade::merge_trades(&pool, &payload.trade_ids).await {
+        Ok(res) => res,
+        Err(e) => return Err(e.into()),
     };
+    invalidate_portfolio(&pool, cache.as_ref()).await;
 
-    let prices: Vec<DailyPrice> = match sqlx::query!(
-        r#"
-        SELECT date, price, ticker FROM prices ORDER BY date asc
+    Ok(Json(id))
+}
+
+#[derive(serde::Deserialize)]
+struct QuickTrade {
+    ticker: String,
+    r#type: Option<String>,
+    cash: Option<String>,
+    units: Option<BigDecimal>,
+}
+
+async fn quick_add_trade(
+    Query(lock): Query<LockOverride>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<QuickTrade>,
+) -> Result<Json<ListTradesResponse>, ApiError> {
+    let today = Utc::today().naive_utc();
+    check_period_lock(&pool, &[today], &lock).await?;
+    let latest_price = match price::get_latest_price(&pool, &payload.ticker).await {
+        Ok(Some(latest_price)) => latest_price,
+        Ok(None) => {
+            return Err(ApiError::not_found(format!(
+                "no price stored for {}",
+                payload.ticker
+            )))
+        }
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    let price = BigDecimal::from_str(&latest_price).map_err(ApiError::internal)?;
+    if price <= BigDecimal::from(0) {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid_price",
+            format!("the latest price of {} is {}", payload.ticker, latest_price),
+        ));
+    }
+    let amount = match (payload.units, payload.cash) {
+        (Some(units), None) => units,
+        (None, Some(cash)) => {
+            let cash = BigDecimal::from_str(&cash)
+                .map_err(|_| ApiError::invalid_field("cash", "not a decimal"))?;
+            (cash / price).with_scale(decimal::UNITS_SCALE).normalized()
+        }
+        _ => return Err(ApiError::bad_request("send either units or cash")),
+    };
+    if amount <= BigDecimal::from(0) {
+        return Err(ApiError::bad_request("the trade would be of no units"));
+    }
+
+    let create_trade = CreateTrade {
+        ticker: payload.ticker.clone(),
+        date: today.format("%Y-%m-%d").to_string(),
+        r#type: payload.r#type.unwrap_or_else(|| "BUY".to_string()),
+        amount,
+        price: latest_price,
+        fee: None,
+        tags: Vec::new(),
+        force: false,
+    };
+    create_trade.validate()?;
+    create_trade.check_holding(&pool, today).await?;
+
+    let id = match trade::create_trade(&pool, create_trade.into()).await {
+        Ok(res) => res,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    invalidate_portfolio_from(&pool, cache.as_ref(), &payload.ticker, today).await;
+
+    match trade::get_trade(&pool, id).await {
+        Ok(Some(trade)) => Ok(Json(trade.into())),
+        Ok(None) => Err(ApiError::internal(format!("trade {} vanished", id))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct OperationResponse {
+    /// Id to undo the deletion with at `/admin/undo/:operation_id`.
+    operation_id: i64,
+}
+
+async fn delete_trade(
+    Query(lock): Query<LockOverride>,
+    Path(trade_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Json<OperationResponse>, ApiError> {
+    check_trades_period_lock(&pool, &[trade_id], &lock).await?;
+    let trade = trade::get_trade(&pool, trade_id)
+        .await
+        .map_err(ApiError::internal)?;
+    let deleted = trade::delete_trade(&pool, trade_id).await;
+    match trade {
+        Some(trade) => match NaiveDate::parse_from_str(&trade.date, "%Y-%m-%d") {
+            Ok(date) => invalidate_portfolio_from(&pool, cache.as_ref(), &trade.ticker, date).await,
+            Err(_) => invalidate_portfolio(&pool, cache.as_ref()).await,
+        },
+        None => cache.clear().await,
+    }
+    match deleted {
+        Ok(operation_id) => Ok(Json(OperationResponse { operation_id })),
+        Err(trade::EditTradeError::NotFound) => {
+            Err(ApiError::not_found(format!("trade {} not found", trade_id)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SaveJournalEntry {
+    ticker: Option<String>,
+    date: String,
+    text: String,
+}
+
+impl From<SaveJournalEntry> for journal::SaveJournalEntry {
+    fn from(entry: SaveJournalEntry) -> Self {
+        journal::SaveJournalEntry {
+            ticker: entry.ticker,
+            date: entry.date,
+            text: entry.text,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JournalEntryResponse {
+    id: i64,
+    ticker: Option<String>,
+    date: String,
+    text: String,
+}
+
+impl From<journal::JournalEntry> for JournalEntryResponse {
+    fn from(entry: journal::JournalEntry) -> Self {
+        Self {
+            id: entry.id,
+            ticker: entry.ticker,
+            date: entry.date,
+            text: entry.text,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JournalFilter {
+    ticker: Option<String>,
+}
+
+async fn create_journal_entry(
+    pool: Extension<Arc<DbPool>>,
+    Json(payload): Json<SaveJournalEntry>,
+) -> Result<Json<i64>, ApiError> {
+    let id = match journal::create_journal_entry(&pool, payload.into()).await {
+        Ok(res) => res,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(id))
+}
+
+async fn list_journal_entries(
+    Query(filter): Query<JournalFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<JournalEntryResponse>>, ApiError> {
+    let entries = match journal::list_journal_entries(&pool, filter.ticker).await {
+        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(entries))
+}
+
+async fn get_journal_entry(
+    Path(entry_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<JournalEntryResponse>, ApiError> {
+    match journal::get_journal_entry(&pool, entry_id).await {
+        Ok(Some(entry)) => Ok(Json(entry.into())),
+        Ok(None) => Err(ApiError::not_found(format!(
+            "journal entry {} not found",
+            entry_id
+        ))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+async fn update_journal_entry(
+    Path(entry_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    Json(payload): Json<SaveJournalEntry>,
+) -> Result<StatusCode, ApiError> {
+    match journal::update_journal_entry(&pool, entry_id, payload.into()).await {
+        Ok(updated_count) => {
+            if updated_count == 1 {
+                Ok(StatusCode::OK)
+            } else {
+                Err(ApiError::not_found(format!(
+                    "journal entry {} not found",
+                    entry_id
+                )))
+            }
+        }
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+async fn delete_journal_entry(
+    Path(entry_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<StatusCode, ApiError> {
+    match journal::delete_journal_entry(&pool, entry_id).await {
+        Ok(deleted_count) => {
+            if deleted_count == 1 {
+                Ok(StatusCode::OK)
+            } else {
+                Err(ApiError::not_found(format!(
+                    "journal entry {} not found",
+                    entry_id
+                )))
+            }
+        }
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct SaveDividend {
+    ticker: String,
+    ex_date: NaiveDate,
+    pay_date: NaiveDate,
+    gross_amount: BigDecimal,
+    tax: BigDecimal,
+    /// Currency the amounts are paid in, when not the base currency.
+    currency: Option<String>,
+    #[serde(default)]
+    reinvested: bool,
+}
+
+impl SaveDividend {
+    /// Checks the currency. A reinvested dividend buys units at the price of
+    /// the ticker, so it must be paid in the currency the ticker is quoted in.
+    fn validate(&self) -> Result<(), ApiError> {
+        let currency = match &self.currency {
+            Some(currency) => {
+                if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+                    return Err(ApiError::invalid_field(
+                        "currency",
+                        format!("{} is not a three letter currency code", currency),
+                    ));
+                }
+                currency.clone()
+            }
+            None => base_currency(),
+        };
+        let quote_currency = ticker_currency(&self.ticker);
+        if self.reinvested && currency != quote_currency {
+            return Err(ApiError::invalid_field(
+                "currency",
+                format!(
+                    "a reinvested dividend must be paid in {}, the currency {} is quoted in",
+                    quote_currency, self.ticker
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl From<SaveDividend> for dividend::SaveDividend {
+    fn from(dividend: SaveDividend) -> Self {
+        dividend::SaveDividend {
+            ticker: dividend.ticker,
+            ex_date: dividend.ex_date.to_string(),
+            pay_date: dividend.pay_date.to_string(),
+            gross_amount: dividend.gross_amount.to_string(),
+            tax: dividend.tax.to_string(),
+            currency: dividend.currency,
+            reinvested: dividend.reinvested,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DividendResponse {
+    id: i64,
+    ticker: String,
+    ex_date: String,
+    pay_date: String,
+    gross_amount: String,
+    tax: String,
+    /// `None` for the base currency.
+    currency: Option<String>,
+    reinvested: bool,
+    reinvestment_trade_id: Option<i64>,
+}
+
+impl From<dividend::Dividend> for DividendResponse {
+    fn from(dividend: dividend::Dividend) -> Self {
+        Self {
+            id: dividend.id,
+            ticker: dividend.ticker,
+            ex_date: dividend.ex_date,
+            pay_date: dividend.pay_date,
+            gross_amount: dividend.gross_amount,
+            tax: dividend.tax,
+            currency: dividend.currency,
+            reinvested: dividend.reinvested,
+            reinvestment_trade_id: dividend.reinvestment_trade_id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DividendFilter {
+    ticker: Option<String>,
+}
+
+impl From<dividend::SaveDividendError> for ApiError {
+    fn from(e: dividend::SaveDividendError) -> Self {
+        match e {
+            dividend::SaveDividendError::NoPriceToReinvest => {
+                ApiError::bad_request("no price stored to reinvest the dividend at")
+            }
+            dividend::SaveDividendError::InvalidField { field, value } => {
+                ApiError::invalid_field(field, format!("{} is not valid", value))
+            }
+            dividend::SaveDividendError::InvalidPrice { date, price } => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_price",
+                format!("cannot reinvest at the price {} stored on {}", price, date),
+            ),
+            dividend::SaveDividendError::Database(e) => ApiError::internal(e),
+        }
+    }
+}
+
+async fn create_dividend(
+    Query(lock): Query<LockOverride>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<SaveDividend>,
+) -> Result<Json<i64>, ApiError> {
+    payload.validate()?;
+    if payload.reinvested {
+        check_period_lock(&pool, &[payload.pay_date], &lock).await?;
+    }
+    let id = match dividend::create_dividend(&pool, payload.into()).await {
+        Ok(res) => res,
+        Err(e) => return Err(e.into()),
+    };
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+
+    Ok(Json(id))
+}
+
+async fn list_dividends(
+    Query(filter): Query<DividendFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<DividendResponse>>, ApiError> {
+    let dividends = match dividend::list_dividends(&pool, filter.ticker).await {
+        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(dividends))
+}
+
+async fn get_dividend(
+    Path(dividend_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<DividendResponse>, ApiError> {
+    match dividend::get_dividend(&pool, dividend_id).await {
+        Ok(Some(dividend)) => Ok(Json(dividend.into())),
+        Ok(None) => Err(dividend_not_found(dividend_id)),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+fn dividend_not_found(dividend_id: i64) -> ApiError {
+    ApiError::not_found(format!("dividend {} not found", dividend_id))
+}
+
+async fn update_dividend(
+    Query(lock): Query<LockOverride>,
+    Path(dividend_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<SaveDividend>,
+) -> Result<StatusCode, ApiError> {
+    payload.validate()?;
+    let mut reinvestment_dates = Vec::from_iter(reinvestment_trade_date(&pool, dividend_id).await?);
+    if payload.reinvested {
+        reinvestment_dates.push(payload.pay_date);
+    }
+    check_period_lock(&pool, &reinvestment_dates, &lock).await?;
+    let updated = dividend::update_dividend(&pool, dividend_id, payload.into()).await;
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    match updated {
+        Ok(updated_count) => {
+            if updated_count == 1 {
+                Ok(StatusCode::OK)
+            } else {
+                Err(dividend_not_found(dividend_id))
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn delete_dividend(
+    Query(lock): Query<LockOverride>,
+    Path(dividend_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<StatusCode, ApiError> {
+    let reinvestment_dates = Vec::from_iter(reinvestment_trade_date(&pool, dividend_id).await?);
+    check_period_lock(&pool, &reinvestment_dates, &lock).await?;
+    let deleted = dividend::delete_dividend(&pool, dividend_id).await;
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    match deleted {
+        Ok(deleted_count) => {
+            if deleted_count == 1 {
+                Ok(StatusCode::OK)
+            } else {
+                Err(dividend_not_found(dividend_id))
+            }
+        }
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct RenameTicker {
+    from: String,
+    to: String,
+    /// Keeps fetching prices under the old symbol.
+    #[serde(default)]
+    keep_alias: bool,
+}
+
+/// Renames a ticker across trades, prices and everything else that refers to
+/// it. The configured tickers have to be updated to the new symbol as well.
+async fn rename_ticker(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<RenameTicker>,
+) -> Result<StatusCode, ApiError> {
+    let renamed =
+        ticker::rename_ticker(&pool, &payload.from, &payload.to, payload.keep_alias).await;
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    match renamed {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(ticker::RenameTickerError::NotFound) => Err(ApiError::not_found(format!(
+            "nothing refers to {}",
+            payload.from
+        ))),
+        Err(ticker::RenameTickerError::Conflict) => Err(ApiError::conflict(format!(
+            "{} is already in use",
+            payload.to
+        ))),
+        Err(ticker::RenameTickerError::Database(e)) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct TickerSearchQuery {
+    q: String,
+}
+
+#[derive(serde::Serialize)]
+struct TickerSearchResponse {
+    symbol: String,
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    region: String,
+    currency: String,
+    match_score: String,
+}
+
+impl From<alpha_vantage::SymbolMatch> for TickerSearchResponse {
+    fn from(symbol: alpha_vantage::SymbolMatch) -> Self {
+        TickerSearchResponse {
+            symbol: symbol.symbol,
+            name: symbol.name,
+            kind: symbol.kind,
+            region: symbol.region,
+            currency: symbol.currency,
+            match_score: symbol.match_score,
+        }
+    }
+}
+
+/// Looks symbols up by name or symbol on Alpha Vantage, to find the one, with
+/// its exchange suffix, to register a ticker or a trade under.
+async fn search_tickers(
+    Query(query): Query<TickerSearchQuery>,
+) -> Result<Json<Vec<TickerSearchResponse>>, ApiError> {
+    let keywords = query.q.trim();
+    if keywords.is_empty() {
+        return Err(ApiError::invalid_field("q", "must not be empty"));
+    }
+    let matches = alpha_vantage::search_symbols(&alpha_vantage_api_key()?, keywords)
+        .await
+        .map_err(|e| ApiError::bad_gateway(format!("alpha_vantage: {}", e)))?;
+    Ok(Json(matches.into_iter().map(|x| x.into()).collect()))
+}
+
+/// Providers prices can be fetched from.
+const PRICE_PROVIDERS: &[&str] = &[ticker::DEFAULT_PRICE_PROVIDER, "stooq", "coingecko", "mock"];
+
+/// Providers tried in order, with the same symbol, when the one of a ticker
+/// fails or has no new prices.
+const PRICE_FALLBACK_PROVIDERS: &[&str] = &["stooq"];
+
+/// Providers from the most to the least trusted, set with `PRICE_TRUST_ORDER`
+/// as a comma separated list, `PRICE_PROVIDERS` by default. When providers
+/// fetched different prices for a date, the one of the most trusted is stored.
+fn price_trust_order() -> Vec<String> {
+    match &config().price_trust_order {
+        Some(order) => order.clone(),
+        None => PRICE_PROVIDERS
+            .iter()
+            .map(|provider| provider.to_string())
+            .collect(),
+    }
+}
+
+/// How far back a new price source must have prices to be accepted.
+const PRICE_SOURCE_VALIDATION_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+struct UpdatePriceSource {
+    provider: Option<String>,
+    symbol: String,
+    /// Replaces the stored prices with the full history of the new source.
+    #[serde(default)]
+    backfill: bool,
+}
+
+#[derive(serde::Serialize)]
+struct PriceSourceResponse {
+    ticker: String,
+    provider: String,
+    symbol: String,
+    /// Currency and exchange of the symbol, when the provider tells them.
+    currency: Option<String>,
+    exchange: Option<String>,
+    backfilled: usize,
+}
+
+/// Currency and exchange of the instrument `source` stands for, from the
+/// provider's symbol search. Only Alpha Vantage describes its symbols; a
+/// failed search is logged and leaves the instrument unknown.
+async fn detect_instrument(source: &ticker::PriceSource) -> Option<ticker::Instrument> {
+    if source.provider != "alpha_vantage" {
+        return None;
+    }
+    let api_key = alpha_vantage_api_key().ok()?;
+    match alpha_vantage::search_symbols(&api_key, &source.symbol).await {
+        Ok(matches) => matches
+            .into_iter()
+            .find(|symbol| symbol.symbol.eq_ignore_ascii_case(&source.symbol))
+            .map(|symbol| ticker::Instrument {
+                currency: symbol.currency,
+                exchange: Some(symbol.region),
+            }),
+        Err(e) => {
+            println!("Instrument of {} not detected: {}", source.symbol, e);
+            None
+        }
+    }
+}
+
+/// Checks that prices of `instrument` are in the currency `ticker` is quoted
+/// in.
+fn check_instrument_currency(ticker: &str, instrument: &ticker::Instrument) -> Result<(), String> {
+    let currency = ticker_currency(ticker);
+    if instrument.currency.eq_ignore_ascii_case(currency) {
+        Ok(())
+    } else {
+        Err(format!(
+            "prices are in {}, {} is quoted in {}",
+            instrument.currency, ticker, currency
+        ))
+    }
+}
+
+/// Points a ticker to another provider or symbol. The new source is only
+/// saved once a fetch from it succeeds and, when the provider tells the
+/// currency of the symbol, it is the one the ticker is quoted in.
+async fn update_price_source(
+    Path(ticker): Path<String>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<UpdatePriceSource>,
+) -> Result<Json<PriceSourceResponse>, ApiError> {
+    if !tracked_tickers().contains(&ticker.as_str()) {
+        return Err(ApiError::not_found(format!("{} is not tracked", ticker)));
+    }
+    let source = ticker::PriceSource {
+        provider: payload
+            .provider
+            .unwrap_or_else(|| ticker::DEFAULT_PRICE_PROVIDER.to_string()),
+        symbol: payload.symbol,
+    };
+
+    let since = if payload.backfill {
+        chrono::naive::MIN_DATE
+    } else {
+        Utc::today().naive_utc() + Duration::days(-PRICE_SOURCE_VALIDATION_DAYS)
+    };
+    let prices = fetch_daily_prices(&ticker, &source, since).await?;
+    if prices.is_empty() {
+        return Err(ApiError::bad_gateway(format!(
+            "{} has no recent prices for {}",
+            source.provider, source.symbol
+        )));
+    }
+    let instrument = detect_instrument(&source).await;
+    if let Some(instrument) = &instrument {
+        check_instrument_currency(&ticker, instrument)
+            .map_err(|message| ApiError::invalid_field("symbol", message))?;
+    }
+
+    let mut tx = begin(&pool).await?;
+    ticker::set_price_source(&mut tx, &ticker, &source, instrument.as_ref())
+        .await
+        .map_err(ApiError::internal)?;
+
+    let mut backfilled = 0;
+    if payload.backfill {
+        // The backfilled prices replace the stored ones whatever the trust
+        // order.
+        let trust_order: Vec<String> = std::iter::once(source.provider.clone())
+            .chain(price_trust_order())
+            .collect();
+        for daily_price in prices {
+            price::upsert_price(
+                &mut tx,
+                &ticker,
+                &daily_price,
+                &source.provider,
+                &trust_order,
+            )
+            .await
+            .map_err(ApiError::internal)?;
+            backfilled += 1;
+        }
+    }
+    commit(tx).await?;
+    if payload.backfill {
+        invalidate_portfolio(&pool, cache.as_ref()).await;
+    }
+
+    Ok(Json(PriceSourceResponse {
+        ticker,
+        provider: source.provider,
+        symbol: source.symbol,
+        currency: instrument
+            .as_ref()
+            .map(|instrument| instrument.currency.clone()),
+        exchange: instrument.and_then(|instrument| instrument.exchange),
+        backfilled,
+    }))
+}
+
+#[derive(Deserialize)]
+struct CreateSplit {
+    ticker: String,
+    date: NaiveDate,
+    ratio: BigDecimal,
+}
+
+async fn create_split(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<CreateSplit>,
+) -> Result<Json<i64>, ApiError> {
+    if payload.ratio <= BigDecimal::from(0) {
+        return Err(ApiError::invalid_field("ratio", "must be positive"));
+    }
+    let split = corporate_action::CreateSplit {
+        ticker: payload.ticker,
+        date: payload.date.to_string(),
+        ratio: payload.ratio.to_string(),
+    };
+    let id = match corporate_action::create_split(pool.as_ref(), split).await {
+        Ok(res) => res,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+
+    Ok(Json(id))
+}
+
+#[derive(serde::Serialize)]
+struct CorporateActionResponse {
+    id: i64,
+    ticker: String,
+    date: String,
+    r#type: String,
+    ratio: String,
+}
+
+impl From<corporate_action::CorporateAction> for CorporateActionResponse {
+    fn from(action: corporate_action::CorporateAction) -> Self {
+        Self {
+            id: action.id,
+            ticker: action.ticker,
+            date: action.date,
+            r#type: action.r#type,
+            ratio: action.ratio,
+        }
+    }
+}
+
+async fn list_corporate_actions(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<CorporateActionResponse>>, ApiError> {
+    match corporate_action::list_corporate_actions(&pool).await {
+        Ok(res) => Ok(Json(res.into_iter().map(|x| x.into()).collect())),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum CashMovementType {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Deserialize)]
+struct CreateCashMovement {
+    date: NaiveDate,
+    r#type: CashMovementType,
+    amount: BigDecimal,
+}
+
+impl From<CreateCashMovement> for cash::CreateCashMovement {
+    fn from(movement: CreateCashMovement) -> Self {
+        cash::CreateCashMovement {
+            date: movement.date.to_string(),
+            r#type: match movement.r#type {
+                CashMovementType::Deposit => "DEPOSIT".to_string(),
+                CashMovementType::Withdrawal => "WITHDRAWAL".to_string(),
+            },
+            amount: movement.amount.to_string(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CashMovementResponse {
+    id: i64,
+    date: String,
+    r#type: String,
+    amount: String,
+}
+
+impl From<cash::CashMovement> for CashMovementResponse {
+    fn from(movement: cash::CashMovement) -> Self {
+        Self {
+            id: movement.id,
+            date: movement.date,
+            r#type: movement.r#type,
+            amount: movement.amount,
+        }
+    }
+}
+
+async fn create_cash_movement(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<CreateCashMovement>,
+) -> Result<Json<i64>, ApiError> {
+    let id = match cash::create_cash_movement(&pool, payload.into()).await {
+        Ok(res) => res,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    cache.clear().await;
+
+    Ok(Json(id))
+}
+
+async fn list_cash_movements(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<CashMovementResponse>>, ApiError> {
+    let movements = match cash::list_cash_movements(&pool).await {
+        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(movements))
+}
+
+async fn delete_cash_movement(
+    Path(movement_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = cash::delete_cash_movement(&pool, movement_id).await;
+    cache.clear().await;
+    match deleted {
+        Ok(deleted_count) => {
+            if deleted_count == 1 {
+                Ok(StatusCode::OK)
+            } else {
+                Err(ApiError::not_found(format!(
+                    "cash movement {} not found",
+                    movement_id
+                )))
+            }
+        }
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+/// Cash flows from movements, trades and received dividends, sorted by date.
+async fn all_cash_flows(pool: &DbPool) -> Result<Vec<(NaiveDate, BigDecimal)>, ApiError> {
+    let movements = match cash::list_cash_movements(pool).await {
+        Ok(movements) => movements,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let trades = trades_in_base_currency(pool, None).await?;
+    let dividends = received_dividends(pool, None).await?;
+    Ok(cash::cash_flows(&movements, &trades, &dividends))
+}
+
+#[derive(serde::Serialize)]
+struct CashBalanceResponse {
+    date: NaiveDate,
+    balance: BigDecimal,
+}
+
+async fn get_cash_balance(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<CashBalanceResponse>, ApiError> {
+    let date = Utc::today().naive_utc();
+    let balance = cash::cash_balance(&all_cash_flows(&pool).await?, date);
+    Ok(Json(CashBalanceResponse {
+        date,
+        balance: decimal::round_half_up(balance, decimal::AMOUNT_SCALE),
+    }))
+}
+
+#[derive(serde::Serialize)]
+struct LastPriceDate {
+    date: String,
+}
+
+/// Provider called `name`, configured from the environment, for a ticker
+/// quoted in `currency`.
+fn price_provider(name: &str, currency: &str) -> Result<Box<dyn price::PriceProvider>, ApiError> {
+    match name {
+        "alpha_vantage" => Ok(Box::new(
+            price::AlphaVantagePriceProvider::new(alpha_vantage_api_key()?)
+                .adjusted(alpha_vantage_adjusted()),
+        )),
+        "stooq" => Ok(Box::new(price::StooqPriceProvider)),
+        "coingecko" => Ok(Box::new(price::CoinGeckoPriceProvider::new(currency))),
+        "mock" => Ok(Box::new(price::MockPriceProvider)),
+        _ => Err(ApiError::invalid_field(
+            "provider",
+            format!("{} is not one of {}", name, PRICE_PROVIDERS.join(", ")),
+        )),
+    }
+}
+
+/// Whether every price and FX rate comes from the mock providers, when
+/// `MOCK_PROVIDERS` is `true`, so the tracker runs offline and without API
+/// keys, like for development and demos.
+fn mock_providers() -> bool {
+    config().mock_providers
+}
+
+/// Where the prices of `ticker` are fetched from: the mock provider with
+/// `MOCK_PROVIDERS`, its configured source otherwise.
+async fn price_source(pool: &DbPool, ticker: &str) -> Result<ticker::PriceSource, sqlx::Error> {
+    if mock_providers() {
+        return Ok(ticker::PriceSource {
+            provider: "mock".to_string(),
+            symbol: ticker.to_string(),
+        });
+    }
+    ticker::get_price_source(pool, ticker).await
+}
+
+fn alpha_vantage_api_key() -> Result<String, ApiError> {
+    config().alpha_vantage_api_key.clone().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "not_configured",
+            "ALPHA_VANTAGE_API_KEY is not set",
+        )
+    })
+}
+
+/// Whether Alpha Vantage prices come with adjusted closes, unless
+/// `ALPHA_VANTAGE_ADJUSTED` is `false`. Stored prices keep the closes they were
+/// fetched with until backfilled.
+fn alpha_vantage_adjusted() -> bool {
+    config().alpha_vantage_adjusted
+}
+
+/// Daily closes of `ticker` from `source` after `since`, sorted by date.
+async fn fetch_daily_prices(
+    ticker: &str,
+    source: &ticker::PriceSource,
+    since: NaiveDate,
+) -> Result<Vec<price::DailyPrice>, ApiError> {
+    price_provider(&source.provider, ticker_currency(ticker))?
+        .fetch_daily(&source.symbol, since)
+        .await
+        .map_err(|e| ApiError::bad_gateway(format!("{}: {}", source.provider, e)))
+}
+
+/// Daily closes after `since` from the provider of `source` or, when it fails
+/// or has none, from the first fallback provider that has some, along with
+/// the name of the provider they came from. Failures are logged before falling
+/// back; the last one is returned when every provider fails. There is no
+/// fallback with `MOCK_PROVIDERS`, as they all need network access.
+async fn fetch_daily_prices_with_fallback(
+    ticker: &str,
+    source: &ticker::PriceSource,
+    since: NaiveDate,
+) -> Result<(String, Vec<price::DailyPrice>), ApiError> {
+    let providers = std::iter::once(source.provider.as_str()).chain(
+        PRICE_FALLBACK_PROVIDERS
+            .iter()
+            .copied()
+            .filter(|provider| *provider != source.provider)
+            .filter(|_| !mock_providers()),
+    );
+    let mut last_error = None;
+    let mut answered = false;
+    for provider in providers {
+        let provider_source = ticker::PriceSource {
+            provider: provider.to_string(),
+            symbol: source.symbol.clone(),
+        };
+        match fetch_daily_prices(ticker, &provider_source, since).await {
+            Ok(prices) if !prices.is_empty() => return Ok((provider.to_string(), prices)),
+            Ok(_) => answered = true,
+            Err(e) => {
+                println!("Prices of {} failed: {}", source.symbol, e.message);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if !answered => Err(e),
+        _ => Ok((source.provider.clone(), Vec::new())),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct TickerUpdateResponse {
+    ticker: String,
+    /// Provider the prices were fetched from, `None` when none answered.
+    source: Option<String>,
+    inserted: usize,
+    errors: Vec<String>,
+    /// Date of the first price stored, from which the portfolio changed.
+    #[serde(skip)]
+    changed_from: Option<NaiveDate>,
+}
+
+#[derive(serde::Serialize)]
+struct UpdatePricesResponse {
+    tickers: Vec<TickerUpdateResponse>,
+    /// Failures of the steps after the prices: FX rates and targets.
+    errors: Vec<String>,
+}
+
+const PRICE_UPDATE_JOB: &str = "price_update";
+
+#[derive(serde::Serialize)]
+struct JobStartedResponse {
+    job_id: u64,
+}
+
+/// Starts fetching the prices of every ticker after its last final one in the
+/// background, and answers with the id of the job to poll at `/jobs/:job_id`.
+/// A failure with one ticker, or with a single price, is reported in the job
+/// and the update goes on with the rest. Only one update runs at a time.
+async fn update_prices(
+    Extension(pool): Extension<Arc<DbPool>>,
+    Extension(cache): Extension<Arc<dyn ResponseCache>>,
+    Extension(jobs): Extension<Arc<job::JobRegistry>>,
+) -> Result<(StatusCode, Json<JobStartedResponse>), ApiError> {
+    let id = jobs
+        .start(PRICE_UPDATE_JOB, tracked_tickers().len())
+        .map_err(|running| {
+            ApiError::conflict("prices are already being updated").with_detail("job_id", running)
+        })?;
+    tokio::spawn(async move {
+        let response = update_all_prices(&pool, cache.as_ref(), &mut |ticker| {
+            jobs.update(id, |job| {
+                job.done += 1;
+                job.inserted += ticker.inserted;
+                job.errors.extend(
+                    ticker
+                        .errors
+                        .iter()
+                        .map(|error| format!("{}: {}", ticker.ticker, error)),
+                );
+            })
+        })
+        .await;
+        jobs.update(id, |job| job.errors.extend(response.errors));
+        jobs.finish(id);
+    });
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(JobStartedResponse { job_id: id }),
+    ))
+}
+
+/// What `/prices/update` does, for it and the scheduled updates alike.
+/// `on_ticker` is called as each ticker is done.
+async fn update_all_prices(
+    pool: &DbPool,
+    cache: &dyn ResponseCache,
+    on_ticker: &mut (dyn FnMut(&TickerUpdateResponse) + Send),
+) -> UpdatePricesResponse {
+    // Stored series are only computed again from the first new price or FX
+    // rate of each ticker, unless there are none to start from.
+    let mut incremental = matches!(snapshot::has_snapshots(pool).await, Ok(true));
+    let mut tickers = Vec::with_capacity(tracked_tickers().len());
+    for ticker in tracked_tickers() {
+        let response = update_ticker_prices(pool, ticker).await;
+        on_ticker(&response);
+        tickers.push(response);
+    }
+
+    let mut errors = Vec::new();
+    let fx_changed_from = match update_fx_rates(pool).await {
+        Ok(changed_from) => changed_from,
+        Err(e) => {
+            // Some rates may have been stored before the failure.
+            incremental = false;
+            errors.push(format!("fx rates: {}", e.message));
+            None
+        }
+    };
+    if let Err(e) = check_targets(pool).await {
+        errors.push(format!("targets: {}", e.message));
+    }
+
+    if incremental {
+        cache.clear().await;
+        let base_currency = base_currency();
+        for response in &tickers {
+            let fx_changed_from =
+                fx_changed_from.filter(|_| ticker_currency(&response.ticker) != base_currency);
+            let changed_from = response
+                .changed_from
+                .into_iter()
+                .chain(fx_changed_from)
+                .min();
+            if let Some(changed_from) = changed_from {
+                if let Err(e) = refresh_snapshots_from(pool, &response.ticker, changed_from).await {
+                    invalidate_portfolio(pool, cache).await;
+                    errors.push(format!("snapshots: {}", e.message));
+                }
+            }
+        }
+    } else {
+        invalidate_portfolio(pool, cache).await;
+        if let Err(e) = store_snapshots(pool, cache).await {
+            errors.push(format!("snapshots: {}", e.message));
+        }
+    }
+    warm_portfolio_cache(pool, cache).await;
+    UpdatePricesResponse { tickers, errors }
+}
+
+/// Stores the series of every ticker and their total for the portfolio
+/// endpoints to be served from, unless the trades or prices changed while they
+/// were computed.
+async fn store_snapshots(pool: &DbPool, cache: &dyn ResponseCache) -> Result<(), ApiError> {
+    let generation = cache.generation().await;
+    let portfolios = compute_portfolios(pool, None, None, false).await?;
+    let total = portfolio::total_portfolio(portfolios.clone());
+    if cache.generation().await != generation {
+        return Ok(());
+    }
+    snapshot::replace_snapshots(pool, &portfolios, &total)
+        .await
+        .map_err(ApiError::internal)
+}
+
+/// Drops what was computed from the trades and prices, once they changed.
+async fn invalidate_portfolio(pool: &DbPool, cache: &dyn ResponseCache) {
+    if let Err(e) = snapshot::clear_snapshots(pool).await {
+        println!("Error clearing the portfolio snapshots: {}", e);
+    }
+    cache.clear().await;
+}
+
+/// Like `invalidate_portfolio`, once only the trades of `ticker` on or after
+/// `from` changed: the stored series are computed again from that date on
+/// instead of being dropped.
+async fn invalidate_portfolio_from(
+    pool: &DbPool,
+    cache: &dyn ResponseCache,
+    ticker: &str,
+    from: NaiveDate,
+) {
+    cache.clear().await;
+    if let Err(e) = refresh_snapshots_from(pool, ticker, from).await {
+        println!(
+            "Error refreshing the portfolio snapshots of {}: {}",
+            ticker, e.message
+        );
+        invalidate_portfolio(pool, cache).await;
+    }
+}
+
+/// Computes the stored series of `ticker` and the total again from `from` on.
+/// Units and prices before it did not change, so neither did the days before
+/// it. Without stored series there is nothing to refresh.
+async fn refresh_snapshots_from(
+    pool: &DbPool,
+    ticker: &str,
+    from: NaiveDate,
+) -> Result<(), ApiError> {
+    if !snapshot::has_snapshots(pool)
+        .await
+        .map_err(ApiError::internal)?
+    {
+        return Ok(());
+    }
+    if !tracked_tickers().contains(&ticker) {
+        return Err(ApiError::internal(format!("{} is not tracked", ticker)));
+    }
+    let trades = trade::list_trades_for_calculation(pool, Some(ticker), None)
+        .await
+        .map_err(ApiError::internal)?;
+    let mut builder = portfolio::PortfolioBuilder::new(trades, false);
+    let currency = ticker_currency(ticker);
+    let base_currency = base_currency();
+    if currency != base_currency {
+        builder = builder.with_fx_rates(
+            fx::list_fx_rates_for_calculation(pool, currency, &base_currency)
+                .await
+                .map_err(ApiError::internal)?,
+        );
+    }
+    let strategy = valuation::Valuation::Close.strategy();
+    price::for_each_daily_price(pool, Some(ticker), |price| {
+        if price.date >= from {
+            builder.push_price(price.date, strategy.unit_price(&price), price.preliminary)
+        }
+    })
+    .await
+    .map_err(ApiError::internal)?;
+    snapshot::replace_snapshots_from(pool, ticker, from, &builder.finish())
+        .await
+        .map_err(ApiError::internal)
+}
+
+/// Time of day, in UTC, of the scheduled price update, `None` when it is off.
+fn price_update_time() -> Option<NaiveTime> {
+    config().price_update_time
+}
+
+/// Updates the prices every day at `at`, UTC, and logs the outcome.
+async fn run_price_updates(pool: Arc<DbPool>, cache: Arc<dyn ResponseCache>, at: NaiveTime) {
+    loop {
+        let now = Utc::now().naive_utc();
+        let mut next = now.date().and_time(at);
+        if next <= now {
+            next += Duration::days(1);
+        }
+        if let Ok(wait) = (next - now).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+
+        let response = update_all_prices(&pool, cache.as_ref(), &mut |_| ()).await;
+        for ticker in &response.tickers {
+            println!(
+                "Scheduled price update of {}: {} prices from {}{}",
+                ticker.ticker,
+                ticker.inserted,
+                ticker.source.as_deref().unwrap_or("no provider"),
+                if ticker.errors.is_empty() {
+                    String::new()
+                } else {
+                    format!(", errors: {}", ticker.errors.join("; "))
+                }
+            );
+        }
+        for error in &response.errors {
+            println!("Scheduled price update error: {}", error);
+        }
+    }
+}
+
+async fn update_ticker_prices(pool: &DbPool, ticker: &str) -> TickerUpdateResponse {
+    let mut response = TickerUpdateResponse {
+        ticker: ticker.to_string(),
+        source: None,
+        inserted: 0,
+        errors: Vec::new(),
+        changed_from: None,
+    };
+
+    let last_ticker_date = match sqlx::query_as!(
+        LastPriceDate,
+        r#"
+        SELECT date from prices where ticker = $1 AND NOT preliminary ORDER BY date desc limit 1
         "#,
+        ticker,
+    )
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(last_price_date)) => {
+            match NaiveDate::parse_from_str(&last_price_date.date, "%Y-%m-%d") {
+                Ok(date) => date,
+                Err(_) => {
+                    response.errors.push(format!(
+                        "stored date {} is not a YYYY-MM-DD date",
+                        last_price_date.date
+                    ));
+                    return response;
+                }
+            }
+        }
+        Ok(None) => chrono::naive::MIN_DATE,
+        Err(e) => {
+            response.errors.push(e.to_string());
+            return response;
+        }
+    };
+
+    let source = match price_source(pool, ticker).await {
+        Ok(source) => source,
+        Err(e) => {
+            response.errors.push(e.to_string());
+            return response;
+        }
+    };
+    match ticker::get_instrument(pool, ticker).await {
+        Ok(Some(instrument)) => {
+            if let Err(message) = check_instrument_currency(ticker, &instrument) {
+                response.errors.push(message);
+                return response;
+            }
+        }
+        Ok(None) => (),
+        Err(e) => {
+            response.errors.push(e.to_string());
+            return response;
+        }
+    }
+    let (provider, prices) =
+        match fetch_daily_prices_with_fallback(ticker, &source, last_ticker_date).await {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                response.errors.push(e.message);
+                return response;
+            }
+        };
+    response.source = Some(provider.clone());
+    // The splits and the prices adjusted to them are stored together.
+    let mut tx = match begin(pool).await {
+        Ok(tx) => tx,
+        Err(e) => {
+            response.errors.push(e.message);
+            return response;
+        }
+    };
+    let mut changed_from = match record_splits(&mut tx, pool, ticker, &prices).await {
+        // A new split changes the units of every trade before it.
+        Ok(true) => Some(chrono::naive::MIN_DATE),
+        Ok(false) => None,
+        Err(e) => {
+            response.errors.push(format!("splits: {}", e));
+            return response;
+        }
+    };
+    let trust_order = price_trust_order();
+    let mut inserted = 0;
+    for daily_price in prices {
+        match price::upsert_price(&mut tx, ticker, &daily_price, &provider, &trust_order).await {
+            Ok(()) => {
+                inserted += 1;
+                changed_from = changed_from.or(Some(daily_price.date));
+            }
+            Err(e) => response
+                .errors
+                .push(format!("price of {}: {}", daily_price.date, e)),
+        }
+    }
+    match commit(tx).await {
+        Ok(()) => {
+            response.inserted = inserted;
+            response.changed_from = changed_from;
+        }
+        Err(e) => response.errors.push(e.message),
+    }
+    response
+}
+
+/// Records the splits the provider reported in `prices` that are not recorded
+/// yet, so trades before them are adjusted like the prices are. Returns
+/// whether any was.
+async fn record_splits(
+    tx: &mut Transaction<'_, Db>,
+    pool: &DbPool,
+    ticker: &str,
+    prices: &[price::DailyPrice],
+) -> Result<bool, sqlx::Error> {
+    let recorded = corporate_action::list_splits(pool, Some(ticker)).await?;
+    let mut recorded_any = false;
+    for daily_price in prices {
+        let ratio = match &daily_price.split_coefficient {
+            Some(ratio) if *ratio != BigDecimal::from(1) => ratio,
+            _ => continue,
+        };
+        if recorded.iter().any(|split| split.date == daily_price.date) {
+            continue;
+        }
+        let split = corporate_action::CreateSplit {
+            ticker: ticker.to_string(),
+            date: daily_price.date.to_string(),
+            ratio: ratio.to_string(),
+        };
+        corporate_action::create_split(&mut *tx, split).await?;
+        recorded_any = true;
+    }
+    Ok(recorded_any)
+}
+
+/// Order to try FX providers in for currencies another provider than the
+/// default one covers better.
+const FX_PROVIDER_PREFERENCES: &[(&str, &[&str])] = &[];
+
+/// The ECB first, then Alpha Vantage when an API key is configured. Only the
+/// mock provider with `MOCK_PROVIDERS`.
+fn fx_providers() -> fx::FxProviders {
+    if mock_providers() {
+        return fx::FxProviders::new(vec![Box::new(fx::MockFxProvider)]);
+    }
+    let mut providers: Vec<Box<dyn fx::FxProvider>> = vec![Box::new(fx::EcbFxProvider)];
+    if let Some(api_key) = config().alpha_vantage_api_key.clone() {
+        providers.push(Box::new(fx::AlphaVantageFxProvider::new(api_key)));
+    }
+    FX_PROVIDER_PREFERENCES.iter().fold(
+        fx::FxProviders::new(providers),
+        |providers, (currency, names)| providers.prefer(currency, names),
+    )
+}
+
+/// Every currency tickers are quoted in or dividends are paid in, other than
+/// `base_currency`.
+async fn fx_currencies(pool: &DbPool, base_currency: &str) -> Result<Vec<String>, ApiError> {
+    let mut currencies: Vec<String> = dividend::list_currencies(pool)
+        .await
+        .map_err(ApiError::internal)?;
+    currencies.extend(
+        ticker_currencies()
+            .iter()
+            .map(|(_, currency)| currency.to_string()),
+    );
+    currencies.retain(|currency| currency != base_currency);
+    currencies.sort_unstable();
+    currencies.dedup();
+    Ok(currencies)
+}
+
+/// Fetches the daily rates of every currency tickers are quoted in, other than
+/// the base currency, after the last stored one. Returns the date of the
+/// first rate stored.
+async fn update_fx_rates(pool: &DbPool) -> Result<Option<NaiveDate>, ApiError> {
+    let base_currency = base_currency();
+    let providers = fx_providers();
+    let mut changed_from: Option<NaiveDate> = None;
+    for currency in fx_currencies(pool, &base_currency).await? {
+        let currency = currency.as_str();
+        let last_rate_date = fx::get_last_fx_rate_date(pool, currency, &base_currency)
+            .await
+            .map_err(ApiError::internal)?
+            .unwrap_or(chrono::naive::MIN_DATE);
+        let rates = providers
+            .fetch_rates(currency, &base_currency, last_rate_date)
+            .await
+            .map_err(|e| ApiError::bad_gateway(e.to_string()))?;
+        for (date, rate) in rates {
+            fx::upsert_fx_rate(
+                pool,
+                currency,
+                &base_currency,
+                &date.to_string(),
+                &rate.to_string(),
+            )
+            .await
+            .map_err(ApiError::internal)?;
+            changed_from = Some(changed_from.map_or(date, |changed_from| changed_from.min(date)));
+        }
+    }
+    Ok(changed_from)
+}
+
+async fn update_fx(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<StatusCode, ApiError> {
+    update_fx_rates(&pool).await?;
+
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    warm_portfolio_cache(&pool, cache.as_ref()).await;
+    Ok(StatusCode::OK)
+}
+
+/// Rates stored per transaction by the FX backfill.
+const FX_BACKFILL_CHUNK_SIZE: usize = 250;
+
+/// How far before the first trade the FX backfill starts, so that trades on
+/// days without a published rate have an earlier one.
+const FX_BACKFILL_LOOKBACK_DAYS: i64 = 7;
+
+#[derive(serde::Serialize)]
+struct FxBackfillResponse {
+    currency: String,
+    base_currency: String,
+    /// Dates of the first and last rates stored, `None` when there were none
+    /// missing.
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    inserted: usize,
+}
+
+/// Fetches the rates of every currency tickers are quoted in from before the
+/// first trade until the first rate already stored, or until today when none
+/// is. Rates are stored newest first in chunks of their own transaction, so an
+/// interrupted backfill leaves no gap and resumes where it stopped.
+async fn backfill_fx_rates(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Json<Vec<FxBackfillResponse>>, ApiError> {
+    let first_trade_date = match trade::get_first_trade_date(&pool).await {
+        Ok(Some(first_trade_date)) => first_trade_date,
+        Ok(None) => return Ok(Json(Vec::new())),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let since = first_trade_date + Duration::days(-FX_BACKFILL_LOOKBACK_DAYS);
+
+    let base_currency = base_currency();
+    let providers = fx_providers();
+    let mut backfills = Vec::new();
+    for currency in fx_currencies(&pool, &base_currency).await? {
+        let first_rate_date = fx::get_first_fx_rate_date(&pool, &currency, &base_currency)
+            .await
+            .map_err(ApiError::internal)?;
+        let mut rates = if first_rate_date.is_some_and(|first_rate_date| first_rate_date <= since) {
+            Vec::new()
+        } else {
+            providers
+                .fetch_rates(&currency, &base_currency, since.pred())
+                .await
+                .map_err(|e| ApiError::bad_gateway(e.to_string()))?
+        };
+        rates.retain(|(date, _)| {
+            first_rate_date.is_none_or(|first_rate_date| *date < first_rate_date)
+        });
+
+        for chunk in rates.rchunks(FX_BACKFILL_CHUNK_SIZE) {
+            fx::upsert_fx_rates(&pool, &currency, &base_currency, chunk)
+                .await
+                .map_err(ApiError::internal)?;
+        }
+        backfills.push(FxBackfillResponse {
+            currency,
+            base_currency: base_currency.clone(),
+            from: rates.first().map(|(date, _)| *date),
+            to: rates.last().map(|(date, _)| *date),
+            inserted: rates.len(),
+        });
+    }
+
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    Ok(Json(backfills))
+}
+
+#[derive(Deserialize)]
+struct FxRateFilter {
+    currency: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct FxRateResponse {
+    id: i64,
+    currency: String,
+    base_currency: String,
+    date: String,
+    rate: String,
+}
+
+impl From<fx::FxRate> for FxRateResponse {
+    fn from(rate: fx::FxRate) -> Self {
+        FxRateResponse {
+            id: rate.id,
+            currency: rate.currency,
+            base_currency: rate.base_currency,
+            date: rate.date,
+            rate: rate.rate,
+        }
+    }
+}
+
+async fn list_fx_rates(
+    pool: Extension<Arc<DbPool>>,
+    Query(filter): Query<FxRateFilter>,
+) -> Result<Json<Vec<FxRateResponse>>, ApiError> {
+    match fx::list_fx_rates(&pool, filter.currency.as_deref()).await {
+        Ok(res) => Ok(Json(res.into_iter().map(|rate| rate.into()).collect())),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ListPricesResponse {
+    id: i64,
+    ticker: String,
+    date: String,
+    price: String,
+    preliminary: bool,
+    /// Provider the price was fetched from, unknown for prices stored before
+    /// it was recorded.
+    source: Option<String>,
+}
+
+/// Rows sent per chunk of the `/prices` listing.
+const PRICE_LISTING_CHUNK_ROWS: usize = 500;
+
+/// Lists every stored price as a JSON array streamed in chunks as the rows
+/// are read, so years of daily prices are never held in memory at once. A
+/// database failure midway cuts the response short.
+async fn list_prices(Extension(pool): Extension<Arc<DbPool>>) -> Response {
+    let (mut chunks, body) = futures::channel::mpsc::channel::<Result<Vec<u8>, sqlx::Error>>(1);
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as!(
+            ListPricesResponse,
+            r#"
+            SELECT id as "id!", ticker, date, price, preliminary as "preliminary: bool", source FROM prices ORDER by date asc, id asc
+            "#,
+        )
+        .fetch(&*pool);
+
+        let mut chunk = b"[".to_vec();
+        let mut listed = 0;
+        loop {
+            let row = match rows.try_next().await {
+                Ok(Some(row)) => row,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = chunks.send(Err(e)).await;
+                    return;
+                }
+            };
+            if listed > 0 {
+                chunk.push(b',');
+            }
+            if let Err(e) = serde_json::to_writer(&mut chunk, &row) {
+                let _ = chunks.send(Err(sqlx::Error::Protocol(e.to_string()))).await;
+                return;
+            }
+            listed += 1;
+            if listed % PRICE_LISTING_CHUNK_ROWS == 0
+                && chunks.send(Ok(std::mem::take(&mut chunk))).await.is_err()
+            {
+                // The client went away.
+                return;
+            }
+        }
+        chunk.push(b']');
+        let _ = chunks.send(Ok(chunk)).await;
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        StreamBody::new(body),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct CreatePrice {
+    ticker: String,
+    date: NaiveDate,
+    price: BigDecimal,
+}
+
+/// Stores a close entered by hand for a date the provider has none for.
+/// Fetched prices don't replace it.
+async fn create_price(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<CreatePrice>,
+) -> Result<(StatusCode, Json<i64>), ApiError> {
+    if !tracked_tickers().contains(&payload.ticker.as_str()) {
+        return Err(ApiError::invalid_field(
+            "ticker",
+            format!("{} is not tracked", payload.ticker),
+        ));
+    }
+    if payload.price <= BigDecimal::from(0) {
+        return Err(ApiError::invalid_field("price", "must be positive"));
+    }
+    let date = payload.date.to_string();
+    match price::insert_manual_price(&pool, &payload.ticker, &date, &payload.price.to_string())
+        .await
+    {
+        Ok(Some(id)) => {
+            invalidate_portfolio(&pool, cache.as_ref()).await;
+            Ok((StatusCode::CREATED, Json(id)))
+        }
+        Ok(None) => Err(ApiError::conflict(format!(
+            "{} has a price on {} already, correct it instead",
+            payload.ticker, date
+        ))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct CorrectPrice {
+    price: BigDecimal,
+}
+
+/// Replaces a wrong close with one entered by hand. Fetched prices don't
+/// replace it.
+async fn correct_price(
+    Path(price_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<CorrectPrice>,
+) -> Result<StatusCode, ApiError> {
+    if payload.price <= BigDecimal::from(0) {
+        return Err(ApiError::invalid_field("price", "must be positive"));
+    }
+    match price::correct_price(&pool, price_id, &payload.price.to_string()).await {
+        Ok(true) => {
+            invalidate_portfolio(&pool, cache.as_ref()).await;
+            Ok(StatusCode::OK)
+        }
+        Ok(false) => Err(ApiError::not_found(format!("price {} not found", price_id))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct SetPriceMarks {
+    nav: Option<BigDecimal>,
+    bid: Option<BigDecimal>,
+    ask: Option<BigDecimal>,
+}
+
+/// Sets the NAV and the bid and ask of a stored price, used by the `nav` and
+/// `bid_ask` valuations. Marks left out are cleared.
+async fn set_price_marks(
+    Path(price_id): Path<i64>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<SetPriceMarks>,
+) -> Result<StatusCode, ApiError> {
+    for (field, mark) in [
+        ("nav", &payload.nav),
+        ("bid", &payload.bid),
+        ("ask", &payload.ask),
+    ] {
+        if mark
+            .as_ref()
+            .is_some_and(|mark| *mark <= BigDecimal::from(0))
+        {
+            return Err(ApiError::invalid_field(field, "must be positive"));
+        }
+    }
+    if let (Some(bid), Some(ask)) = (&payload.bid, &payload.ask) {
+        if bid > ask {
+            return Err(ApiError::invalid_field("bid", "must not be above the ask"));
+        }
+    }
+    let mark = |mark: &Option<BigDecimal>| mark.as_ref().map(BigDecimal::to_string);
+    let (nav, bid, ask) = (mark(&payload.nav), mark(&payload.bid), mark(&payload.ask));
+    match price::set_price_marks(
+        &pool,
+        price_id,
+        nav.as_deref(),
+        bid.as_deref(),
+        ask.as_deref(),
+    )
+    .await
+    {
+        Ok(true) => {
+            // The snapshots are valued at the close, which didn't change.
+            cache.clear().await;
+            Ok(StatusCode::OK)
+        }
+        Ok(false) => Err(ApiError::not_found(format!("price {} not found", price_id))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PriceGapResponse {
+    from: NaiveDate,
+    to: NaiveDate,
+    days: usize,
+}
+
+#[derive(serde::Serialize)]
+struct TickerPriceGapsResponse {
+    ticker: String,
+    gaps: Vec<PriceGapResponse>,
+}
+
+/// Gaps in the stored prices of `ticker`: weekdays without a price, or any
+/// day for cryptocurrencies.
+async fn ticker_price_gaps(pool: &DbPool, ticker: &str) -> Result<Vec<price::PriceGap>, ApiError> {
+    let dates = price::list_price_dates(pool, ticker)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(price::find_gaps(&dates, crypto_tickers().contains(&ticker)))
+}
+
+/// Days missing between the first and the last stored price of every ticker,
+/// as ranges of consecutive days. Exchange holidays show up as gaps too.
+async fn list_price_gaps(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<TickerPriceGapsResponse>>, ApiError> {
+    let mut tickers = Vec::with_capacity(tracked_tickers().len());
+    for ticker in tracked_tickers() {
+        tickers.push(TickerPriceGapsResponse {
+            ticker: ticker.to_string(),
+            gaps: ticker_price_gaps(&pool, ticker)
+                .await?
+                .into_iter()
+                .map(|gap| PriceGapResponse {
+                    from: gap.from,
+                    to: gap.to,
+                    days: gap.days,
+                })
+                .collect(),
+        });
+    }
+    Ok(Json(tickers))
+}
+
+#[derive(serde::Serialize)]
+struct GapBackfillResponse {
+    ticker: String,
+    /// Days missing before the backfill.
+    missing: usize,
+    filled: usize,
+    /// Provider the prices were fetched from, `None` when none was needed or
+    /// none answered.
+    source: Option<String>,
+    errors: Vec<String>,
+}
+
+/// Fetches the prices of every ticker from its first gap on and stores those
+/// of the missing days only, leaving the stored ones as they are. Days the
+/// provider has no price for, like holidays, stay missing.
+async fn backfill_price_gaps(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Json<Vec<GapBackfillResponse>>, ApiError> {
+    let trust_order = price_trust_order();
+    let mut tickers = Vec::with_capacity(tracked_tickers().len());
+    for ticker in tracked_tickers() {
+        let gaps = ticker_price_gaps(&pool, ticker).await?;
+        let mut response = GapBackfillResponse {
+            ticker: ticker.to_string(),
+            missing: gaps.iter().map(|gap| gap.days).sum(),
+            filled: 0,
+            source: None,
+            errors: Vec::new(),
+        };
+        let first_gap = match gaps.first() {
+            Some(first_gap) => first_gap.from,
+            None => {
+                tickers.push(response);
+                continue;
+            }
+        };
+
+        let source = price_source(&pool, ticker)
+            .await
+            .map_err(ApiError::internal)?;
+        let (provider, prices) =
+            match fetch_daily_prices_with_fallback(ticker, &source, first_gap.pred()).await {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    response.errors.push(e.message);
+                    tickers.push(response);
+                    continue;
+                }
+            };
+        let mut tx = begin(&pool).await?;
+        for daily_price in prices.iter().filter(|daily_price| {
+            gaps.iter()
+                .any(|gap| daily_price.date >= gap.from && daily_price.date <= gap.to)
+        }) {
+            match price::upsert_price(&mut tx, ticker, daily_price, &provider, &trust_order).await {
+                Ok(()) => response.filled += 1,
+                Err(e) => response
+                    .errors
+                    .push(format!("price of {}: {}", daily_price.date, e)),
+            }
+        }
+        commit(tx).await?;
+        response.source = Some(provider);
+        tickers.push(response);
+    }
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    Ok(Json(tickers))
+}
+
+#[derive(Deserialize)]
+struct PriceConflictQuery {
+    #[serde(default)]
+    tolerance_percent: Option<BigDecimal>,
+}
+
+#[derive(serde::Serialize)]
+struct PriceQuoteResponse {
+    source: String,
+    price: String,
+}
+
+#[derive(serde::Serialize)]
+struct PriceConflictResponse {
+    ticker: String,
+    date: String,
+    price: String,
+    source: Option<String>,
+    quotes: Vec<PriceQuoteResponse>,
+}
+
+impl From<price::PriceConflict> for PriceConflictResponse {
+    fn from(conflict: price::PriceConflict) -> Self {
+        PriceConflictResponse {
+            ticker: conflict.ticker,
+            date: conflict.date,
+            price: conflict.price,
+            source: conflict.source,
+            quotes: conflict
+                .quotes
+                .into_iter()
+                .map(|quote| PriceQuoteResponse {
+                    source: quote.source,
+                    price: quote.price.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Dates on which providers fetched different prices, with the price each
+/// fetched and the one stored, until one is picked with
+/// `/prices/conflicts/resolve`. Differences of up to `tolerance_percent`, 0 by
+/// default, are ignored.
+async fn list_price_conflicts(
+    Query(query): Query<PriceConflictQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<PriceConflictResponse>>, ApiError> {
+    let tolerance_percent = query.tolerance_percent.unwrap_or_default();
+    if tolerance_percent < BigDecimal::from(0) {
+        return Err(ApiError::invalid_field(
+            "tolerance_percent",
+            "must not be negative",
+        ));
+    }
+    match price::list_conflicts(&pool, &tolerance_percent).await {
+        Ok(conflicts) => Ok(Json(conflicts.into_iter().map(|x| x.into()).collect())),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct ResolvePriceConflict {
+    ticker: String,
+    date: NaiveDate,
+    source: String,
+}
+
+/// Stores the price `source` fetched for the date, and keeps it over the ones
+/// fetched later.
+async fn resolve_price_conflict(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<ResolvePriceConflict>,
+) -> Result<StatusCode, ApiError> {
+    let date = payload.date.to_string();
+    match price::pick_price(&pool, &payload.ticker, &date, &payload.source).await {
+        Ok(true) => {
+            invalidate_portfolio(&pool, cache.as_ref()).await;
+            Ok(StatusCode::OK)
+        }
+        Ok(false) => Err(ApiError::not_found(format!(
+            "{} has no price of {} on {}",
+            payload.source, payload.ticker, date
+        ))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct DeletePricesQuery {
+    ticker: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(serde::Serialize)]
+struct DeletePricesResponse {
+    /// Id to undo the deletion with at `/admin/undo/:operation_id`.
+    operation_id: i64,
+    deleted: usize,
+}
+
+/// Deletes the stored prices of `ticker` from `from` to `to`, both included,
+/// or of every ticker and date when not set.
+async fn delete_prices(
+    Query(query): Query<DeletePricesQuery>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Json<DeletePricesResponse>, ApiError> {
+    let range = price::PriceRange {
+        ticker: query.ticker,
+        from: query.from.map(|from| from.to_string()),
+        to: query.to.map(|to| to.to_string()),
+    };
+    let deleted = price::delete_prices(&pool, &range).await;
+    invalidate_portfolio(&pool, cache.as_ref()).await;
+    match deleted {
+        Ok((operation_id, deleted)) => Ok(Json(DeletePricesResponse {
+            operation_id,
+            deleted,
+        })),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+const PRICE_VERIFICATION_SAMPLE_SIZE: i64 = 20;
+const PRICE_VERIFICATION_INTERVAL_SECONDS: u64 = 30 * 24 * 60 * 60;
+/// Keeps the verification well under the Alpha Vantage free tier limit of 5
+/// requests per minute, leaving room for regular updates.
+const PRICE_VERIFICATION_REQUEST_DELAY_SECONDS: u64 = 30;
+
+#[derive(serde::Serialize)]
+struct PriceMismatch {
+    ticker: String,
+    date: String,
+    stored_price: String,
+    /// `None` when the provider no longer has a price for the date.
+    fetched_price: Option<String>,
+}
+
+/// Re-fetches the full history of every ticker and compares a random sample
+/// of stored closes against it, to catch restatements by the provider or
+/// prices stored wrongly. Prices normalized after a split or redenomination
+/// are reported as mismatches too.
+async fn verify_prices(pool: &DbPool) -> Result<Vec<PriceMismatch>, ApiError> {
+    let mut mismatches = Vec::new();
+    for (i, ticker) in tracked_tickers().iter().enumerate() {
+        if i > 0 {
+            tokio::time::sleep(std::time::Duration::from_secs(
+                PRICE_VERIFICATION_REQUEST_DELAY_SECONDS,
+            ))
+            .await;
+        }
+        let sample = price::sample_prices(pool, ticker, PRICE_VERIFICATION_SAMPLE_SIZE)
+            .await
+            .map_err(ApiError::internal)?;
+        if sample.is_empty() {
+            continue;
+        }
+        let source = price_source(pool, ticker)
+            .await
+            .map_err(ApiError::internal)?;
+        let fetched_prices: HashMap<String, BigDecimal> =
+            fetch_daily_prices(ticker, &source, chrono::naive::MIN_DATE)
+                .await?
+                .into_iter()
+                .map(|daily_price| (daily_price.date.to_string(), daily_price.price))
+                .collect();
+        for stored in sample {
+            let fetched_price = fetched_prices.get(&stored.date);
+            let matches = fetched_price.is_some_and(|fetched| {
+                BigDecimal::from_str(&stored.price).ok().as_ref() == Some(fetched)
+            });
+            if !matches {
+                mismatches.push(PriceMismatch {
+                    ticker: ticker.to_string(),
+                    date: stored.date,
+                    stored_price: stored.price,
+                    fetched_price: fetched_price.map(|fetched| fetched.to_string()),
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+async fn verify_prices_report(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<PriceMismatch>>, ApiError> {
+    Ok(Json(verify_prices(&pool).await?))
+}
+
+/// Day-over-day move above which a price is flagged when no threshold is
+/// given.
+const DEFAULT_PRICE_JUMP_THRESHOLD_PERCENT: u32 = 25;
+
+#[derive(Deserialize)]
+struct PriceAnomalyQuery {
+    threshold_percent: Option<BigDecimal>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PriceAnomalyKindResponse {
+    NonPositive,
+    Jump,
+}
+
+#[derive(serde::Serialize)]
+struct PriceAnomalyResponse {
+    ticker: String,
+    date: NaiveDate,
+    price: String,
+    kind: PriceAnomalyKindResponse,
+    previous_date: Option<NaiveDate>,
+    previous_price: Option<String>,
+    change_percent: Option<String>,
+}
+
+impl From<price::PriceAnomaly> for PriceAnomalyResponse {
+    fn from(anomaly: price::PriceAnomaly) -> Self {
+        PriceAnomalyResponse {
+            ticker: anomaly.ticker,
+            date: anomaly.date,
+            price: anomaly.price.to_string(),
+            kind: match anomaly.kind {
+                price::PriceAnomalyKind::NonPositive => PriceAnomalyKindResponse::NonPositive,
+                price::PriceAnomalyKind::Jump => PriceAnomalyKindResponse::Jump,
+            },
+            previous_date: anomaly.previous.as_ref().map(|(date, _)| *date),
+            previous_price: anomaly.previous.map(|(_, price)| price.to_string()),
+            change_percent: anomaly.change_percent.map(|change| change.to_string()),
+        }
+    }
+}
+
+/// Flags stored prices that look like provider glitches: zero or negative
+/// ones, and moves from the previous price above `threshold_percent`, 25 by
+/// default. Wrong prices can then be fixed with `PATCH /prices/:price_id`.
+async fn price_anomalies_report(
+    Query(query): Query<PriceAnomalyQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<PriceAnomalyResponse>>, ApiError> {
+    let threshold_percent = query
+        .threshold_percent
+        .unwrap_or_else(|| BigDecimal::from(DEFAULT_PRICE_JUMP_THRESHOLD_PERCENT));
+    if threshold_percent <= BigDecimal::from(0) {
+        return Err(ApiError::invalid_field(
+            "threshold_percent",
+            "must be positive",
+        ));
+    }
+    match price::find_anomalies(&pool, &threshold_percent).await {
+        Ok(anomalies) => Ok(Json(anomalies.into_iter().map(|x| x.into()).collect())),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct TickerQualityResponse {
+    ticker: String,
+    /// From 0 to 100: the share of trading days, from the first stored price
+    /// to yesterday, that have a price without any of the issues below.
+    score: usize,
+    first_price_date: Option<NaiveDate>,
+    last_price_date: Option<NaiveDate>,
+    gaps: usize,
+    /// Trading days missing between the first and the last stored price.
+    gap_days: usize,
+    /// Trading days after the last stored price, up to yesterday.
+    stale_days: usize,
+    /// Dates providers fetched different prices on.
+    conflicts: usize,
+    /// Prices that look like provider glitches, as flagged by
+    /// `/admin/prices/anomalies` with its default threshold.
+    anomalies: usize,
+}
+
+/// How far the stored prices of `ticker` can be trusted: the gaps in them,
+/// how long ago the last one is, and the conflicting and suspicious ones,
+/// summed up in a score.
+async fn ticker_quality(
+    Path(ticker): Path<String>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<TickerQualityResponse>, ApiError> {
+    if !tracked_tickers().contains(&ticker.as_str()) {
+        return Err(ApiError::not_found(format!("{} is not tracked", ticker)));
+    }
+    let crypto = crypto_tickers().contains(&ticker.as_str());
+    let dates = price::list_price_dates(&pool, &ticker)
+        .await
+        .map_err(ApiError::internal)?;
+    let gaps = price::find_gaps(&dates, crypto);
+    let gap_days: usize = gaps.iter().map(|gap| gap.days).sum();
+    // Today's close may not be published yet.
+    let today = Utc::today().naive_utc();
+    let stale_days = dates
+        .last()
+        .map_or(0, |last| price::trading_days_between(*last, today, crypto));
+    let conflicts = price::list_conflicts(&pool, &BigDecimal::from(0))
+        .await
+        .map_err(ApiError::internal)?
+        .into_iter()
+        .filter(|conflict| conflict.ticker == ticker)
+        .count();
+    let anomalies = price::find_anomalies(
+        &pool,
+        &BigDecimal::from(DEFAULT_PRICE_JUMP_THRESHOLD_PERCENT),
+    )
+    .await
+    .map_err(ApiError::internal)?
+    .into_iter()
+    .filter(|anomaly| anomaly.ticker == ticker)
+    .count();
+
+    let expected_days = dates.len() + gap_days + stale_days;
+    let flawed_days = (gap_days + stale_days + conflicts + anomalies).min(expected_days);
+    let score = match expected_days {
+        0 => 0,
+        _ => 100 * (expected_days - flawed_days) / expected_days,
+    };
+    Ok(Json(TickerQualityResponse {
+        score,
+        first_price_date: dates.first().copied(),
+        last_price_date: dates.last().copied(),
+        gaps: gaps.len(),
+        gap_days,
+        stale_days,
+        conflicts,
+        anomalies,
+        ticker,
+    }))
+}
+
+/// Runs the price verification once a month in the background and logs the
+/// mismatches it finds.
+async fn run_price_verification(pool: Arc<DbPool>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        PRICE_VERIFICATION_INTERVAL_SECONDS,
+    ));
+    // The first tick completes immediately; skip it so a restart doesn't
+    // trigger a verification.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match verify_prices(&pool).await {
+            Ok(mismatches) => {
+                for m in &mismatches {
+                    println!(
+                        "Price mismatch for {} on {}: stored {}, provider {}",
+                        m.ticker,
+                        m.date,
+                        m.stored_price,
+                        m.fetched_price.as_deref().unwrap_or("missing")
+                    );
+                }
+                println!("Price verification found {} mismatches", mismatches.len());
+            }
+            Err(e) => println!("Error verifying prices {}", e.message),
+        }
+    }
+}
+
+/// Where the database is copied to, ideally on another disk. Backups are off
+/// without it.
+fn backup_path() -> Option<PathBuf> {
+    config().backup_path.clone()
+}
+
+fn backup_interval_minutes() -> u64 {
+    config().backup_interval_minutes
+}
+
+/// Copies the database to `path` at boot and then every
+/// `backup_interval_minutes`, keeping a standby copy to restore from.
+async fn run_backups(pool: Arc<DbPool>, path: PathBuf) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        backup_interval_minutes() * 60,
+    ));
+    loop {
+        interval.tick().await;
+        if let Err(e) = backup::backup_to(&pool, &path).await {
+            println!("Error backing up the database to {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct BackupResponse {
+    path: String,
+    interval_minutes: u64,
+    /// When the copy at `path` was last written, `None` before the first one.
+    last_backup_at: Option<chrono::DateTime<Utc>>,
+    bytes: Option<u64>,
+    restore: String,
+}
+
+fn backup_response(path: &std::path::Path, last_backup: Option<backup::Backup>) -> BackupResponse {
+    BackupResponse {
+        path: path.display().to_string(),
+        interval_minutes: backup_interval_minutes(),
+        last_backup_at: last_backup.as_ref().map(|backup| backup.created_at),
+        bytes: last_backup.map(|backup| backup.bytes),
+        restore: format!(
+            "stop the server, copy {} over {} and start it again",
+            path.display(),
+            config().database_path()
+        ),
+    }
+}
+
+fn configured_backup_path() -> Result<PathBuf, ApiError> {
+    backup_path().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "not_configured",
+            "BACKUP_PATH is not set",
+        )
+    })
+}
+
+/// Describes the standby copy of the database and how to restore it.
+async fn get_backup() -> Result<Json<BackupResponse>, ApiError> {
+    let path = configured_backup_path()?;
+    let last_backup = backup::last_backup(&path)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(backup_response(&path, last_backup)))
+}
+
+/// Copies the database to the backup path now, like before an upgrade.
+async fn create_backup(pool: Extension<Arc<DbPool>>) -> Result<Json<BackupResponse>, ApiError> {
+    let path = configured_backup_path()?;
+    let backup = backup::backup_to(&pool, &path)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(backup_response(&path, Some(backup))))
+}
+
+/// Checks the configuration and the environment the server depends on, so
+/// problems show up at boot instead of as failing requests later.
+async fn run_self_check(pool: &DbPool) -> selfcheck::SelfCheckReport {
+    let base_currency = base_currency();
+    let tickers: Vec<(&str, &str)> = tracked_tickers()
+        .iter()
+        .map(|ticker| (*ticker, ticker_currency(ticker)))
+        .collect();
+    selfcheck::SelfCheckReport::new(vec![
+        selfcheck::check_migrations(pool).await,
+        selfcheck::check_database_writable(pool).await,
+        selfcheck::check_alpha_vantage_key(
+            config().alpha_vantage_api_key.clone(),
+            tracked_tickers()[0],
+        )
+        .await,
+        selfcheck::check_tickers(pool, &tickers, &base_currency).await,
+        selfcheck::Check::new(
+            "price_verification_job",
+            selfcheck::CheckStatus::Ok,
+            format!(
+                "every {} days",
+                PRICE_VERIFICATION_INTERVAL_SECONDS / (24 * 60 * 60)
+            ),
+        ),
+        match backup_path() {
+            Some(path) => selfcheck::Check::new(
+                "backup",
+                selfcheck::CheckStatus::Ok,
+                format!(
+                    "to {} every {} minutes",
+                    path.display(),
+                    backup_interval_minutes()
+                ),
+            ),
+            None => selfcheck::Check::new(
+                "backup",
+                selfcheck::CheckStatus::Warning,
+                "off, set BACKUP_PATH to keep a copy of the database",
+            ),
+        },
+        match price_update_time() {
+            Some(at) => selfcheck::Check::new(
+                "price_update_job",
+                selfcheck::CheckStatus::Ok,
+                format!("daily at {} UTC", at.format("%H:%M")),
+            ),
+            None => selfcheck::Check::new(
+                "price_update_job",
+                selfcheck::CheckStatus::Warning,
+                "off, prices are only updated through /prices/update",
+            ),
+        },
+    ])
+}
+
+#[derive(serde::Serialize)]
+struct JobResponse {
+    id: u64,
+    kind: &'static str,
+    state: &'static str,
+    started_at: chrono::DateTime<Utc>,
+    finished_at: Option<chrono::DateTime<Utc>>,
+    total: usize,
+    done: usize,
+    inserted: usize,
+    errors: Vec<String>,
+}
+
+impl From<job::Job> for JobResponse {
+    fn from(job: job::Job) -> Self {
+        JobResponse {
+            id: job.id,
+            kind: job.kind,
+            state: match job.state {
+                job::JobState::Running => "running",
+                job::JobState::Finished => "finished",
+            },
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            total: job.total,
+            done: job.done,
+            inserted: job.inserted,
+            errors: job.errors,
+        }
+    }
+}
+
+async fn get_job(
+    Path(job_id): Path<u64>,
+    jobs: Extension<Arc<job::JobRegistry>>,
+) -> Result<Json<JobResponse>, ApiError> {
+    match jobs.get(job_id) {
+        Some(job) => Ok(Json(job.into())),
+        None => Err(ApiError::not_found(format!("job {} not found", job_id))),
+    }
+}
+
+/// Runs the self-check again; answers 503 when any check fails.
+async fn self_check_report(pool: Extension<Arc<DbPool>>) -> Response {
+    let report = run_self_check(&pool).await;
+    let status = if report.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report)).into_response()
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PriceNormalization {
+    Split { ratio: String },
+    Redenomination { rate: String },
+    DecimalShift { places: i64 },
+}
+
+impl PriceNormalization {
+    fn factor(&self) -> Option<BigDecimal> {
+        let factor = match self {
+            PriceNormalization::Split { ratio } => BigDecimal::from_str(ratio).ok()?.inverse(),
+            PriceNormalization::Redenomination { rate } => BigDecimal::from_str(rate).ok()?,
+            PriceNormalization::DecimalShift { places } => {
+                BigDecimal::from_str(&format!("1e{}", places)).ok()?
+            }
+        };
+        if factor > BigDecimal::from(0) {
+            Some(factor)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NormalizePrices {
+    ticker: String,
+    from: String,
+    to: String,
+    normalization: PriceNormalization,
+    #[serde(default)]
+    preview: bool,
+}
+
+#[derive(serde::Serialize)]
+struct NormalizedPriceResponse {
+    id: i64,
+    date: String,
+    old_price: String,
+    new_price: String,
+}
+
+impl From<price::NormalizedPrice> for NormalizedPriceResponse {
+    fn from(normalized_price: price::NormalizedPrice) -> Self {
+        Self {
+            id: normalized_price.id,
+            date: normalized_price.date,
+            old_price: normalized_price.old_price,
+            new_price: normalized_price.new_price,
+        }
+    }
+}
+
+async fn normalize_prices(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<NormalizePrices>,
+) -> Result<Json<Vec<NormalizedPriceResponse>>, ApiError> {
+    let from = NaiveDate::parse_from_str(&payload.from, "%Y-%m-%d");
+    let to = NaiveDate::parse_from_str(&payload.to, "%Y-%m-%d");
+    let factor = match (from, to, payload.normalization.factor()) {
+        (Ok(from), Ok(to), Some(factor)) if from <= to => factor,
+        (Err(_), _, _) => return Err(ApiError::invalid_field("from", "not a YYYY-MM-DD date")),
+        (_, Err(_), _) => return Err(ApiError::invalid_field("to", "not a YYYY-MM-DD date")),
+        (_, _, None) => {
+            return Err(ApiError::invalid_field(
+                "normalization",
+                "the ratio or rate must be a positive decimal",
+            ))
+        }
+        _ => return Err(ApiError::bad_request("from is after to")),
+    };
+
+    let normalization = price::NormalizePrices {
+        ticker: payload.ticker,
+        from: payload.from,
+        to: payload.to,
+        factor,
+        preview: payload.preview,
+    };
+    let preview = normalization.preview;
+    let normalized_prices = match price::normalize_prices(&pool, normalization).await {
+        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    if !preview {
+        invalidate_portfolio(&pool, cache.as_ref()).await;
+    }
+
+    Ok(Json(normalized_prices))
+}
+
+/// Computes the series of every ticker (or only `ticker`), optionally only from
+/// the trades carrying `tag`, valued at their closes.
+async fn compute_portfolios(
+    pool: &DbPool,
+    ticker: Option<&str>,
+    tag: Option<&str>,
+    forward_fill: bool,
+) -> Result<HashMap<String, Vec<Portfolio>>, ApiError> {
+    // Returns add the dividends received to the value, so it mustn't include
+    // them already like the adjusted close does.
+    compute_valued_portfolios(pool, ticker, tag, forward_fill, valuation::Valuation::Close).await
+}
+
+/// Like `compute_portfolios`, valued by `valuation`. Each ticker runs in its
+/// own task, streaming its price rows from the database into a
+/// `PortfolioBuilder`, so tickers are computed in parallel.
+async fn compute_valued_portfolios(
+    pool: &DbPool,
+    ticker: Option<&str>,
+    tag: Option<&str>,
+    forward_fill: bool,
+    valuation: valuation::Valuation,
+) -> Result<HashMap<String, Vec<Portfolio>>, ApiError> {
+    let trades = match trade::list_trades_for_calculation(pool, ticker, tag).await {
+        Ok(trades) => trades,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let mut trades_by_ticker: HashMap<String, Vec<trade::TradeForCalculation>> = HashMap::new();
+    for trade in trades {
+        trades_by_ticker
+            .entry(trade.ticker.clone())
+            .or_default()
+            .push(trade);
+    }
+
+    let base_currency = base_currency();
+    let tasks = tracked_tickers()
+        .iter()
+        .filter(|t| ticker.is_none_or(|ticker| ticker == **t))
+        .map(|t| {
+            let pool = pool.clone();
+            let base_currency = base_currency.clone();
+            let ticker_trades = trades_by_ticker.remove(*t).unwrap_or_default();
+            tokio::spawn(async move {
+                let strategy = valuation
+                    .for_series(&price::missing_values(&pool, t).await?)
+                    .strategy();
+                let mut builder = portfolio::PortfolioBuilder::new(ticker_trades, forward_fill);
+                let currency = ticker_currency(t);
+                if currency != base_currency {
+                    builder = builder.with_fx_rates(
+                        fx::list_fx_rates_for_calculation(&pool, currency, &base_currency).await?,
+                    );
+                }
+                price::for_each_daily_price(&pool, Some(t), |price| {
+                    builder.push_price(price.date, strategy.unit_price(&price), price.preliminary)
+                })
+                .await?;
+                Ok::<_, sqlx::Error>((t.to_string(), builder))
+            })
+        });
+
+    let mut builders = Vec::new();
+    for result in futures::future::join_all(tasks).await {
+        match result {
+            Ok(Ok(builder)) => builders.push(builder),
+            Ok(Err(e)) => return Err(ApiError::internal(e)),
+            Err(e) => return Err(ApiError::internal(e)),
+        }
+    }
+
+    let last_date = builders
+        .iter()
+        .filter_map(|(_, builder)| builder.last_date())
+        .max();
+    Ok(builders
+        .into_iter()
+        .map(|(ticker, mut builder)| {
+            if let Some(last_date) = last_date {
+                builder.fill_until(last_date);
+            }
+            (ticker, builder.finish())
+        })
+        .collect())
+}
+
+/// The series of every ticker, read from the snapshots of the last price update
+/// when they hold it.
+async fn load_portfolios(
+    pool: &DbPool,
+    tag: Option<&str>,
+    forward_fill: bool,
+    valuation: valuation::Valuation,
+) -> Result<HashMap<String, Vec<Portfolio>>, ApiError> {
+    if tag.is_none() && !forward_fill && valuation == valuation::Valuation::Close {
+        match snapshot::load_snapshots(pool).await {
+            Ok(Some((portfolios, _))) => return Ok(portfolios),
+            Ok(None) => (),
+            Err(e) => return Err(ApiError::internal(e)),
+        }
+    }
+    compute_valued_portfolios(pool, None, tag, forward_fill, valuation).await
+}
+
+/// The total series, read from the snapshots of the last price update when
+/// they hold it.
+async fn load_total_portfolio(
+    pool: &DbPool,
+    tag: Option<&str>,
+    forward_fill: bool,
+    valuation: valuation::Valuation,
+) -> Result<Vec<Portfolio>, ApiError> {
+    if tag.is_none() && !forward_fill && valuation == valuation::Valuation::Close {
+        match snapshot::load_snapshots(pool).await {
+            Ok(Some((_, total))) => return Ok(total),
+            Ok(None) => (),
+            Err(e) => return Err(ApiError::internal(e)),
+        }
+    }
+    Ok(portfolio::total_portfolio(
+        compute_valued_portfolios(pool, None, tag, forward_fill, valuation).await?,
+    ))
+}
+
+/// Restricts an analytics endpoint to the trades carrying `tag`, as if they
+/// were a portfolio of their own.
+#[derive(Deserialize)]
+struct TagFilter {
+    tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PortfolioQuery {
+    #[serde(default)]
+    forward_fill: bool,
+    /// Only return the points after this date.
+    since: Option<NaiveDate>,
+    /// Overrides the valuation set for the portfolio.
+    valuation: Option<valuation::Valuation>,
+}
+
+/// The valuation asked for, or else the one set for the portfolio of the
+/// trades carrying `tag`, or of every trade, or else the close.
+async fn resolve_valuation(
+    pool: &DbPool,
+    tag: Option<&str>,
+    requested: Option<valuation::Valuation>,
+) -> Result<valuation::Valuation, ApiError> {
+    if let Some(valuation) = requested {
+        return Ok(valuation);
+    }
+    valuation::get_portfolio_valuation(pool, tag)
+        .await
+        .map(Option::unwrap_or_default)
+        .map_err(ApiError::internal)
+}
+
+/// Serves the JSON payload stored under `key`, computing and storing it on a
+/// miss.
+async fn cached_json<T, F, Fut>(
+    cache: &dyn ResponseCache,
+    key: String,
+    compute: F,
+) -> Result<Response, ApiError>
+where
+    T: serde::Serialize,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let payload = match cache.get(&key).await {
+        Some(payload) => payload,
+        None => {
+            let generation = cache.generation().await;
+            let payload = serde_json::to_string(&compute().await?).map_err(ApiError::internal)?;
+            cache.insert(key, payload.clone(), generation).await;
+            payload
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "application/json")], payload).into_response())
+}
+
+fn portfolio_cache_key(
+    forward_fill: bool,
+    since: Option<NaiveDate>,
+    tag: Option<&str>,
+    valuation: valuation::Valuation,
+) -> String {
+    let mut key = format!(
+        "portfolio?forward_fill={}&valuation={}",
+        forward_fill,
+        valuation.strategy().name()
+    );
+    if let Some(since) = since {
+        key.push_str(&format!("&since={}", since));
+    }
+    if let Some(tag) = tag {
+        key.push_str(&format!("&tag={}", tag));
+    }
+    key
+}
+
+fn total_portfolio_cache_key(
+    forward_fill: bool,
+    tag: Option<&str>,
+    valuation: valuation::Valuation,
+) -> String {
+    let mut key = format!(
+        "portfolio/total?forward_fill={}&valuation={}",
+        forward_fill,
+        valuation.strategy().name()
+    );
+    if let Some(tag) = tag {
+        key.push_str(&format!("&tag={}", tag));
+    }
+    key
+}
+
+/// Precomputes the portfolio payloads so the first request after a price
+/// update is served from the cache.
+async fn warm_portfolio_cache(pool: &DbPool, cache: &dyn ResponseCache) {
+    let valuation = match resolve_valuation(pool, None, None).await {
+        Ok(valuation) => valuation,
+        Err(e) => {
+            println!("Error warming the portfolio cache {}", e.message);
+            return;
+        }
+    };
+    for forward_fill in [false, true] {
+        let key = portfolio_cache_key(forward_fill, None, None, valuation);
+        let warmed = cached_json(cache, key, || {
+            load_portfolios(pool, None, forward_fill, valuation)
+        })
+        .await;
+        if warmed.is_err() {
+            println!("Error warming the portfolio cache");
+        }
+        let key = total_portfolio_cache_key(forward_fill, None, valuation);
+        let warmed = cached_json(cache, key, || {
+            load_total_portfolio(pool, None, forward_fill, valuation)
+        })
+        .await;
+        if warmed.is_err() {
+            println!("Error warming the total portfolio cache");
+        }
+    }
+}
+
+async fn generate_portfolio(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<PortfolioQuery>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Response, ApiError> {
+    let valuation = resolve_valuation(&pool, filter.tag.as_deref(), query.valuation).await?;
+    cached_json(
+        cache.as_ref(),
+        portfolio_cache_key(
+            query.forward_fill,
+            query.since,
+            filter.tag.as_deref(),
+            valuation,
+        ),
+        || async {
+            let mut portfolios =
+                load_portfolios(&pool, filter.tag.as_deref(), query.forward_fill, valuation)
+                    .await?;
+            if let Some(since) = query.since {
+                for series in portfolios.values_mut() {
+                    series.retain(|day| day.date > since);
+                }
+            }
+            Ok(portfolios)
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum PortfolioEngine {
+    #[default]
+    Rust,
+    Sql,
+}
+
+#[derive(Deserialize)]
+struct TotalPortfolioQuery {
+    #[serde(default)]
+    forward_fill: bool,
+    #[serde(default)]
+    engine: PortfolioEngine,
+    /// Adds the cash balance to the value of the holdings.
+    #[serde(default)]
+    include_cash: bool,
+    /// Overrides the valuation set for the portfolio.
+    valuation: Option<valuation::Valuation>,
+}
+
+async fn generate_total_portfolio(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<TotalPortfolioQuery>,
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+) -> Result<Response, ApiError> {
+    if query.include_cash && filter.tag.is_some() {
+        return Err(ApiError::bad_request(
+            "cash is not tagged, include_cash can't be combined with tag",
+        ));
+    }
+    let valuation = resolve_valuation(&pool, filter.tag.as_deref(), query.valuation).await?;
+    if query.engine == PortfolioEngine::Sql {
+        // The SQL engine doesn't convert prices between currencies.
+        let base_currency = base_currency();
+        let needs_conversion = tracked_tickers()
+            .iter()
+            .any(|ticker| ticker_currency(ticker) != base_currency);
+        if query.forward_fill
+            || filter.tag.is_some()
+            || query.include_cash
+            || valuation != valuation::Valuation::Close
+            || needs_conversion
+        {
+            return Err(ApiError::bad_request(
+                "the sql engine supports no options nor currency conversion",
+            ));
+        }
+        let trades: Vec<_> = trade::list_trades_for_calculation(&pool, None, None)
+            .await
+            .map_err(ApiError::internal)?
+            .into_iter()
+            .filter(|trade| tracked_tickers().contains(&trade.ticker.as_str()))
+            .collect();
+        return match portfolio::total_portfolio_from_sql(&pool, &trades).await {
+            Ok(res) => Ok(Json(res).into_response()),
+            Err(e) => Err(ApiError::internal(e)),
+        };
+    }
+
+    if query.include_cash {
+        return cached_json(
+            cache.as_ref(),
+            format!(
+                "{}&include_cash=true",
+                total_portfolio_cache_key(query.forward_fill, None, valuation)
+            ),
+            || async {
+                let series =
+                    load_total_portfolio(&pool, None, query.forward_fill, valuation).await?;
+                Ok(portfolio::with_cash(series, &all_cash_flows(&pool).await?))
+            },
+        )
+        .await;
+    }
+
+    cached_json(
+        cache.as_ref(),
+        total_portfolio_cache_key(query.forward_fill, filter.tag.as_deref(), valuation),
+        || load_total_portfolio(&pool, filter.tag.as_deref(), query.forward_fill, valuation),
     )
-    .fetch_all(&*pool.0)
     .await
+}
+
+#[derive(serde::Serialize)]
+struct PortfolioValuationResponse {
+    /// `None` for the portfolio of every trade.
+    tag: Option<String>,
+    valuation: valuation::Valuation,
+}
+
+/// Valuations set per portfolio. Portfolios not listed are valued at the
+/// close.
+async fn list_portfolio_valuations(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<PortfolioValuationResponse>>, ApiError> {
+    match valuation::list_portfolio_valuations(&pool).await {
+        Ok(valuations) => Ok(Json(
+            valuations
+                .into_iter()
+                .map(|valuation| PortfolioValuationResponse {
+                    tag: valuation.tag,
+                    valuation: valuation.valuation,
+                })
+                .collect(),
+        )),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct SetPortfolioValuation {
+    /// The portfolio of the trades carrying the tag, of every trade without.
+    tag: Option<String>,
+    valuation: valuation::Valuation,
+}
+
+/// Sets the valuation a portfolio is served with when a request doesn't ask
+/// for one, so each account is valued by its own convention.
+async fn set_portfolio_valuation(
+    pool: Extension<Arc<DbPool>>,
+    cache: Extension<Arc<dyn ResponseCache>>,
+    Json(payload): Json<SetPortfolioValuation>,
+) -> Result<StatusCode, ApiError> {
+    match valuation::set_portfolio_valuation(&pool, payload.tag.as_deref(), payload.valuation).await
+    {
+        Ok(()) => {
+            cache.clear().await;
+            Ok(StatusCode::OK)
+        }
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct InvestedCapitalResponse {
+    date: NaiveDate,
+    invested: BigDecimal,
+    market_value: BigDecimal,
+}
+
+impl From<portfolio::InvestedCapital> for InvestedCapitalResponse {
+    fn from(invested_capital: portfolio::InvestedCapital) -> Self {
+        Self {
+            date: invested_capital.date,
+            invested: invested_capital.invested,
+            market_value: invested_capital.market_value,
+        }
+    }
+}
+
+async fn generate_invested_capital(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<PortfolioQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<InvestedCapitalResponse>>, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let series = portfolio::total_portfolio(
+        compute_valued_portfolios(
+            &pool,
+            None,
+            filter.tag.as_deref(),
+            query.forward_fill,
+            resolve_valuation(&pool, filter.tag.as_deref(), query.valuation).await?,
+        )
+        .await?,
+    );
+
+    Ok(Json(
+        portfolio::invested_capital(&series, &trades)
+            .into_iter()
+            .filter(|day| query.since.is_none_or(|since| day.date > since))
+            .map(InvestedCapitalResponse::from)
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+struct WaterfallQuery {
+    year: i32,
+    #[serde(default)]
+    forward_fill: bool,
+    /// Overrides the valuation set for the portfolio.
+    valuation: Option<valuation::Valuation>,
+}
+
+#[derive(serde::Serialize)]
+struct WaterfallResponse {
+    year: i32,
+    /// Date of the starting value, the last day of the year before.
+    from: NaiveDate,
+    /// Date of the ending value, the last one of the year with a value.
+    to: NaiveDate,
+    starting_value: BigDecimal,
+    contributions: BigDecimal,
+    withdrawals: BigDecimal,
+    market_gains: BigDecimal,
+    dividends: BigDecimal,
+    fees: BigDecimal,
+    ending_value: BigDecimal,
+}
+
+/// What made the portfolio value change over a year, as components adding up
+/// from the starting to the ending value, ready to render as a waterfall. The
+/// values are those of `/portfolio/total` with the same options.
+async fn generate_waterfall(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<WaterfallQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<WaterfallResponse>, ApiError> {
+    let (from, to) = match (
+        NaiveDate::from_ymd_opt(query.year - 1, 12, 31),
+        NaiveDate::from_ymd_opt(query.year, 12, 31),
+    ) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(ApiError::invalid_field("year", "is out of range")),
+    };
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+    let valuation = resolve_valuation(&pool, filter.tag.as_deref(), query.valuation).await?;
+    let series =
+        load_total_portfolio(&pool, filter.tag.as_deref(), query.forward_fill, valuation).await?;
+
+    match report::waterfall(&series, &trades, &dividends, from, to) {
+        Some(waterfall) if waterfall.to > from => Ok(Json(WaterfallResponse {
+            year: query.year,
+            from: waterfall.from,
+            to: waterfall.to,
+            starting_value: waterfall.starting_value,
+            contributions: waterfall.contributions,
+            withdrawals: waterfall.withdrawals,
+            market_gains: waterfall.market_gains,
+            dividends: waterfall.dividends,
+            fees: waterfall.fees,
+            ending_value: waterfall.ending_value,
+        })),
+        _ => Err(ApiError::not_found(format!(
+            "the portfolio has no value in {}",
+            query.year
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct PortfolioValueQuery {
+    date: NaiveDate,
+    #[serde(default)]
+    forward_fill: bool,
+}
+
+#[derive(serde::Serialize)]
+struct TickerValueResponse {
+    ticker: String,
+    amount_in_base_currency: BigDecimal,
+    preliminary: bool,
+}
+
+#[derive(serde::Serialize)]
+struct PortfolioValueResponse {
+    date: NaiveDate,
+    amount_in_base_currency: BigDecimal,
+    preliminary: bool,
+    tickers: Vec<TickerValueResponse>,
+}
+
+/// Value of the portfolio and of each ticker on a single date, the point of
+/// that date in the `/portfolio` series. Without `forward_fill`, only tickers
+/// with a price on the date are valued.
+async fn portfolio_value_on_date(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<PortfolioValueQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<PortfolioValueResponse>, ApiError> {
+    let portfolios =
+        compute_portfolios(&pool, None, filter.tag.as_deref(), query.forward_fill).await?;
+    let mut tickers: Vec<TickerValueResponse> = portfolios
+        .into_iter()
+        .filter_map(|(ticker, series)| {
+            series
+                .into_iter()
+                .find(|day| day.date == query.date)
+                .map(|day| TickerValueResponse {
+                    ticker,
+                    amount_in_base_currency: day.amount_in_base_currency,
+                    preliminary: day.preliminary,
+                })
+        })
+        .collect();
+    if tickers.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "the portfolio has no value on {}",
+            query.date
+        )));
+    }
+    tickers.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+
+    Ok(Json(PortfolioValueResponse {
+        date: query.date,
+        amount_in_base_currency: tickers
+            .iter()
+            .map(|ticker| &ticker.amount_in_base_currency)
+            .sum(),
+        preliminary: tickers.iter().any(|ticker| ticker.preliminary),
+        tickers,
+    }))
+}
+
+async fn generate_ticker_portfolio(
+    Query(filter): Query<TagFilter>,
+    Path(ticker): Path<String>,
+    Query(query): Query<PortfolioQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<Portfolio>>, ApiError> {
+    if !tracked_tickers().contains(&ticker.as_str()) {
+        return Err(ApiError::not_found(format!("{} is not tracked", ticker)));
+    }
+
+    let mut portfolios = compute_valued_portfolios(
+        &pool,
+        Some(&ticker),
+        filter.tag.as_deref(),
+        query.forward_fill,
+        resolve_valuation(&pool, filter.tag.as_deref(), query.valuation).await?,
+    )
+    .await?;
+    let mut series = portfolios.remove(&ticker).unwrap_or_default();
+    if let Some(since) = query.since {
+        series.retain(|day| day.date > since);
+    }
+    Ok(Json(series))
+}
+
+/// Positions of every ticker with trades, in the base currency `trades` were
+/// converted to with `converter`, valued at its latest stored price. Tickers
+/// without any stored price are left out.
+async fn current_positions(
+    pool: &DbPool,
+    converter: &trade::TradeCostConverter,
+    trades: &[trade::TradeForCalculation],
+) -> Result<Vec<(String, position::Position)>, ApiError> {
+    let mut positions = Vec::new();
+    for ticker in tracked_tickers() {
+        let ticker_trades: Vec<trade::TradeForCalculation> = trades
+            .iter()
+            .filter(|trade| trade.ticker == *ticker)
+            .cloned()
+            .collect();
+        if ticker_trades.is_empty() {
+            continue;
+        }
+
+        let latest_price = match price::get_latest_prices(pool, ticker, 1).await {
+            Ok(prices) => match prices.into_iter().next() {
+                Some(latest_price) => latest_price,
+                None => continue,
+            },
+            Err(e) => return Err(ApiError::internal(e)),
+        };
+        let date = NaiveDate::parse_from_str(&latest_price.date, "%Y-%m-%d")
+            .map_err(ApiError::internal)?;
+        let fx_rate = converter.rate(ticker, date)?;
+        let latest_price = BigDecimal::from_str(&latest_price.price).map_err(ApiError::internal)?;
+
+        positions.push((
+            ticker.to_string(),
+            position::calculate_position(&ticker_trades, &latest_price, &fx_rate),
+        ));
+    }
+    Ok(positions)
+}
+
+#[derive(serde::Serialize)]
+struct PositionResponse {
+    ticker: String,
+    crypto: bool,
+    units: BigDecimal,
+    total_cost: BigDecimal,
+    /// Whether the ticker has a stored price. Without one, the position can't
+    /// be valued until prices are updated.
+    price_available: bool,
+    market_value: Option<BigDecimal>,
+    unrealized_gain: Option<BigDecimal>,
+    unrealized_gain_percent: Option<BigDecimal>,
+    target_price: Option<String>,
+    exit_criteria: Option<String>,
+    distance_to_target_percent: Option<BigDecimal>,
+    target_reached_on: Option<String>,
+}
+
+async fn list_positions(
+    Query(filter): Query<TagFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<PositionResponse>>, ApiError> {
+    let converter = trade_cost_converter(&pool).await?;
+    let trades = match trade::list_trades_for_calculation(&pool, None, filter.tag.as_deref()).await
+    {
+        Ok(trades) => converter.convert(trades)?,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    let mut targets: HashMap<String, target::Target> = match target::list_targets(&pool).await {
+        Ok(targets) => targets
+            .into_iter()
+            .map(|target| (target.ticker.clone(), target))
+            .collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    let mut positions: Vec<PositionResponse> = current_positions(&pool, &converter, &trades)
+        .await?
+        .into_iter()
+        .map(|(ticker, position)| {
+            let target = targets.remove(&ticker);
+            let distance_to_target_percent = target
+                .as_ref()
+                .and_then(|target| target.target_price.as_deref())
+                .and_then(|target_price| BigDecimal::from_str(target_price).ok())
+                .filter(|_| position.price != BigDecimal::from(0))
+                .map(|target_price| target::distance_percent(&position.price, &target_price));
+            let (target_price, exit_criteria, target_reached_on) = match target {
+                Some(target) => (target.target_price, target.exit_criteria, target.reached_on),
+                None => (None, None, None),
+            };
+            PositionResponse {
+                crypto: crypto_tickers().contains(&ticker.as_str()),
+                ticker,
+                units: position.units,
+                total_cost: position.total_cost,
+                price_available: true,
+                market_value: Some(position.market_value),
+                unrealized_gain: Some(position.unrealized_gain),
+                unrealized_gain_percent: position.unrealized_gain_percent,
+                target_price,
+                exit_criteria,
+                distance_to_target_percent,
+                target_reached_on,
+            }
+        })
+        .collect();
+
+    for ticker in tracked_tickers() {
+        if positions.iter().any(|position| position.ticker == *ticker) {
+            continue;
+        }
+        let ticker_trades: Vec<trade::TradeForCalculation> = trades
+            .iter()
+            .filter(|trade| trade.ticker == *ticker)
+            .cloned()
+            .collect();
+        if ticker_trades.is_empty() {
+            continue;
+        }
+        let holding = position::calculate_holding(&ticker_trades);
+        let target = targets.remove(*ticker);
+        positions.push(PositionResponse {
+            ticker: ticker.to_string(),
+            crypto: crypto_tickers().contains(ticker),
+            units: holding.units,
+            total_cost: decimal::round_half_up(holding.total_cost, decimal::AMOUNT_SCALE),
+            price_available: false,
+            market_value: None,
+            unrealized_gain: None,
+            unrealized_gain_percent: None,
+            target_price: target
+                .as_ref()
+                .and_then(|target| target.target_price.clone()),
+            exit_criteria: target
+                .as_ref()
+                .and_then(|target| target.exit_criteria.clone()),
+            distance_to_target_percent: None,
+            target_reached_on: target.and_then(|target| target.reached_on),
+        });
+    }
+
+    Ok(Json(positions))
+}
+
+#[derive(Deserialize)]
+struct SaveTarget {
+    target_price: Option<BigDecimal>,
+    exit_criteria: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct TargetResponse {
+    ticker: String,
+    target_price: Option<String>,
+    exit_criteria: Option<String>,
+    reached_on: Option<String>,
+}
+
+impl From<target::Target> for TargetResponse {
+    fn from(target: target::Target) -> Self {
+        Self {
+            ticker: target.ticker,
+            target_price: target.target_price,
+            exit_criteria: target.exit_criteria,
+            reached_on: target.reached_on,
+        }
+    }
+}
+
+/// Sets the target price or exit criteria of a ticker, replacing its previous
+/// target.
+async fn set_target(
+    Path(ticker): Path<String>,
+    pool: Extension<Arc<DbPool>>,
+    Json(payload): Json<SaveTarget>,
+) -> Result<StatusCode, ApiError> {
+    if !tracked_tickers().contains(&ticker.as_str()) {
+        return Err(ApiError::not_found(format!("{} is not tracked", ticker)));
+    }
+    if payload.target_price.is_none() && payload.exit_criteria.is_none() {
+        return Err(ApiError::bad_request(
+            "send a target_price, exit_criteria or both",
+        ));
+    }
+    if payload
+        .target_price
+        .as_ref()
+        .is_some_and(|target_price| *target_price <= BigDecimal::from(0))
+    {
+        return Err(ApiError::invalid_field("target_price", "must be positive"));
+    }
+    let target = target::SaveTarget {
+        target_price: payload
+            .target_price
+            .map(|target_price| target_price.to_string()),
+        exit_criteria: payload.exit_criteria,
+    };
+    match target::set_target(&pool, &ticker, target).await {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+async fn list_targets(pool: Extension<Arc<DbPool>>) -> Result<Json<Vec<TargetResponse>>, ApiError> {
+    match target::list_targets(&pool).await {
+        Ok(targets) => Ok(Json(targets.into_iter().map(|x| x.into()).collect())),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+async fn delete_target(
+    Path(ticker): Path<String>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<StatusCode, ApiError> {
+    match target::delete_target(&pool, &ticker).await {
+        Ok(1) => Ok(StatusCode::OK),
+        Ok(_) => Err(ApiError::not_found(format!("{} has no target", ticker))),
+        Err(e) => Err(ApiError::internal(e)),
+    }
+}
+
+/// Logs an alert for every target price the latest price reached since the
+/// last check, and marks the target reached so it alerts once.
+async fn check_targets(pool: &DbPool) -> Result<(), ApiError> {
+    let targets = target::list_targets(pool)
+        .await
+        .map_err(ApiError::internal)?;
+    let today = Utc::today().naive_utc();
+    for target in targets {
+        if target.reached_on.is_some() {
+            continue;
+        }
+        let target_price = match target.target_price.as_deref().map(BigDecimal::from_str) {
+            Some(Ok(target_price)) => target_price,
+            _ => continue,
+        };
+        let latest_price = match price::get_latest_price(pool, &target.ticker).await {
+            Ok(Some(latest_price)) => {
+                BigDecimal::from_str(&latest_price).map_err(ApiError::internal)?
+            }
+            Ok(None) => continue,
+            Err(e) => return Err(ApiError::internal(e)),
+        };
+        if latest_price >= target_price {
+            println!(
+                "Target reached for {}: price {} is at or above {}{}",
+                target.ticker,
+                latest_price,
+                target_price,
+                target
+                    .exit_criteria
+                    .as_deref()
+                    .map(|criteria| format!(", exit criteria: {}", criteria))
+                    .unwrap_or_default()
+            );
+            target::mark_reached(pool, &target.ticker, today)
+                .await
+                .map_err(ApiError::internal)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct AllocationResponse {
+    ticker: String,
+    crypto: bool,
+    market_value: BigDecimal,
+    weight_percent: Option<BigDecimal>,
+}
+
+impl From<position::Allocation> for AllocationResponse {
+    fn from(allocation: position::Allocation) -> Self {
+        Self {
+            crypto: crypto_tickers().contains(&allocation.ticker.as_str()),
+            ticker: allocation.ticker,
+            market_value: allocation.market_value,
+            weight_percent: allocation.weight_percent,
+        }
+    }
+}
+
+async fn list_allocation(
+    Query(filter): Query<TagFilter>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<AllocationResponse>>, ApiError> {
+    let converter = trade_cost_converter(&pool).await?;
+    let trades = match trade::list_trades_for_calculation(&pool, None, filter.tag.as_deref()).await
     {
+        Ok(trades) => converter.convert(trades)?,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    let positions = current_positions(&pool, &converter, &trades).await?;
+    Ok(Json(
+        position::allocation(&positions)
+            .into_iter()
+            .map(AllocationResponse::from)
+            .collect(),
+    ))
+}
+
+#[derive(serde::Serialize)]
+struct RealizedGainResponse {
+    trade_id: i64,
+    ticker: String,
+    date: NaiveDate,
+    units: BigDecimal,
+    proceeds: BigDecimal,
+    cost: BigDecimal,
+    gain: BigDecimal,
+    unmatched_units: BigDecimal,
+}
+
+impl From<report::RealizedGain> for RealizedGainResponse {
+    fn from(realized_gain: report::RealizedGain) -> Self {
+        Self {
+            trade_id: realized_gain.trade_id,
+            ticker: realized_gain.ticker,
+            date: realized_gain.date,
+            units: realized_gain.units,
+            proceeds: realized_gain.proceeds,
+            cost: realized_gain.cost,
+            gain: realized_gain.gain,
+            unmatched_units: realized_gain.unmatched_units,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: export::ExportFormat,
+}
+
+/// Renders a report in the format asked for with `?format=`. Anything but
+/// JSON is served as a file to download.
+fn export_report<T: serde::Serialize>(
+    report: &str,
+    format: export::ExportFormat,
+    response: &T,
+) -> Result<Response, ApiError> {
+    let exporter = format.exporter();
+    let output = serde_json::to_value(response)
+        .map_err(|e| e.into())
+        .and_then(|value| exporter.export(report, &value))
+        .map_err(|e: export::ExportError| {
+            ApiError::internal(format!("exporting report {}: {}", report, e))
+        })?;
+    if format == export::ExportFormat::Json {
+        return Ok(([(header::CONTENT_TYPE, exporter.content_type())], output).into_response());
+    }
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"",
+        report,
+        exporter.file_extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, exporter.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        output,
+    )
+        .into_response())
+}
+
+#[derive(serde::Serialize)]
+struct YearlyRealizedGainResponse {
+    year: i32,
+    gain: BigDecimal,
+    dividends: BigDecimal,
+}
+
+#[derive(serde::Serialize)]
+struct RealizedGainsReportResponse {
+    trades: Vec<RealizedGainResponse>,
+    years: Vec<YearlyRealizedGainResponse>,
+}
+
+fn empty_year(year: i32) -> YearlyRealizedGainResponse {
+    YearlyRealizedGainResponse {
+        year,
+        gain: BigDecimal::from(0),
+        dividends: BigDecimal::from(0),
+    }
+}
+
+/// Dividends paid up to today, in the base currency at the rate of their pay
+/// date. Dividends are not tagged, so a tagged virtual portfolio has none.
+/// Those recorded for accumulating funds are left out: their prices include
+/// the income already.
+async fn received_dividends(
+    pool: &DbPool,
+    tag: Option<&str>,
+) -> Result<Vec<dividend::DividendForCalculation>, ApiError> {
+    if tag.is_some() {
+        return Ok(Vec::new());
+    }
+    let dividends: Vec<_> =
+        dividend::list_dividends_for_calculation(pool, Utc::today().naive_utc())
+            .await
+            .map_err(ApiError::internal)?
+            .into_iter()
+            .filter(|dividend| !accumulating_tickers().contains(&dividend.ticker.as_str()))
+            .collect();
+    let base_currency = base_currency();
+    let mut fx_rates = HashMap::new();
+    for currency in dividends.iter().filter_map(|d| d.currency.as_deref()) {
+        if currency != base_currency && !fx_rates.contains_key(currency) {
+            let rates = fx::list_fx_rates_for_calculation(pool, currency, &base_currency)
+                .await
+                .map_err(ApiError::internal)?;
+            fx_rates.insert(currency.to_string(), rates);
+        }
+    }
+    Ok(dividend::in_base_currency(
+        dividends,
+        &base_currency,
+        &fx_rates,
+    )?)
+}
+
+async fn realized_gains_report(
+    Query(filter): Query<TagFilter>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+
+    export_report(
+        REALIZED_GAINS_REPORT,
+        export.format,
+        &build_realized_gains_report(&trades, &dividends),
+    )
+}
+
+fn build_realized_gains_report(
+    trades: &[trade::TradeForCalculation],
+    dividends: &[dividend::DividendForCalculation],
+) -> RealizedGainsReportResponse {
+    let realized_gains = report::realized_gains(trades);
+    let mut years: BTreeMap<i32, YearlyRealizedGainResponse> = BTreeMap::new();
+    for (year, gain) in report::realized_gains_per_year(&realized_gains) {
+        years.entry(year).or_insert_with(|| empty_year(year)).gain = gain;
+    }
+    for (year, dividends) in report::dividends_per_year(dividends) {
+        years
+            .entry(year)
+            .or_insert_with(|| empty_year(year))
+            .dividends = decimal::round_half_up(dividends, decimal::AMOUNT_SCALE);
+    }
+    let years = years.into_values().collect();
+
+    RealizedGainsReportResponse {
+        trades: realized_gains.into_iter().map(|x| x.into()).collect(),
+        years,
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ArchivedReportResponse {
+    id: i64,
+    report: String,
+    created_at: String,
+    inputs_hash: String,
+}
+
+impl From<archive::ArchivedReport> for ArchivedReportResponse {
+    fn from(archived_report: archive::ArchivedReport) -> Self {
+        Self {
+            id: archived_report.id,
+            report: archived_report.report,
+            created_at: archived_report.created_at,
+            inputs_hash: archived_report.inputs_hash,
+        }
+    }
+}
+
+const REALIZED_GAINS_REPORT: &str = "realized-gains";
+
+/// Generates the realized gains report of the whole portfolio and keeps an
+/// immutable copy of it, together with a hash of its inputs.
+async fn archive_realized_gains_report(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<ArchivedReportResponse>, ApiError> {
+    let trades = match trade::list_trades_for_calculation(&pool, None, None).await {
+        Ok(trades) => trades,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+    let dividends = received_dividends(&pool, None).await?;
+
+    let converted_trades = trade_cost_converter(&pool).await?.convert(trades.clone())?;
+    let output = serde_json::to_string(&build_realized_gains_report(&converted_trades, &dividends))
+        .map_err(ApiError::internal)?;
+    let created_at = Utc::now().to_rfc3339();
+    let inputs_hash = archive::inputs_hash(&trades, &dividends);
+    let archive_report = archive::ArchiveReport {
+        report: REALIZED_GAINS_REPORT.to_string(),
+        created_at: created_at.clone(),
+        inputs_hash: inputs_hash.clone(),
+        output,
+    };
+    let id = match archive::archive_report(&pool, archive_report).await {
         Ok(res) => res,
-        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(ArchivedReportResponse {
+        id,
+        report: REALIZED_GAINS_REPORT.to_string(),
+        created_at,
+        inputs_hash,
+    }))
+}
+
+async fn list_archived_reports(
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Json<Vec<ArchivedReportResponse>>, ApiError> {
+    let archived_reports = match archive::list_archived_reports(&pool).await {
+        Ok(res) => res.into_iter().map(|x| x.into()).collect(),
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    Ok(Json(archived_reports))
+}
+
+/// Serves an archived report exactly as it was rendered, or converted to
+/// another format.
+async fn get_archived_report(
+    Path(archive_id): Path<i64>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    match archive::get_archived_report_output(&pool, archive_id).await {
+        Ok(Some(output)) if export.format == export::ExportFormat::Json => {
+            Ok(([(header::CONTENT_TYPE, "application/json")], output).into_response())
+        }
+        Ok(Some(output)) => {
+            let report: serde_json::Value =
+                serde_json::from_str(&output).map_err(ApiError::internal)?;
+            export_report(
+                &format!("archived-report-{}", archive_id),
+                export.format,
+                &report,
+            )
+        }
+        Ok(None) => Err(ApiError::not_found(format!(
+            "archived report {} not found",
+            archive_id
+        ))),
+        Err(e) => Err(ApiError::internal(e)),
     }
-    .iter()
-    .map(|row| DailyPrice {
-        price: BigDecimal::from_str(&row.price).unwrap(),
-        date: NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap(),
-        ticker: row.ticker.clone(),
-    })
+}
+
+#[derive(serde::Serialize)]
+struct XirrReportResponse {
+    current_value: BigDecimal,
+    xirr_percent: Option<BigDecimal>,
+}
+
+async fn xirr_report(
+    Query(filter): Query<TagFilter>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let converter = trade_cost_converter(&pool).await?;
+    let trades = match trade::list_trades_for_calculation(&pool, None, filter.tag.as_deref()).await
+    {
+        Ok(trades) => converter.convert(trades)?,
+        Err(e) => return Err(ApiError::internal(e)),
+    };
+
+    let current_value: BigDecimal = current_positions(&pool, &converter, &trades)
+        .await?
+        .into_iter()
+        .map(|(_, position)| position.market_value)
+        .sum();
+
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+
+    let mut cash_flows = report::trade_cash_flows(&trades);
+    cash_flows.extend(report::dividend_cash_flows(&dividends));
+    cash_flows.push(xirr::CashFlow {
+        date: Utc::today().naive_utc(),
+        amount: current_value.to_f64().unwrap_or_default(),
+    });
+    let xirr_percent = xirr::xirr(&cash_flows).and_then(percent);
+
+    export_report(
+        "xirr",
+        export.format,
+        &XirrReportResponse {
+            current_value,
+            xirr_percent,
+        },
+    )
+}
+
+#[derive(Deserialize)]
+struct TwrQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(serde::Serialize)]
+struct TwrReportResponse {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    twr_percent: Option<BigDecimal>,
+}
+
+async fn twr_report(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<TwrQuery>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let series: Vec<Portfolio> = portfolio::total_portfolio(
+        compute_portfolios(&pool, None, filter.tag.as_deref(), true).await?,
+    )
+    .into_iter()
+    .filter(|day| query.from.is_none_or(|from| day.date >= from))
+    .filter(|day| query.to.is_none_or(|to| day.date <= to))
     .collect();
 
-    let mut response_map = HashMap::new();
-    for ticker in TICKERS {
-        response_map.insert(
-            ticker.to_string(),
-            build_porfolio(
-                prices
-                    .clone() //not a good idea because we create a lot of clones of the same big Vec
-                    .into_iter()
-                    .filter(|price| price.ticker == ticker.to_string())
-                    .collect(),
-                trades
-                    .clone() //not a good idea because we create a lot of clones of the same big Vec
-                    .into_iter()
-                    .filter(|trade| trade.ticker == ticker.to_string())
-                    .collect(),
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+
+    let twr_percent = report::time_weighted_return(&series, &trades, &dividends).and_then(percent);
+
+    export_report(
+        "twr",
+        export.format,
+        &TwrReportResponse {
+            from: series.first().map(|day| day.date),
+            to: series.last().map(|day| day.date),
+            twr_percent,
+        },
+    )
+}
+
+#[derive(Deserialize)]
+struct CashFlowStatementQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(serde::Serialize)]
+struct StatementEntryResponse {
+    date: NaiveDate,
+    kind: &'static str,
+    ticker: String,
+    amount: BigDecimal,
+    balance: BigDecimal,
+}
+
+#[derive(serde::Serialize)]
+struct CashFlowStatementResponse {
+    /// Balance of the flows before `from`.
+    opening_balance: BigDecimal,
+    entries: Vec<StatementEntryResponse>,
+}
+
+async fn cash_flow_statement_report(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<CashFlowStatementQuery>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+
+    let mut opening_balance = decimal::round_half_up(BigDecimal::from(0), decimal::AMOUNT_SCALE);
+    let mut entries = Vec::new();
+    for entry in report::cash_flow_statement(&trades, &dividends) {
+        if query.to.is_some_and(|to| entry.date > to) {
+            break;
+        }
+        if query.from.is_some_and(|from| entry.date < from) {
+            opening_balance += entry.amount;
+            continue;
+        }
+        let balance = entries
+            .last()
+            .map_or(&opening_balance, |last: &StatementEntryResponse| {
+                &last.balance
+            })
+            + &entry.amount;
+        entries.push(StatementEntryResponse {
+            date: entry.date,
+            kind: match entry.kind {
+                report::StatementEntryKind::Buy => "BUY",
+                report::StatementEntryKind::Sell => "SELL",
+                report::StatementEntryKind::Dividend => "DIVIDEND",
+            },
+            ticker: entry.ticker,
+            amount: entry.amount,
+            balance,
+        });
+    }
+
+    export_report(
+        "cashflows",
+        export.format,
+        &CashFlowStatementResponse {
+            opening_balance,
+            entries,
+        },
+    )
+}
+
+fn percent(rate: f64) -> Option<BigDecimal> {
+    BigDecimal::from_f64(rate * 100.0)
+        .map(|percent| decimal::round_half_up(percent, decimal::AMOUNT_SCALE))
+}
+
+#[derive(serde::Serialize)]
+struct PeriodReturnResponse {
+    window: &'static str,
+    from: NaiveDate,
+    to: NaiveDate,
+    absolute_return: BigDecimal,
+    return_percent: Option<BigDecimal>,
+}
+
+#[derive(serde::Serialize)]
+struct SummaryReportResponse {
+    windows: Vec<PeriodReturnResponse>,
+    cagr_percent: Option<BigDecimal>,
+}
+
+async fn summary_report(
+    Query(filter): Query<TagFilter>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+    let series = portfolio::total_portfolio(
+        compute_portfolios(&pool, None, filter.tag.as_deref(), true).await?,
+    );
+    let last_date = match series.last() {
+        Some(last_day) => last_day.date,
+        None => {
+            return export_report(
+                "summary",
+                export.format,
+                &SummaryReportResponse {
+                    windows: Vec::new(),
+                    cagr_percent: None,
+                },
             )
-            .await,
-        );
+        }
+    };
+
+    let window_starts = [
+        ("ytd", NaiveDate::from_ymd(last_date.year(), 1, 1).pred()),
+        ("1y", report::years_before(last_date, 1)),
+        ("3y", report::years_before(last_date, 3)),
+        ("5y", report::years_before(last_date, 5)),
+    ];
+    let windows = window_starts
+        .into_iter()
+        .filter_map(|(window, from)| {
+            report::period_return(&series, &trades, &dividends, from).map(|period_return| {
+                PeriodReturnResponse {
+                    window,
+                    from: period_return.from,
+                    to: period_return.to,
+                    absolute_return: period_return.absolute_return,
+                    return_percent: period_return.time_weighted_return.and_then(percent),
+                }
+            })
+        })
+        .collect();
+
+    export_report(
+        "summary",
+        export.format,
+        &SummaryReportResponse {
+            windows,
+            cagr_percent: report::cagr(&series, &trades, &dividends).and_then(percent),
+        },
+    )
+}
+
+#[derive(serde::Serialize)]
+struct DrawdownResponse {
+    max_drawdown_percent: Option<BigDecimal>,
+    peak_date: NaiveDate,
+    trough_date: NaiveDate,
+    longest_duration_days: i64,
+}
+
+#[derive(serde::Serialize)]
+struct RiskReportResponse {
+    drawdown: Option<DrawdownResponse>,
+    annualized_volatility_percent: Option<BigDecimal>,
+    risk_free_rate_percent: f64,
+    sharpe_ratio: Option<BigDecimal>,
+    sortino_ratio: Option<BigDecimal>,
+}
+
+#[derive(Deserialize)]
+struct RiskReportQuery {
+    /// Annual risk-free rate used by the Sharpe and Sortino ratios.
+    #[serde(default)]
+    risk_free_rate_percent: f64,
+}
+
+fn ratio(value: f64) -> Option<BigDecimal> {
+    BigDecimal::from_f64(value).map(|ratio| decimal::round_half_up(ratio, decimal::AMOUNT_SCALE))
+}
+
+async fn risk_report(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<RiskReportQuery>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+    let series = portfolio::total_portfolio(
+        compute_portfolios(&pool, None, filter.tag.as_deref(), true).await?,
+    );
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+    let index = report::growth_index(&series, &trades, &dividends);
+    let returns = report::daily_returns(&index);
+    let risk_free_rate = query.risk_free_rate_percent / 100.0;
+
+    export_report(
+        "risk",
+        export.format,
+        &RiskReportResponse {
+            drawdown: report::drawdown(&index).map(|drawdown| DrawdownResponse {
+                max_drawdown_percent: percent(drawdown.max_drawdown),
+                peak_date: drawdown.peak_date,
+                trough_date: drawdown.trough_date,
+                longest_duration_days: drawdown.longest_duration_days,
+            }),
+            annualized_volatility_percent: report::annualized_volatility(&returns)
+                .and_then(percent),
+            risk_free_rate_percent: query.risk_free_rate_percent,
+            sharpe_ratio: report::sharpe_ratio(&returns, risk_free_rate).and_then(ratio),
+            sortino_ratio: report::sortino_ratio(&returns, risk_free_rate).and_then(ratio),
+        },
+    )
+}
+
+/// Default of how far, as a percentage of the usual amount, a month's
+/// contributions can be from it and still be on schedule.
+const DEFAULT_CONTRIBUTION_TOLERANCE_PERCENT: u32 = 20;
+
+/// Latest day a month can start on, as every month has it.
+const MAX_MONTH_START_DAY: u32 = 28;
+
+#[derive(Deserialize)]
+struct ContributionsQuery {
+    tolerance_percent: Option<BigDecimal>,
+    /// Day months start on, for contributions that follow a salary paid on
+    /// another day than the 1st.
+    month_start_day: Option<u32>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ContributionStatusResponse {
+    OnSchedule,
+    Skipped,
+    Deviated,
+}
+
+#[derive(serde::Serialize)]
+struct MonthlyContributionResponse {
+    month: String,
+    starts_on: NaiveDate,
+    contributed: BigDecimal,
+    status: ContributionStatusResponse,
+}
+
+#[derive(serde::Serialize)]
+struct ContributionsReportResponse {
+    /// Usual amount contributed a month, `None` when no pattern was found.
+    amount: Option<BigDecimal>,
+    day_of_month: Option<u32>,
+    months: Vec<MonthlyContributionResponse>,
+}
+
+async fn contributions_report(
+    Query(filter): Query<TagFilter>,
+    Query(query): Query<ContributionsQuery>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let tolerance_percent = query
+        .tolerance_percent
+        .unwrap_or_else(|| BigDecimal::from(DEFAULT_CONTRIBUTION_TOLERANCE_PERCENT));
+    if tolerance_percent.is_negative() {
+        return Err(ApiError::bad_request("tolerance_percent can't be negative"));
+    }
+    let month_start_day = query.month_start_day.unwrap_or(1);
+    if !(1..=MAX_MONTH_START_DAY).contains(&month_start_day) {
+        return Err(ApiError::bad_request(format!(
+            "month_start_day must be between 1 and {}",
+            MAX_MONTH_START_DAY
+        )));
+    }
+    let trades = trades_in_base_currency(&pool, filter.tag.as_deref()).await?;
+
+    let response = match report::contribution_schedule(
+        &trades,
+        &tolerance_percent,
+        month_start_day,
+        Utc::today().naive_utc(),
+    ) {
+        Some(schedule) => ContributionsReportResponse {
+            amount: Some(schedule.amount),
+            day_of_month: Some(schedule.day_of_month),
+            months: schedule
+                .months
+                .into_iter()
+                .map(|month| MonthlyContributionResponse {
+                    month: month.month.format("%Y-%m").to_string(),
+                    starts_on: month.month,
+                    contributed: month.contributed,
+                    status: match month.status {
+                        report::ContributionStatus::OnSchedule => {
+                            ContributionStatusResponse::OnSchedule
+                        }
+                        report::ContributionStatus::Skipped => ContributionStatusResponse::Skipped,
+                        report::ContributionStatus::Deviated => {
+                            ContributionStatusResponse::Deviated
+                        }
+                    },
+                })
+                .collect(),
+        },
+        None => ContributionsReportResponse {
+            amount: None,
+            day_of_month: None,
+            months: Vec::new(),
+        },
+    };
+    export_report("contributions", export.format, &response)
+}
+
+#[derive(serde::Serialize)]
+struct TickerIncomeResponse {
+    ticker: String,
+    /// Its income is in its price, so it is not counted.
+    accumulating: bool,
+    units: BigDecimal,
+    received_last_year: BigDecimal,
+    expected_next_year: BigDecimal,
+}
+
+#[derive(serde::Serialize)]
+struct IncomeReportResponse {
+    received_last_year: BigDecimal,
+    expected_next_year: BigDecimal,
+    tickers: Vec<TickerIncomeResponse>,
+}
+
+/// Dividend income received over the last year and expected over the next
+/// one at the same dividends per unit. Accumulating funds are expected none.
+async fn income_report(
+    Query(filter): Query<TagFilter>,
+    Query(export): Query<ExportQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let trades = trade::list_trades_for_calculation(&pool, None, filter.tag.as_deref())
+        .await
+        .map_err(ApiError::internal)?;
+    let dividends = received_dividends(&pool, filter.tag.as_deref()).await?;
+
+    let tickers: Vec<TickerIncomeResponse> =
+        report::expected_income(&trades, &dividends, Utc::today().naive_utc())
+            .into_iter()
+            .map(|income| TickerIncomeResponse {
+                accumulating: accumulating_tickers().contains(&income.ticker.as_str()),
+                ticker: income.ticker,
+                units: income.units,
+                received_last_year: income.received,
+                expected_next_year: income.expected,
+            })
+            .collect();
+    let response = IncomeReportResponse {
+        received_last_year: tickers
+            .iter()
+            .map(|ticker| &ticker.received_last_year)
+            .sum(),
+        expected_next_year: tickers
+            .iter()
+            .map(|ticker| &ticker.expected_next_year)
+            .sum(),
+        tickers,
+    };
+    export_report("income", export.format, &response)
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum WidgetFormat {
+    #[default]
+    Json,
+    Text,
+}
+
+#[derive(Deserialize)]
+struct WidgetQuery {
+    #[serde(default)]
+    format: WidgetFormat,
+}
+
+#[derive(serde::Serialize)]
+struct TopMoverResponse {
+    ticker: String,
+    change_percent: BigDecimal,
+}
+
+#[derive(serde::Serialize)]
+struct WidgetResponse {
+    date: Option<NaiveDate>,
+    total: Option<BigDecimal>,
+    day_change: Option<BigDecimal>,
+    day_change_percent: Option<BigDecimal>,
+    top_mover: Option<TopMoverResponse>,
+    /// Some traded ticker has no price yet, so the total leaves it out until
+    /// prices are updated.
+    price_update_needed: bool,
+}
+
+impl WidgetResponse {
+    /// A few short lines, for displays that can't parse JSON.
+    fn to_text(&self) -> String {
+        let mut text = match (self.date, &self.total) {
+            (Some(date), Some(total)) => format!("{}\nTotal {} {}\n", date, total, base_currency()),
+            _ => "No prices\n".to_string(),
+        };
+        if let Some(day_change) = &self.day_change {
+            text.push_str(&format!("Day {}", signed(day_change)));
+            if let Some(day_change_percent) = &self.day_change_percent {
+                text.push_str(&format!(" {}%", signed(day_change_percent)));
+            }
+            text.push('\n');
+        }
+        if let Some(top_mover) = &self.top_mover {
+            text.push_str(&format!(
+                "Top {} {}%\n",
+                top_mover.ticker,
+                signed(&top_mover.change_percent)
+            ));
+        }
+        if self.price_update_needed {
+            text.push_str("Update prices\n");
+        }
+        text
+    }
+}
+
+fn signed(value: &BigDecimal) -> String {
+    if value.is_negative() {
+        value.to_string()
+    } else {
+        format!("+{}", value)
+    }
+}
+
+/// Change of the latest of `prices` (newest first) from the one before, as a
+/// percentage.
+fn latest_price_change_percent(
+    prices: &[price::StoredPrice],
+) -> Result<Option<BigDecimal>, ApiError> {
+    let (latest, previous) = match prices {
+        [latest, previous] => (
+            BigDecimal::from_str(&latest.price).map_err(ApiError::internal)?,
+            BigDecimal::from_str(&previous.price).map_err(ApiError::internal)?,
+        ),
+        _ => return Ok(None),
+    };
+    if previous.is_zero() {
+        return Ok(None);
+    }
+    Ok(Some(decimal::round_half_up(
+        (latest - &previous) * BigDecimal::from(100) / previous,
+        decimal::AMOUNT_SCALE,
+    )))
+}
+
+/// Total value, its change from the previous day and the ticker whose price
+/// moved the most, small enough for low-powered displays.
+async fn widget(
+    Query(query): Query<WidgetQuery>,
+    pool: Extension<Arc<DbPool>>,
+) -> Result<Response, ApiError> {
+    let series = portfolio::total_portfolio(compute_portfolios(&pool, None, None, true).await?);
+    let mut last_days = series.iter().rev();
+    let last_day = last_days.next();
+    let previous_day = last_days.next();
+
+    let day_change = last_day.zip(previous_day).map(|(last_day, previous_day)| {
+        &last_day.amount_in_base_currency - &previous_day.amount_in_base_currency
+    });
+    let day_change_percent = day_change
+        .as_ref()
+        .zip(previous_day)
+        .filter(|(_, previous_day)| !previous_day.amount_in_base_currency.is_zero())
+        .map(|(day_change, previous_day)| {
+            decimal::round_half_up(
+                day_change * BigDecimal::from(100) / &previous_day.amount_in_base_currency,
+                decimal::AMOUNT_SCALE,
+            )
+        });
+
+    let traded_tickers: Vec<String> = trade::list_trades_for_calculation(&pool, None, None)
+        .await
+        .map_err(ApiError::internal)?
+        .into_iter()
+        .map(|trade| trade.ticker)
+        .collect();
+    let mut price_update_needed = false;
+    let mut top_mover: Option<TopMoverResponse> = None;
+    for ticker in tracked_tickers() {
+        let prices = price::get_latest_prices(&pool, ticker, 2)
+            .await
+            .map_err(ApiError::internal)?;
+        if prices.is_empty() && traded_tickers.iter().any(|traded| traded == ticker) {
+            price_update_needed = true;
+        }
+        if let Some(change_percent) = latest_price_change_percent(&prices)? {
+            if top_mover
+                .as_ref()
+                .is_none_or(|top_mover| change_percent.abs() > top_mover.change_percent.abs())
+            {
+                top_mover = Some(TopMoverResponse {
+                    ticker: ticker.to_string(),
+                    change_percent,
+                });
+            }
+        }
     }
 
-    Ok(Json(response_map))
+    let response = WidgetResponse {
+        date: last_day.map(|last_day| last_day.date),
+        total: last_day.map(|last_day| {
+            decimal::round_half_up(
+                last_day.amount_in_base_currency.clone(),
+                decimal::AMOUNT_SCALE,
+            )
+        }),
+        day_change: day_change
+            .map(|day_change| decimal::round_half_up(day_change, decimal::AMOUNT_SCALE)),
+        day_change_percent,
+        top_mover,
+        price_update_needed,
+    };
+    Ok(match query.format {
+        WidgetFormat::Json => Json(response).into_response(),
+        WidgetFormat::Text => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            response.to_text(),
+        )
+            .into_response(),
+    })
 }
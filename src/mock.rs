@@ -0,0 +1,51 @@
+use chrono::{Datelike, NaiveDate, Weekday};
+
+/// Date the synthetic series start at.
+pub fn first_date() -> NaiveDate {
+    NaiveDate::from_ymd(2015, 1, 2)
+}
+
+/// FNV-1a hash of `key`, so every key gets its own series.
+fn seed(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Next number of the SplitMix64 sequence, mapped to `[0, 1)`.
+fn next_unit(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Random walk from `first_date` to `until`, on weekdays or every day
+/// `with_weekends`. It starts within `initial` and moves by up to
+/// `daily_volatility` of its value a day. The walk only depends on `key`, so
+/// the value of a date never changes between calls.
+pub fn random_walk(
+    key: &str,
+    until: NaiveDate,
+    with_weekends: bool,
+    initial: (f64, f64),
+    daily_volatility: f64,
+) -> Vec<(NaiveDate, f64)> {
+    let mut state = seed(key);
+    let (low, high) = initial;
+    let mut value = low + (high - low) * next_unit(&mut state);
+    let mut walk = Vec::new();
+    let mut date = first_date();
+    while date <= until {
+        if with_weekends || !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            walk.push((date, value));
+            // Slightly biased upwards, like a market.
+            let change = (next_unit(&mut state) * 2.0 - 0.98) * daily_volatility;
+            value *= 1.0 + change;
+        }
+        date = date.succ();
+    }
+    walk
+}
@@ -0,0 +1,659 @@
+use crate::db::{Db, DbPool};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Transaction;
+
+/// Days a destructive operation can be undone for; older ones are dropped.
+pub const RETENTION_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedPrice {
+    pub ticker: String,
+    pub date: String,
+    pub price: String,
+    pub preliminary: bool,
+    pub source: Option<String>,
+    pub adjusted_price: Option<String>,
+    pub split_coefficient: Option<String>,
+    pub pinned: bool,
+    pub nav: Option<String>,
+    pub bid: Option<String>,
+    pub ask: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedQuote {
+    pub ticker: String,
+    pub date: String,
+    pub source: String,
+    pub price: String,
+    pub preliminary: bool,
+    pub adjusted_price: Option<String>,
+    pub split_coefficient: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedTrade {
+    pub id: i64,
+    pub ticker: String,
+    pub date: String,
+    pub r#type: String,
+    pub amount: String,
+    pub price: String,
+    pub fee: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Rows a destructive operation removed or changed, kept to put them back.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Operation {
+    DeletePrices {
+        prices: Vec<DeletedPrice>,
+        quotes: Vec<DeletedQuote>,
+    },
+    DeleteTrade {
+        trade: DeletedTrade,
+    },
+    /// The prices and quotes as they were before they were normalized.
+    NormalizePrices {
+        prices: Vec<DeletedPrice>,
+        quotes: Vec<DeletedQuote>,
+    },
+    /// The trade as it was before the split, and the lots added next to it.
+    SplitTrade {
+        trade: DeletedTrade,
+        lot_ids: Vec<i64>,
+    },
+    /// The trades as they were before the merge, the remaining one first.
+    MergeTrades {
+        trades: Vec<DeletedTrade>,
+    },
+}
+
+impl Operation {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Operation::DeletePrices { .. } => "delete_prices",
+            Operation::DeleteTrade { .. } => "delete_trade",
+            Operation::NormalizePrices { .. } => "normalize_prices",
+            Operation::SplitTrade { .. } => "split_trade",
+            Operation::MergeTrades { .. } => "merge_trades",
+        }
+    }
+
+    /// Rows the operation removed or changed.
+    pub fn rows(&self) -> usize {
+        match self {
+            Operation::DeletePrices { prices, .. } | Operation::NormalizePrices { prices, .. } => {
+                prices.len()
+            }
+            Operation::DeleteTrade { .. } | Operation::SplitTrade { .. } => 1,
+            Operation::MergeTrades { trades } => trades.len(),
+        }
+    }
+}
+
+/// Records `operation` within the transaction that carries it out and returns
+/// its id. Operations past the retention window are dropped along the way.
+pub async fn record_operation(
+    tx: &mut Transaction<'_, Db>,
+    operation: &Operation,
+) -> Result<i64, sqlx::Error> {
+    let now = Utc::now();
+    let expired_before = (now - Duration::days(RETENTION_DAYS)).to_rfc3339();
+    sqlx::query!(
+        r#"
+        DELETE FROM operations WHERE created_at < $1
+        "#,
+        expired_before
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let kind = operation.kind();
+    let created_at = now.to_rfc3339();
+    let payload =
+        serde_json::to_string(operation).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO operations ( kind, created_at, payload ) VALUES ( $1, $2, $3 )
+        RETURNING id as "id!"
+        "#,
+        kind,
+        created_at,
+        payload
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .id)
+}
+
+pub struct RecordedOperation {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub undone_at: Option<DateTime<Utc>>,
+    pub operation: Operation,
+}
+
+impl RecordedOperation {
+    pub fn is_expired(&self) -> bool {
+        self.created_at < Utc::now() - Duration::days(RETENTION_DAYS)
+    }
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, sqlx::Error> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+fn parse_operation(
+    id: i64,
+    created_at: &str,
+    undone_at: Option<&str>,
+    payload: &str,
+) -> Result<RecordedOperation, sqlx::Error> {
+    Ok(RecordedOperation {
+        id,
+        created_at: parse_time(created_at)?,
+        undone_at: undone_at.map(parse_time).transpose()?,
+        operation: serde_json::from_str(payload).map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+    })
+}
+
+/// Recorded operations, the last one first.
+pub async fn list_operations(pool: &DbPool) -> Result<Vec<RecordedOperation>, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        SELECT id as "id!", created_at, undone_at, payload FROM operations ORDER BY id desc
+        "#
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        parse_operation(
+            row.id,
+            &row.created_at,
+            row.undone_at.as_deref(),
+            &row.payload,
+        )
+    })
+    .collect()
+}
+
+pub async fn get_operation(
+    pool: &DbPool,
+    id: i64,
+) -> Result<Option<RecordedOperation>, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        SELECT id as "id!", created_at, undone_at, payload FROM operations WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| {
+        parse_operation(
+            row.id,
+            &row.created_at,
+            row.undone_at.as_deref(),
+            &row.payload,
+        )
+    })
+    .transpose()
+}
+
+/// Rows an undo put back, and those it left out because a row taking their
+/// place was stored since.
+pub struct UndoOutcome {
+    pub restored: usize,
+    pub skipped: usize,
+}
+
+/// Puts back the rows removed or changed by operation `id` and marks it
+/// undone, so it can't be undone twice. Returns `None` when it was already
+/// undone. Operations can be undone in any order; rows stored since are kept
+/// over the ones being put back, and changed rows deleted since are skipped.
+pub async fn undo_operation(
+    pool: &DbPool,
+    id: i64,
+    operation: &Operation,
+) -> Result<Option<UndoOutcome>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let undone_at = Utc::now().to_rfc3339();
+    let marked = sqlx::query!(
+        r#"
+        UPDATE operations SET undone_at = $1 WHERE id = $2 AND undone_at IS NULL
+        "#,
+        undone_at,
+        id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+    if marked == 0 {
+        return Ok(None);
+    }
+
+    let mut outcome = UndoOutcome {
+        restored: 0,
+        skipped: 0,
+    };
+    match operation {
+        Operation::DeletePrices { prices, quotes } => {
+            for price in prices {
+                let inserted = sqlx::query!(
+                    r#"
+                    INSERT INTO prices ( ticker, date, price, preliminary, source, adjusted_price, split_coefficient, pinned, nav, bid, ask )
+                    VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11 )
+                    ON CONFLICT DO NOTHING
+                    "#,
+                    price.ticker,
+                    price.date,
+                    price.price,
+                    price.preliminary,
+                    price.source,
+                    price.adjusted_price,
+                    price.split_coefficient,
+                    price.pinned,
+                    price.nav,
+                    price.bid,
+                    price.ask
+                )
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+                if inserted == 1 {
+                    outcome.restored += 1;
+                } else {
+                    outcome.skipped += 1;
+                }
+            }
+            for quote in quotes {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO price_quotes ( ticker, date, source, price, preliminary, adjusted_price, split_coefficient )
+                    VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+                    ON CONFLICT DO NOTHING
+                    "#,
+                    quote.ticker,
+                    quote.date,
+                    quote.source,
+                    quote.price,
+                    quote.preliminary,
+                    quote.adjusted_price,
+                    quote.split_coefficient
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        Operation::NormalizePrices { prices, quotes } => {
+            for price in prices {
+                let updated = sqlx::query!(
+                    r#"
+                    UPDATE prices SET price = $1, adjusted_price = $2, nav = $3, bid = $4, ask = $5
+                    WHERE ticker = $6 AND date = $7
+                    "#,
+                    price.price,
+                    price.adjusted_price,
+                    price.nav,
+                    price.bid,
+                    price.ask,
+                    price.ticker,
+                    price.date
+                )
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+                if updated == 1 {
+                    outcome.restored += 1;
+                } else {
+                    outcome.skipped += 1;
+                }
+            }
+            for quote in quotes {
+                sqlx::query!(
+                    r#"
+                    UPDATE price_quotes SET price = $1, adjusted_price = $2
+                    WHERE ticker = $3 AND date = $4 AND source = $5
+                    "#,
+                    quote.price,
+                    quote.adjusted_price,
+                    quote.ticker,
+                    quote.date,
+                    quote.source
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+        Operation::DeleteTrade { trade } => {
+            put_back_trade(&mut tx, trade).await?;
+            outcome.restored += 1;
+        }
+        Operation::SplitTrade { trade, lot_ids } => {
+            for lot_id in lot_ids {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM trade_tags WHERE trade_id = $1
+                    "#,
+                    lot_id
+                )
+                .execute(&mut tx)
+                .await?;
+                sqlx::query!(
+                    r#"
+                    DELETE FROM trades WHERE id = $1
+                    "#,
+                    lot_id
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            reset_trade(&mut tx, trade).await?;
+            outcome.restored += 1;
+        }
+        Operation::MergeTrades { trades } => {
+            if let Some((kept, merged)) = trades.split_first() {
+                reset_trade(&mut tx, kept).await?;
+                for trade in merged {
+                    put_back_trade(&mut tx, trade).await?;
+                }
+            }
+            outcome.restored += trades.len();
+        }
+    }
+    tx.commit().await?;
+    Ok(Some(outcome))
+}
+
+/// Inserts a trade removed by an operation, with its id unless another trade
+/// took it, and its tags.
+async fn put_back_trade(
+    tx: &mut Transaction<'_, Db>,
+    trade: &DeletedTrade,
+) -> Result<(), sqlx::Error> {
+    let id_taken = sqlx::query!(
+        r#"
+        SELECT EXISTS ( SELECT 1 FROM trades WHERE id = $1 ) as "taken!: bool"
+        "#,
+        trade.id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .taken;
+    let trade_id = if id_taken {
+        sqlx::query!(
+            r#"
+            INSERT INTO trades ( ticker, date, type, amount, price, fee )
+            VALUES ( $1, $2, $3, $4, $5, $6 )
+            RETURNING id as "id!"
+            "#,
+            trade.ticker,
+            trade.date,
+            trade.r#type,
+            trade.amount,
+            trade.price,
+            trade.fee
+        )
+        .fetch_one(&mut *tx)
+        .await?
+        .id
+    } else {
+        sqlx::query!(
+            r#"
+            INSERT INTO trades ( id, ticker, date, type, amount, price, fee )
+            VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+            "#,
+            trade.id,
+            trade.ticker,
+            trade.date,
+            trade.r#type,
+            trade.amount,
+            trade.price,
+            trade.fee
+        )
+        .execute(&mut *tx)
+        .await?;
+        trade.id
+    };
+    for tag in &trade.tags {
+        sqlx::query!(
+            r#"
+            INSERT INTO trade_tags ( trade_id, tag ) VALUES ( $1, $2 )
+            ON CONFLICT DO NOTHING
+            "#,
+            trade_id,
+            tag
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Sets a trade changed by an operation back to its recorded row and tags, or
+/// puts it back when it was deleted since.
+async fn reset_trade(
+    tx: &mut Transaction<'_, Db>,
+    trade: &DeletedTrade,
+) -> Result<(), sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE trades SET amount = $1, price = $2, fee = $3 WHERE id = $4
+        "#,
+        trade.amount,
+        trade.price,
+        trade.fee,
+        trade.id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return put_back_trade(tx, trade).await;
+    }
+    sqlx::query!(
+        r#"
+        DELETE FROM trade_tags WHERE trade_id = $1
+        "#,
+        trade.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    for tag in &trade.tags {
+        sqlx::query!(
+            r#"
+            INSERT INTO trade_tags ( trade_id, tag ) VALUES ( $1, $2 )
+            "#,
+            trade.id,
+            tag
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price() -> DeletedPrice {
+        DeletedPrice {
+            ticker: "IWDA.AMS".to_string(),
+            date: "2024-05-02".to_string(),
+            price: "80.5".to_string(),
+            preliminary: false,
+            source: Some("stooq".to_string()),
+            adjusted_price: Some("79.1".to_string()),
+            split_coefficient: None,
+            pinned: true,
+            nav: None,
+            bid: Some("80.4".to_string()),
+            ask: Some("80.6".to_string()),
+        }
+    }
+
+    fn quote() -> DeletedQuote {
+        DeletedQuote {
+            ticker: "IWDA.AMS".to_string(),
+            date: "2024-05-02".to_string(),
+            source: "alpha_vantage".to_string(),
+            price: "80.45".to_string(),
+            preliminary: true,
+            adjusted_price: None,
+            split_coefficient: Some("1".to_string()),
+        }
+    }
+
+    fn trade(id: i64) -> DeletedTrade {
+        DeletedTrade {
+            id,
+            ticker: "IWDA.AMS".to_string(),
+            date: "2024-05-02".to_string(),
+            r#type: "BUY".to_string(),
+            amount: "10".to_string(),
+            price: "80".to_string(),
+            fee: Some("1.5".to_string()),
+            tags: vec!["core".to_string()],
+        }
+    }
+
+    #[test]
+    fn operations_read_back_as_recorded() {
+        let operations = vec![
+            Operation::DeletePrices {
+                prices: vec![price()],
+                quotes: vec![quote()],
+            },
+            Operation::DeleteTrade { trade: trade(1) },
+            Operation::NormalizePrices {
+                prices: vec![price(), price()],
+                quotes: vec![quote()],
+            },
+            Operation::SplitTrade {
+                trade: trade(1),
+                lot_ids: vec![7, 8],
+            },
+            Operation::MergeTrades {
+                trades: vec![trade(1), trade(2), trade(3)],
+            },
+        ];
+
+        for operation in operations {
+            let payload = serde_json::to_string(&operation).unwrap();
+            let value: serde_json::Value = serde_json::from_str(&payload).unwrap();
+            assert_eq!(value["kind"], operation.kind());
+
+            let recorded = parse_operation(1, "2024-05-02T10:00:00+00:00", None, &payload).unwrap();
+            assert_eq!(recorded.operation.kind(), operation.kind());
+            assert_eq!(recorded.operation.rows(), operation.rows());
+            assert_eq!(serde_json::to_string(&recorded.operation).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn times_are_read_in_utc() {
+        let payload = serde_json::to_string(&Operation::DeleteTrade { trade: trade(1) }).unwrap();
+
+        let recorded = parse_operation(
+            1,
+            "2024-05-02T10:00:00+00:00",
+            Some("2024-05-02T12:00:00+02:00"),
+            &payload,
+        )
+        .unwrap();
+
+        assert_eq!(recorded.undone_at, Some(recorded.created_at));
+    }
+
+    #[test]
+    fn unknown_operations_are_rejected() {
+        let payload = r#"{"kind":"delete_journal","entries":[]}"#;
+
+        assert!(parse_operation(1, "2024-05-02T10:00:00+00:00", None, payload).is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn undo(pool: &DbPool, id: i64) -> Option<UndoOutcome> {
+        let recorded = get_operation(pool, id).await.unwrap().unwrap();
+        undo_operation(pool, id, &recorded.operation).await.unwrap()
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn undoing_a_trade_deletion_puts_it_back_with_its_id_and_tags() {
+        use crate::trade::{self, CreateTrade};
+
+        let pool = crate::test_util::pool().await;
+        let trade_id = trade::create_trade(
+            &pool,
+            CreateTrade {
+                ticker: "IWDA.AMS".to_string(),
+                date: "2024-05-02".to_string(),
+                r#type: "BUY".to_string(),
+                amount: "10".to_string(),
+                price: "80".to_string(),
+                fee: Some("1.5".to_string()),
+                tags: vec!["core".to_string()],
+            },
+        )
+        .await
+        .unwrap();
+        let operation_id = trade::delete_trade(&pool, trade_id).await.ok().unwrap();
+
+        let outcome = undo(&pool, operation_id).await.unwrap();
+
+        assert_eq!((outcome.restored, outcome.skipped), (1, 0));
+        let trade = trade::get_trade(&pool, trade_id).await.unwrap().unwrap();
+        assert_eq!(trade.fee.as_deref(), Some("1.5"));
+        assert_eq!(
+            trade::list_trade_tags(&pool).await.unwrap()[&trade_id],
+            vec!["core"]
+        );
+        assert!(undo(&pool, operation_id).await.is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn undoing_a_price_deletion_skips_dates_stored_again() {
+        use crate::price::{self, PriceRange};
+
+        let pool = crate::test_util::pool().await;
+        for date in ["2024-05-01", "2024-05-02"] {
+            sqlx::query!(
+                r#"
+                INSERT INTO prices ( ticker, date, price ) VALUES ( 'IWDA.AMS', $1, '80' )
+                "#,
+                date
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let range = PriceRange {
+            ticker: Some("IWDA.AMS".to_string()),
+            from: None,
+            to: None,
+        };
+        let (operation_id, deleted) = price::delete_prices(&pool, &range).await.unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO prices ( ticker, date, price ) VALUES ( 'IWDA.AMS', '2024-05-02', '81' )
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let outcome = undo(&pool, operation_id).await.unwrap();
+
+        assert_eq!(deleted, 2);
+        assert_eq!((outcome.restored, outcome.skipped), (1, 1));
+    }
+}
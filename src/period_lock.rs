@@ -0,0 +1,77 @@
+use crate::db::DbPool;
+use chrono::{NaiveDate, Utc};
+
+/// Last date of the locked period: trades on or before it can't be created,
+/// edited or deleted. Every lock is kept; the latest one applies.
+pub async fn get_locked_until(pool: &DbPool) -> Result<Option<NaiveDate>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT until FROM period_locks ORDER BY id desc LIMIT 1
+        "#,
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| NaiveDate::parse_from_str(&row.until, "%Y-%m-%d").unwrap()))
+}
+
+/// The last locked date when any of `dates` falls in the locked period, the
+/// lock date itself included.
+pub fn lock_covering(dates: &[NaiveDate], locked_until: Option<NaiveDate>) -> Option<NaiveDate> {
+    let locked_until = locked_until?;
+    dates
+        .iter()
+        .any(|date| *date <= locked_until)
+        .then_some(locked_until)
+}
+
+pub async fn lock_period(pool: &DbPool, until: NaiveDate) -> Result<(), sqlx::Error> {
+    let until = until.to_string();
+    let created_at = Utc::now().to_rfc3339();
+    sqlx::query!(
+        r#"
+        INSERT INTO period_locks ( until, created_at ) VALUES ( $1, $2 )
+        "#,
+        until,
+        created_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::date;
+
+    #[test]
+    fn lock_covers_dates_up_to_and_including_its_date() {
+        let locked_until = Some(date("2024-03-31"));
+
+        assert_eq!(
+            lock_covering(&[date("2024-03-30")], locked_until),
+            locked_until
+        );
+        assert_eq!(
+            lock_covering(&[date("2024-03-31")], locked_until),
+            locked_until
+        );
+        assert_eq!(lock_covering(&[date("2024-04-01")], locked_until), None);
+    }
+
+    #[test]
+    fn lock_covers_a_batch_with_any_locked_date() {
+        let locked_until = Some(date("2024-03-31"));
+
+        assert_eq!(
+            lock_covering(&[date("2024-04-02"), date("2024-01-15")], locked_until),
+            locked_until
+        );
+        assert_eq!(lock_covering(&[], locked_until), None);
+    }
+
+    #[test]
+    fn nothing_is_locked_without_a_lock() {
+        assert_eq!(lock_covering(&[date("1999-01-01")], None), None);
+    }
+}
@@ -0,0 +1,461 @@
+use crate::corporate_action::{self, adjust_price, split_factor};
+use crate::db::DbPool;
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use crate::price::DailyPrice;
+use crate::trade::TradeForCalculation;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+
+#[derive(Clone, serde::Serialize)]
+pub struct Portfolio {
+    pub date: NaiveDate,
+    pub amount_in_base_currency: BigDecimal,
+    /// Valued with at least one price that is not the official close yet.
+    pub preliminary: bool,
+}
+
+/// Incrementally builds the daily value series of a single ticker. Prices are
+/// pushed one at a time in date order, so callers can feed rows straight from
+/// a database cursor; only the trades (sorted by date) are held in memory.
+/// Prices of tickers quoted in another currency are converted to the base
+/// currency with the last FX rate on or before their date.
+pub struct PortfolioBuilder {
+    trades: Peekable<IntoIter<TradeForCalculation>>,
+    first_trade_date: Option<NaiveDate>,
+    forward_fill: bool,
+    units: BigDecimal,
+    last_price: Option<(NaiveDate, BigDecimal, bool)>,
+    fx_rates: Option<Peekable<IntoIter<(NaiveDate, BigDecimal)>>>,
+    fx_rate: Option<BigDecimal>,
+    portfolio: Vec<Portfolio>,
+}
+
+impl PortfolioBuilder {
+    pub fn new(trades: Vec<TradeForCalculation>, forward_fill: bool) -> Self {
+        Self {
+            first_trade_date: trades.first().map(|trade| trade.date),
+            trades: trades.into_iter().peekable(),
+            forward_fill,
+            units: BigDecimal::zero(),
+            last_price: None,
+            fx_rates: None,
+            fx_rate: None,
+            portfolio: Vec::new(),
+        }
+    }
+
+    /// Converts the pushed prices with `fx_rates`, sorted by date. Prices
+    /// before the first rate are skipped.
+    pub fn with_fx_rates(mut self, fx_rates: Vec<(NaiveDate, BigDecimal)>) -> Self {
+        self.fx_rates = Some(fx_rates.into_iter().peekable());
+        self
+    }
+
+    fn in_base_currency(&mut self, date: NaiveDate, price: BigDecimal) -> Option<BigDecimal> {
+        let fx_rates = match self.fx_rates.as_mut() {
+            Some(fx_rates) => fx_rates,
+            None => return Some(price),
+        };
+        while let Some((_, rate)) = fx_rates.next_if(|(rate_date, _)| *rate_date <= date) {
+            self.fx_rate = Some(rate);
+        }
+        self.fx_rate.as_ref().map(|rate| price * rate)
+    }
+
+    pub fn push_price(&mut self, date: NaiveDate, price: BigDecimal, preliminary: bool) {
+        match self.first_trade_date {
+            Some(first_trade_date) if date >= first_trade_date => (),
+            _ => return,
+        }
+        let price = match self.in_base_currency(date, price) {
+            Some(price) => price,
+            None => return,
+        };
+
+        if self.forward_fill {
+            if let Some((last_date, last_price, last_preliminary)) = self.last_price.take() {
+                let mut day = last_date.succ();
+                while day < date {
+                    self.push_day(day, &last_price, last_preliminary);
+                    day = day.succ();
+                }
+            }
+        }
+        self.push_day(date, &price, preliminary);
+        self.last_price = Some((date, price, preliminary));
+    }
+
+    pub fn last_date(&self) -> Option<NaiveDate> {
+        self.last_price.as_ref().map(|(date, _, _)| *date)
+    }
+
+    /// With forward fill, carries the last price up to `until`, so series of
+    /// tickers whose prices stop earlier still line up with the others.
+    pub fn fill_until(&mut self, until: NaiveDate) {
+        if !self.forward_fill {
+            return;
+        }
+        if let Some((last_date, last_price, preliminary)) = self.last_price.take() {
+            let mut day = last_date.succ();
+            while day <= until {
+                self.push_day(day, &last_price, preliminary);
+                day = day.succ();
+            }
+            self.last_price = Some((last_date.max(until), last_price, preliminary));
+        }
+    }
+
+    fn push_day(&mut self, day: NaiveDate, price: &BigDecimal, preliminary: bool) {
+        while let Some(trade) = self.trades.next_if(|trade| trade.date <= day) {
+            self.units += trade.amount;
+        }
+        self.portfolio.push(Portfolio {
+            date: day,
+            amount_in_base_currency: price * &self.units,
+            preliminary,
+        });
+    }
+
+    pub fn finish(self) -> Vec<Portfolio> {
+        self.portfolio
+    }
+}
+
+/// Builds the daily value series of a single ticker from in-memory prices and
+/// trades, both sorted by date. `fx_rates` are given for tickers not quoted in
+/// the base currency.
+pub fn build_porfolio(
+    prices: Vec<DailyPrice>,
+    trades: Vec<TradeForCalculation>,
+    forward_fill: bool,
+    fx_rates: Option<Vec<(NaiveDate, BigDecimal)>>,
+) -> Vec<Portfolio> {
+    let mut builder = PortfolioBuilder::new(trades, forward_fill);
+    if let Some(fx_rates) = fx_rates {
+        builder = builder.with_fx_rates(fx_rates);
+    }
+    for price in prices {
+        builder.push_price(price.date, price.price, price.preliminary);
+    }
+    builder.finish()
+}
+
+pub struct InvestedCapital {
+    pub date: NaiveDate,
+    pub invested: BigDecimal,
+    pub market_value: BigDecimal,
+}
+
+/// Pairs every point of a value series with the net money put in by the trades
+/// (sorted by date) up to that date: buys add their cost, sells remove their
+/// proceeds, fees included.
+pub fn invested_capital(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+) -> Vec<InvestedCapital> {
+    let mut trades = trades.iter().peekable();
+    let mut invested = BigDecimal::from(0);
+    series
+        .iter()
+        .map(|day| {
+            while let Some(trade) = trades.next_if(|trade| trade.date <= day.date) {
+                invested += trade.cash_flow();
+            }
+            InvestedCapital {
+                date: day.date,
+                invested: round_half_up(invested.clone(), AMOUNT_SCALE),
+                market_value: round_half_up(day.amount_in_base_currency.clone(), AMOUNT_SCALE),
+            }
+        })
+        .collect()
+}
+
+/// Sums per-ticker series into a single series with one point per date. A
+/// ticker without a point on a date some other ticker has one, like when its
+/// market is closed for a holiday, counts with its last value before it.
+pub fn total_portfolio(portfolios: HashMap<String, Vec<Portfolio>>) -> Vec<Portfolio> {
+    let dates: BTreeSet<NaiveDate> = portfolios
+        .values()
+        .flatten()
+        .map(|portfolio| portfolio.date)
+        .collect();
+    let mut totals: BTreeMap<NaiveDate, (BigDecimal, bool)> = BTreeMap::new();
+    for series in portfolios.into_values() {
+        let first_date = match series.first() {
+            Some(first) => first.date,
+            None => continue,
+        };
+        let mut series = series.into_iter().peekable();
+        let mut last: Option<Portfolio> = None;
+        for date in dates.range(first_date..) {
+            while let Some(portfolio) = series.next_if(|portfolio| portfolio.date <= *date) {
+                last = Some(portfolio);
+            }
+            if let Some(last) = &last {
+                let total = totals.entry(*date).or_default();
+                total.0 += &last.amount_in_base_currency;
+                total.1 |= last.preliminary;
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(date, (amount_in_base_currency, preliminary))| Portfolio {
+            date,
+            amount_in_base_currency,
+            preliminary,
+        })
+        .collect()
+}
+
+/// Adds to every point of a value series the cash balance on its date, given
+/// the cash flows sorted by date.
+pub fn with_cash(series: Vec<Portfolio>, cash_flows: &[(NaiveDate, BigDecimal)]) -> Vec<Portfolio> {
+    let mut cash_flows = cash_flows.iter().peekable();
+    let mut cash = BigDecimal::from(0);
+    series
+        .into_iter()
+        .map(|day| {
+            while let Some((_, amount)) = cash_flows.next_if(|(date, _)| *date <= day.date) {
+                cash += amount;
+            }
+            Portfolio {
+                amount_in_base_currency: day.amount_in_base_currency + &cash,
+                ..day
+            }
+        })
+        .collect()
+}
+
+/// Computes the daily total value of all tickers largely inside the database:
+/// a window function over the trade dates of each ticker joins every price to
+/// the last trade on or before it. Only the tickers of `trades` are valued:
+/// the units held after each trade date are summed from them, and units and
+/// prices are adjusted for splits and multiplied as decimals, so the amounts
+/// match the `BigDecimal` engine.
+pub async fn total_portfolio_from_sql(
+    pool: &DbPool,
+    trades: &[TradeForCalculation],
+) -> Result<Vec<Portfolio>, sqlx::Error> {
+    let splits = corporate_action::list_splits(pool, None).await?;
+    let mut units_after: HashMap<&str, BTreeMap<NaiveDate, BigDecimal>> = HashMap::new();
+    for trade in trades {
+        let units = units_after.entry(&trade.ticker).or_default();
+        let held = units.values().next_back().cloned().unwrap_or_default() + &trade.amount;
+        units.insert(trade.date, held);
+    }
+
+    let rows = sqlx::query!(
+        r#"
+        WITH trade_dates AS (
+            SELECT DISTINCT ticker, date FROM trades
+        ),
+        holding_periods AS (
+            SELECT ticker,
+                   date,
+                   LEAD(date) OVER (PARTITION BY ticker ORDER BY date) AS next_date
+            FROM trade_dates
+        )
+        SELECT prices.date as "date!", prices.ticker as "ticker!", prices.price,
+               prices.preliminary as "preliminary!: bool", holding_periods.date as "held_since!"
+        FROM prices
+        JOIN holding_periods ON holding_periods.ticker = prices.ticker
+            AND prices.date >= holding_periods.date
+            AND (holding_periods.next_date IS NULL OR prices.date < holding_periods.next_date)
+        ORDER BY prices.date asc, prices.ticker asc
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let parse_date = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    };
+    let mut series: Vec<Portfolio> = Vec::new();
+    for row in rows {
+        let date = parse_date(&row.date)?;
+        let held_since = parse_date(&row.held_since)?;
+        let price =
+            BigDecimal::from_str(&row.price).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        let price = adjust_price(price, &split_factor(&splits, &row.ticker, date));
+        let units = match units_after
+            .get(row.ticker.as_str())
+            .and_then(|units| units.get(&held_since))
+        {
+            Some(units) => units,
+            None => continue,
+        };
+        let amount = price * units;
+        match series.last_mut() {
+            Some(day) if day.date == date => {
+                day.amount_in_base_currency += amount;
+                day.preliminary |= row.preliminary;
+            }
+            _ => series.push(Portfolio {
+                date,
+                amount_in_base_currency: amount,
+                preliminary: row.preliminary,
+            }),
+        }
+    }
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal, point, trade};
+
+    fn amounts(series: &[Portfolio]) -> Vec<(NaiveDate, BigDecimal)> {
+        series
+            .iter()
+            .map(|day| (day.date, day.amount_in_base_currency.clone()))
+            .collect()
+    }
+
+    fn buy(day: &str, amount: &str) -> TradeForCalculation {
+        trade(1, day, "IWDA.AMS", amount, "1", "0")
+    }
+
+    #[test]
+    fn total_portfolio_carries_a_ticker_over_its_market_holidays() {
+        let portfolios = HashMap::from([
+            (
+                "IWDA.AMS".to_string(),
+                vec![point("2024-05-01", "100"), point("2024-05-03", "110")],
+            ),
+            (
+                "NQSE.DEX".to_string(),
+                vec![
+                    point("2024-05-01", "50"),
+                    point("2024-05-02", "55"),
+                    point("2024-05-03", "60"),
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            amounts(&total_portfolio(portfolios)),
+            vec![
+                (date("2024-05-01"), decimal("150")),
+                (date("2024-05-02"), decimal("155")),
+                (date("2024-05-03"), decimal("170")),
+            ]
+        );
+    }
+
+    #[test]
+    fn total_portfolio_counts_a_ticker_from_its_first_point_on() {
+        let portfolios = HashMap::from([
+            (
+                "IWDA.AMS".to_string(),
+                vec![point("2024-05-01", "100"), point("2024-05-02", "100")],
+            ),
+            ("BTC".to_string(), vec![point("2024-05-02", "20")]),
+        ]);
+
+        assert_eq!(
+            amounts(&total_portfolio(portfolios)),
+            vec![
+                (date("2024-05-01"), decimal("100")),
+                (date("2024-05-02"), decimal("120")),
+            ]
+        );
+    }
+
+    #[test]
+    fn portfolio_builder_forward_fills_the_days_without_a_price() {
+        let mut builder = PortfolioBuilder::new(vec![buy("2024-05-03", "2")], true);
+        builder.push_price(date("2024-05-03"), decimal("10"), false);
+        builder.push_price(date("2024-05-06"), decimal("12"), false);
+        builder.fill_until(date("2024-05-07"));
+
+        assert_eq!(
+            amounts(&builder.finish()),
+            vec![
+                (date("2024-05-03"), decimal("20")),
+                (date("2024-05-04"), decimal("20")),
+                (date("2024-05-05"), decimal("20")),
+                (date("2024-05-06"), decimal("24")),
+                (date("2024-05-07"), decimal("24")),
+            ]
+        );
+    }
+
+    #[test]
+    fn portfolio_builder_without_forward_fill_keeps_the_gaps() {
+        let mut builder = PortfolioBuilder::new(vec![buy("2024-05-03", "2")], false);
+        builder.push_price(date("2024-05-03"), decimal("10"), false);
+        builder.push_price(date("2024-05-06"), decimal("12"), false);
+        builder.fill_until(date("2024-05-07"));
+
+        assert_eq!(
+            amounts(&builder.finish()),
+            vec![
+                (date("2024-05-03"), decimal("20")),
+                (date("2024-05-06"), decimal("24")),
+            ]
+        );
+    }
+
+    #[test]
+    fn portfolio_builder_starts_at_the_first_trade() {
+        let mut builder =
+            PortfolioBuilder::new(vec![buy("2024-05-02", "2"), buy("2024-05-04", "1")], true);
+        builder.push_price(date("2024-05-01"), decimal("9"), false);
+        builder.push_price(date("2024-05-02"), decimal("10"), false);
+        builder.push_price(date("2024-05-05"), decimal("11"), true);
+
+        let portfolio = builder.finish();
+
+        assert_eq!(
+            amounts(&portfolio),
+            vec![
+                (date("2024-05-02"), decimal("20")),
+                (date("2024-05-03"), decimal("20")),
+                (date("2024-05-04"), decimal("30")),
+                (date("2024-05-05"), decimal("33")),
+            ]
+        );
+        assert!(portfolio[3].preliminary);
+        assert!(!portfolio[2].preliminary);
+    }
+
+    #[test]
+    fn portfolio_builder_converts_with_the_last_fx_rate_on_or_before_the_date() {
+        let mut builder =
+            PortfolioBuilder::new(vec![buy("2024-05-01", "2")], false).with_fx_rates(vec![
+                (date("2024-05-02"), decimal("0.9")),
+                (date("2024-05-04"), decimal("0.8")),
+            ]);
+        builder.push_price(date("2024-05-01"), decimal("10"), false);
+        builder.push_price(date("2024-05-02"), decimal("10"), false);
+        builder.push_price(date("2024-05-03"), decimal("10"), false);
+        builder.push_price(date("2024-05-04"), decimal("10"), false);
+
+        assert_eq!(
+            amounts(&builder.finish()),
+            vec![
+                (date("2024-05-02"), decimal("18")),
+                (date("2024-05-03"), decimal("18")),
+                (date("2024-05-04"), decimal("16")),
+            ]
+        );
+    }
+
+    #[test]
+    fn portfolio_builder_without_fx_rates_keeps_the_quoted_prices() {
+        let mut builder = PortfolioBuilder::new(vec![buy("2024-05-01", "2")], false);
+        builder.push_price(date("2024-05-01"), decimal("10"), false);
+
+        assert_eq!(
+            amounts(&builder.finish()),
+            vec![(date("2024-05-01"), decimal("20"))]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use crate::trade::TradeForCalculation;
+use bigdecimal::{BigDecimal, Zero};
+
+pub struct Position {
+    /// Latest known price, the position is valued at, in the currency the
+    /// ticker is quoted in.
+    pub price: BigDecimal,
+    pub units: BigDecimal,
+    pub total_cost: BigDecimal,
+    pub market_value: BigDecimal,
+    pub unrealized_gain: BigDecimal,
+    pub unrealized_gain_percent: Option<BigDecimal>,
+}
+
+/// Units of a single ticker held and what they cost, before valuing them.
+pub struct Holding {
+    pub units: BigDecimal,
+    pub total_cost: BigDecimal,
+}
+
+/// Builds the holding of a single ticker from its trades (sorted by date). The
+/// cost basis uses the average cost method: a sell removes units at the
+/// average cost of the units held at that moment. Buy fees are part of the
+/// cost.
+pub fn calculate_holding(trades: &[TradeForCalculation]) -> Holding {
+    let mut units = BigDecimal::zero();
+    let mut total_cost = BigDecimal::zero();
+
+    for trade in trades {
+        if trade.amount >= BigDecimal::zero() {
+            total_cost += trade.cash_flow();
+        } else if units > BigDecimal::zero() {
+            total_cost -= &total_cost * -&trade.amount / &units;
+        }
+        units += &trade.amount;
+        if units <= BigDecimal::zero() {
+            total_cost = BigDecimal::zero();
+        }
+    }
+
+    Holding {
+        units: units.normalized(),
+        total_cost,
+    }
+}
+
+/// Values the holding built from `trades`, converted to the base currency, at
+/// the latest known price. `fx_rate` converts that price to the base currency.
+pub fn calculate_position(
+    trades: &[TradeForCalculation],
+    latest_price: &BigDecimal,
+    fx_rate: &BigDecimal,
+) -> Position {
+    let Holding { units, total_cost } = calculate_holding(trades);
+    let market_value = latest_price * fx_rate * &units;
+    let unrealized_gain = &market_value - &total_cost;
+    let unrealized_gain_percent = if total_cost.is_zero() {
+        None
+    } else {
+        Some(round_half_up(
+            &unrealized_gain * BigDecimal::from(100) / &total_cost,
+            AMOUNT_SCALE,
+        ))
+    };
+
+    Position {
+        price: latest_price.clone(),
+        units,
+        total_cost: round_half_up(total_cost, AMOUNT_SCALE),
+        market_value: round_half_up(market_value, AMOUNT_SCALE),
+        unrealized_gain: round_half_up(unrealized_gain, AMOUNT_SCALE),
+        unrealized_gain_percent,
+    }
+}
+
+pub struct Allocation {
+    pub ticker: String,
+    pub market_value: BigDecimal,
+    pub weight_percent: Option<BigDecimal>,
+}
+
+/// Share of each position in the total market value. Weights are `None` when
+/// nothing is held.
+pub fn allocation(positions: &[(String, Position)]) -> Vec<Allocation> {
+    let total_market_value: BigDecimal = positions
+        .iter()
+        .map(|(_, position)| &position.market_value)
+        .sum();
+
+    positions
+        .iter()
+        .map(|(ticker, position)| Allocation {
+            ticker: ticker.clone(),
+            market_value: position.market_value.clone(),
+            weight_percent: if total_market_value.is_zero() {
+                None
+            } else {
+                Some(round_half_up(
+                    &position.market_value * BigDecimal::from(100) / &total_market_value,
+                    AMOUNT_SCALE,
+                ))
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{decimal, trade};
+
+    #[test]
+    fn position_is_valued_in_the_base_currency() {
+        // Bought for 900 EUR at a rate of 0.9, worth 550 USD now at 0.8.
+        let trades = vec![trade(1, "2024-05-02", "VOO", "2", "450", "0")];
+
+        let position = calculate_position(&trades, &decimal("550"), &decimal("0.8"));
+
+        assert_eq!(position.price, decimal("550"));
+        assert_eq!(position.market_value, decimal("880"));
+        assert_eq!(position.unrealized_gain, decimal("-20"));
+    }
+}
@@ -0,0 +1,1267 @@
+use crate::alpha_vantage::{self, AlphaVantageError};
+use crate::corporate_action::{self, adjust_price, split_factor};
+use crate::db::{Db, DbPool};
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use crate::mock;
+use crate::operation::{self, DeletedPrice, DeletedQuote, Operation};
+use async_trait::async_trait;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use futures::TryStreamExt;
+use serde::Deserialize;
+use sqlx::{Executor, Transaction};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+const NORMALIZED_PRICE_SCALE: i64 = 6;
+
+/// Both tickers trade in Europe and close at 17:30 CET, which is 16:30 UTC in
+/// winter. The extra half hour gives the provider time to publish the close.
+const OFFICIAL_CLOSE_UTC_HOUR: u32 = 17;
+
+#[derive(Clone)]
+pub struct DailyPrice {
+    pub date: NaiveDate,
+    pub price: BigDecimal,
+    pub ticker: String,
+    pub preliminary: bool,
+    /// Close adjusted for the splits and dividends known when it was fetched,
+    /// from providers that publish it.
+    pub adjusted_price: Option<BigDecimal>,
+    /// Units after a split on this date for every unit before it, 1 on days
+    /// without one, from providers that publish it.
+    pub split_coefficient: Option<BigDecimal>,
+    pub marks: PriceMarks,
+}
+
+/// Values other than the close a unit can be valued at on a day, entered by
+/// hand since no provider publishes them.
+#[derive(Clone, Default)]
+pub struct PriceMarks {
+    /// Net asset value per unit, published by the fund.
+    pub nav: Option<BigDecimal>,
+    pub bid: Option<BigDecimal>,
+    pub ask: Option<BigDecimal>,
+}
+
+/// Streams stored prices ordered by date into `f`, so long histories can be
+/// folded row by row without materializing them. The price and the marks are
+/// adjusted to the splits after them; the adjusted close, when one is stored,
+/// already reflects them.
+pub async fn for_each_daily_price(
+    pool: &DbPool,
+    ticker: Option<&str>,
+    mut f: impl FnMut(DailyPrice),
+) -> Result<(), sqlx::Error> {
+    let splits = corporate_action::list_splits(pool, ticker).await?;
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT date, price, ticker, preliminary as "preliminary: bool", adjusted_price,
+               split_coefficient, nav, bid, ask
+        FROM prices
+        WHERE CAST($1 AS TEXT) IS NULL OR ticker = $1
+        ORDER BY date asc, id asc
+        "#,
+        ticker
+    )
+    .fetch(pool);
+
+    while let Some(row) = rows.try_next().await? {
+        let date = NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap();
+        let adjusted_price = row
+            .adjusted_price
+            .as_deref()
+            .map(|adjusted_price| BigDecimal::from_str(adjusted_price).unwrap());
+        let factor = split_factor(&splits, &row.ticker, date);
+        let mark = |value: Option<String>| {
+            value.map(|value| adjust_price(BigDecimal::from_str(&value).unwrap(), &factor))
+        };
+        let marks = PriceMarks {
+            nav: mark(row.nav),
+            bid: mark(row.bid),
+            ask: mark(row.ask),
+        };
+        f(DailyPrice {
+            price: adjust_price(BigDecimal::from_str(&row.price).unwrap(), &factor),
+            date,
+            ticker: row.ticker,
+            preliminary: row.preliminary,
+            adjusted_price,
+            split_coefficient: row
+                .split_coefficient
+                .as_deref()
+                .map(|split_coefficient| BigDecimal::from_str(split_coefficient).unwrap()),
+            marks,
+        });
+    }
+    Ok(())
+}
+
+/// Which values some price of a ticker is stored without.
+pub struct MissingValues {
+    /// Like prices fetched before adjusted closes were.
+    pub adjusted_price: bool,
+    pub nav: bool,
+    /// Either the bid or the ask.
+    pub bid_ask: bool,
+}
+
+pub async fn missing_values(pool: &DbPool, ticker: &str) -> Result<MissingValues, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS ( SELECT 1 FROM prices WHERE ticker = $1 AND adjusted_price IS NULL ) as "adjusted_price!: bool",
+               EXISTS ( SELECT 1 FROM prices WHERE ticker = $1 AND nav IS NULL ) as "nav!: bool",
+               EXISTS ( SELECT 1 FROM prices WHERE ticker = $1 AND ( bid IS NULL OR ask IS NULL ) ) as "bid_ask!: bool"
+        "#,
+        ticker
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(MissingValues {
+        adjusted_price: row.adjusted_price,
+        nav: row.nav,
+        bid_ask: row.bid_ask,
+    })
+}
+
+/// A price for today fetched before the official close is the last traded
+/// price, not the close, and will still change.
+pub fn is_preliminary(date: NaiveDate, fetched_at: DateTime<Utc>) -> bool {
+    date >= fetched_at.naive_utc().date()
+        && fetched_at.time() < NaiveTime::from_hms(OFFICIAL_CLOSE_UTC_HOUR, 0, 0)
+}
+
+/// Stores the price of `ticker` on its date fetched from the provider named
+/// `source`, replacing the one it fetched before, which is how preliminary
+/// prices get replaced by the close. Prices other providers fetched for the
+/// date are kept, and the one of the provider first in `trust_order` becomes
+/// the stored price, unless one was picked with `pick_price`.
+pub async fn upsert_price(
+    tx: &mut Transaction<'_, Db>,
+    ticker: &str,
+    price: &DailyPrice,
+    source: &str,
+    trust_order: &[String],
+) -> Result<(), sqlx::Error> {
+    let date = price.date.to_string();
+    let close = price.price.to_string();
+    let adjusted_price = price.adjusted_price.as_ref().map(BigDecimal::to_string);
+    let split_coefficient = price.split_coefficient.as_ref().map(BigDecimal::to_string);
+    sqlx::query!(
+        r#"
+        INSERT INTO price_quotes ( ticker, date, source, price, preliminary, adjusted_price, split_coefficient )
+        VALUES ( $1, $2, $3, $4, $5, $6, $7 )
+        ON CONFLICT ( ticker, date, source ) DO UPDATE SET
+            price = excluded.price,
+            preliminary = excluded.preliminary,
+            adjusted_price = excluded.adjusted_price,
+            split_coefficient = excluded.split_coefficient
+        "#,
+        ticker,
+        date,
+        source,
+        close,
+        price.preliminary,
+        adjusted_price,
+        split_coefficient
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let quotes = sqlx::query!(
+        r#"
+        SELECT source FROM price_quotes WHERE ticker = $1 AND date = $2
+        "#,
+        ticker,
+        date
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let trust = |source: &str| {
+        trust_order
+            .iter()
+            .position(|trusted| trusted == source)
+            .unwrap_or(trust_order.len())
+    };
+    let trusted_source = quotes
+        .iter()
+        .map(|quote| quote.source.as_str())
+        .min_by_key(|quote_source| (trust(quote_source), *quote_source != source))
+        .unwrap_or(source);
+    store_quote(tx, ticker, &date, trusted_source, false).await?;
+    Ok(())
+}
+
+/// Makes the price `source` fetched for `ticker` on `date` the stored one.
+/// Unless `pin`, a price picked by hand is kept. Returns whether there was a
+/// price from `source` to store.
+async fn store_quote(
+    tx: &mut Transaction<'_, Db>,
+    ticker: &str,
+    date: &str,
+    source: &str,
+    pin: bool,
+) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        INSERT INTO prices ( ticker, date, price, preliminary, source, adjusted_price, split_coefficient, pinned )
+        SELECT ticker, date, price, preliminary, source, adjusted_price, split_coefficient, $4
+        FROM price_quotes WHERE ticker = $1 AND date = $2 AND source = $3
+        ON CONFLICT ( ticker, date ) DO UPDATE SET
+            price = excluded.price,
+            preliminary = excluded.preliminary,
+            source = excluded.source,
+            adjusted_price = excluded.adjusted_price,
+            split_coefficient = excluded.split_coefficient,
+            pinned = excluded.pinned
+        WHERE $4 OR NOT prices.pinned
+        "#,
+        ticker,
+        date,
+        source,
+        pin
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0)
+}
+
+/// Makes the price `source` fetched for `ticker` on `date` the stored one,
+/// whatever the trust order, for good. Returns whether `source` fetched a
+/// price for the date.
+pub async fn pick_price(
+    pool: &DbPool,
+    ticker: &str,
+    date: &str,
+    source: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let picked = store_quote(&mut tx, ticker, date, source, true).await?;
+    tx.commit().await?;
+    Ok(picked)
+}
+
+/// Source of the prices entered by hand, which fetched prices don't replace.
+pub const MANUAL_SOURCE: &str = "manual";
+
+async fn store_manual_price(
+    tx: &mut Transaction<'_, Db>,
+    ticker: &str,
+    date: &str,
+    price: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO price_quotes ( ticker, date, source, price, preliminary )
+        VALUES ( $1, $2, $3, $4, FALSE )
+        ON CONFLICT ( ticker, date, source ) DO UPDATE SET price = excluded.price
+        "#,
+        ticker,
+        date,
+        MANUAL_SOURCE,
+        price
+    )
+    .execute(&mut *tx)
+    .await?;
+    store_quote(tx, ticker, date, MANUAL_SOURCE, true).await?;
+    Ok(())
+}
+
+/// Stores a price of `ticker` entered by hand for a date without one, and
+/// returns its id, or `None` when the date has a price already.
+pub async fn insert_manual_price(
+    pool: &DbPool,
+    ticker: &str,
+    date: &str,
+    price: &str,
+) -> Result<Option<i64>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    if get_price(&mut tx, ticker, date).await?.is_some() {
+        return Ok(None);
+    }
+    store_manual_price(&mut tx, ticker, date, price).await?;
+    let id = sqlx::query!(
+        r#"
+        SELECT id as "id!" FROM prices WHERE ticker = $1 AND date = $2
+        "#,
+        ticker,
+        date
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .id;
+    tx.commit().await?;
+    Ok(Some(id))
+}
+
+/// Replaces the stored price `id` with one entered by hand. Returns whether
+/// the price exists.
+pub async fn correct_price(pool: &DbPool, id: i64, price: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let stored = match sqlx::query!(
+        r#"
+        SELECT ticker, date FROM prices WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    {
+        Some(stored) => stored,
+        None => return Ok(false),
+    };
+    store_manual_price(&mut tx, &stored.ticker, &stored.date, price).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Sets the NAV and the bid and ask of the stored price `id`, clearing those
+/// given as `None`. Returns whether the price exists.
+pub async fn set_price_marks(
+    pool: &DbPool,
+    id: i64,
+    nav: Option<&str>,
+    bid: Option<&str>,
+    ask: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        UPDATE prices SET nav = $2, bid = $3, ask = $4 WHERE id = $1
+        "#,
+        id,
+        nav,
+        bid,
+        ask
+    )
+    .execute(pool)
+    .await?
+    .rows_affected()
+        > 0)
+}
+
+pub struct PriceQuote {
+    pub source: String,
+    pub price: BigDecimal,
+}
+
+/// Date on which providers fetched prices of a ticker that differ.
+pub struct PriceConflict {
+    pub ticker: String,
+    pub date: String,
+    /// Stored price and the provider it came from.
+    pub price: String,
+    pub source: Option<String>,
+    pub quotes: Vec<PriceQuote>,
+}
+
+/// Dates on which prices fetched from different providers differ by more
+/// than `tolerance_percent` of the lowest one, except those whose price was
+/// picked with `pick_price`, ordered by date.
+pub async fn list_conflicts(
+    pool: &DbPool,
+    tolerance_percent: &BigDecimal,
+) -> Result<Vec<PriceConflict>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT q.ticker, q.date, q.source, q.price, p.price as stored_price, p.source as stored_source
+        FROM price_quotes q
+        JOIN prices p ON p.ticker = q.ticker AND p.date = q.date
+        WHERE NOT p.pinned AND EXISTS (
+            SELECT 1 FROM price_quotes other
+            WHERE other.ticker = q.ticker AND other.date = q.date AND other.source <> q.source
+        )
+        ORDER BY q.date asc, q.ticker asc, q.source asc
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut conflicts: Vec<PriceConflict> = Vec::new();
+    for row in rows {
+        let quote = PriceQuote {
+            source: row.source,
+            price: BigDecimal::from_str(&row.price)
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        };
+        match conflicts.last_mut() {
+            Some(conflict) if conflict.ticker == row.ticker && conflict.date == row.date => {
+                conflict.quotes.push(quote)
+            }
+            _ => conflicts.push(PriceConflict {
+                ticker: row.ticker,
+                date: row.date,
+                price: row.stored_price,
+                source: row.stored_source,
+                quotes: vec![quote],
+            }),
+        }
+    }
+    conflicts.retain(|conflict| {
+        let prices = conflict.quotes.iter().map(|quote| &quote.price);
+        match (prices.clone().min(), prices.max()) {
+            (Some(lowest), Some(highest)) => {
+                lowest > &BigDecimal::from(0)
+                    && deviation_percent(highest, lowest) > *tolerance_percent
+            }
+            _ => false,
+        }
+    });
+    Ok(conflicts)
+}
+
+/// Dates of the stored prices of `ticker`, in order.
+pub async fn list_price_dates(pool: &DbPool, ticker: &str) -> Result<Vec<NaiveDate>, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        SELECT date FROM prices WHERE ticker = $1 ORDER BY date asc
+        "#,
+        ticker
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        NaiveDate::parse_from_str(&row.date, "%Y-%m-%d")
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    })
+    .collect()
+}
+
+/// Weekdays, or every day `with_weekends`, for tickers that trade every day.
+fn is_trading_day(date: NaiveDate, with_weekends: bool) -> bool {
+    with_weekends || !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+/// Trading days after `after` and before `before`.
+pub fn trading_days_between(after: NaiveDate, before: NaiveDate, with_weekends: bool) -> usize {
+    after
+        .iter_days()
+        .skip(1)
+        .take_while(|date| *date < before)
+        .filter(|date| is_trading_day(*date, with_weekends))
+        .count()
+}
+
+/// Consecutive trading days without a price between two days with one.
+pub struct PriceGap {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Trading days missing in the range.
+    pub days: usize,
+}
+
+/// Gaps between the first and the last of `dates`, sorted. Trading days are
+/// weekdays, or every day `with_weekends`, for tickers that trade every day.
+/// Exchange holidays are reported like any other missing day.
+pub fn find_gaps(dates: &[NaiveDate], with_weekends: bool) -> Vec<PriceGap> {
+    dates
+        .windows(2)
+        .filter_map(|pair| {
+            let missing: Vec<NaiveDate> = pair[0]
+                .iter_days()
+                .skip(1)
+                .take_while(|date| *date < pair[1])
+                .filter(|date| is_trading_day(*date, with_weekends))
+                .collect();
+            Some(PriceGap {
+                from: *missing.first()?,
+                to: *missing.last()?,
+                days: missing.len(),
+            })
+        })
+        .collect()
+}
+
+pub struct StoredPrice {
+    pub date: String,
+    pub price: String,
+}
+
+/// Up to `count` closing prices of `ticker` picked at random, ordered by date.
+pub async fn sample_prices(
+    pool: &DbPool,
+    ticker: &str,
+    count: i64,
+) -> Result<Vec<StoredPrice>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredPrice,
+        r#"
+        SELECT date, price FROM (
+            SELECT date, price FROM prices
+            WHERE ticker = $1 AND NOT preliminary
+            ORDER BY RANDOM() LIMIT $2
+        ) AS sample ORDER BY date asc
+        "#,
+        ticker,
+        count
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Stored price of `ticker` on `date`. Runs on a pool or within a
+/// transaction.
+pub async fn get_price(
+    executor: impl Executor<'_, Database = Db>,
+    ticker: &str,
+    date: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT price FROM prices WHERE ticker = $1 AND date = $2
+        "#,
+        ticker,
+        date
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(|row| row.price))
+}
+
+pub enum PriceAnomalyKind {
+    /// Zero or negative.
+    NonPositive,
+    /// Moved more than the threshold from the previous price.
+    Jump,
+}
+
+/// Stored price that is likely a provider glitch.
+pub struct PriceAnomaly {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub price: BigDecimal,
+    pub kind: PriceAnomalyKind,
+    /// Last valid price before it, for jumps.
+    pub previous: Option<(NaiveDate, BigDecimal)>,
+    pub change_percent: Option<BigDecimal>,
+}
+
+/// Stored prices that are zero or negative, or that moved more than
+/// `threshold_percent` from the previous positive price of their ticker,
+/// ordered by date. Prices are compared adjusted for splits, so splits don't
+/// show up as jumps.
+pub async fn find_anomalies(
+    pool: &DbPool,
+    threshold_percent: &BigDecimal,
+) -> Result<Vec<PriceAnomaly>, sqlx::Error> {
+    let mut previous_prices: HashMap<String, (NaiveDate, BigDecimal)> = HashMap::new();
+    let mut anomalies = Vec::new();
+    for_each_daily_price(pool, None, |mut price| {
+        // Checked like the portfolio values them by default.
+        if let Some(adjusted_price) = price.adjusted_price.take() {
+            price.price = adjusted_price;
+        }
+        if price.price <= BigDecimal::from(0) {
+            anomalies.push(PriceAnomaly {
+                ticker: price.ticker,
+                date: price.date,
+                price: price.price,
+                kind: PriceAnomalyKind::NonPositive,
+                previous: None,
+                change_percent: None,
+            });
+            return;
+        }
+        let previous =
+            previous_prices.insert(price.ticker.clone(), (price.date, price.price.clone()));
+        if let Some((previous_date, previous_price)) = previous {
+            let change_percent = deviation_percent(&price.price, &previous_price);
+            if change_percent > *threshold_percent {
+                anomalies.push(PriceAnomaly {
+                    ticker: price.ticker,
+                    date: price.date,
+                    price: price.price,
+                    kind: PriceAnomalyKind::Jump,
+                    previous: Some((previous_date, previous_price)),
+                    change_percent: Some(round_half_up(change_percent, AMOUNT_SCALE)),
+                });
+            }
+        }
+    })
+    .await?;
+    Ok(anomalies)
+}
+
+/// How far `price` is from `reference`, as a percentage of `reference`.
+pub fn deviation_percent(price: &BigDecimal, reference: &BigDecimal) -> BigDecimal {
+    ((price - reference) / reference).abs() * BigDecimal::from(100)
+}
+
+/// Stored prices to delete: those of `ticker`, or of every ticker, from `from`
+/// to `to`, both included, when set.
+pub struct PriceRange {
+    pub ticker: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Deletes the prices in `range` and the quotes providers fetched for them,
+/// and records them to be put back with `operation::undo_operation`. Returns
+/// the id of the operation and how many prices were deleted.
+pub async fn delete_prices(pool: &DbPool, range: &PriceRange) -> Result<(i64, usize), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let prices: Vec<DeletedPrice> = sqlx::query!(
+        r#"
+        SELECT ticker, date, price, preliminary as "preliminary: bool", source, adjusted_price,
+               split_coefficient, pinned as "pinned: bool", nav, bid, ask
+        FROM prices
+        WHERE (CAST($1 AS TEXT) IS NULL OR ticker = $1) AND (CAST($2 AS TEXT) IS NULL OR date >= $2) AND (CAST($3 AS TEXT) IS NULL OR date <= $3)
+        ORDER BY ticker asc, date asc
+        "#,
+        range.ticker,
+        range.from,
+        range.to
+    )
+    .fetch_all(&mut tx)
+    .await?
+    .into_iter()
+    .map(|row| DeletedPrice {
+        ticker: row.ticker,
+        date: row.date,
+        price: row.price,
+        preliminary: row.preliminary,
+        source: row.source,
+        adjusted_price: row.adjusted_price,
+        split_coefficient: row.split_coefficient,
+        pinned: row.pinned,
+        nav: row.nav,
+        bid: row.bid,
+        ask: row.ask,
+    })
+    .collect();
+    let quotes: Vec<DeletedQuote> = sqlx::query!(
+        r#"
+        SELECT ticker, date, source, price, preliminary as "preliminary: bool", adjusted_price,
+               split_coefficient
+        FROM price_quotes
+        WHERE (CAST($1 AS TEXT) IS NULL OR ticker = $1) AND (CAST($2 AS TEXT) IS NULL OR date >= $2) AND (CAST($3 AS TEXT) IS NULL OR date <= $3)
+        ORDER BY ticker asc, date asc, source asc
+        "#,
+        range.ticker,
+        range.from,
+        range.to
+    )
+    .fetch_all(&mut tx)
+    .await?
+    .into_iter()
+    .map(|row| DeletedQuote {
+        ticker: row.ticker,
+        date: row.date,
+        source: row.source,
+        price: row.price,
+        preliminary: row.preliminary,
+        adjusted_price: row.adjusted_price,
+        split_coefficient: row.split_coefficient,
+    })
+    .collect();
+
+    sqlx::query!(
+        r#"
+        DELETE FROM prices
+        WHERE (CAST($1 AS TEXT) IS NULL OR ticker = $1) AND (CAST($2 AS TEXT) IS NULL OR date >= $2) AND (CAST($3 AS TEXT) IS NULL OR date <= $3)
+        "#,
+        range.ticker,
+        range.from,
+        range.to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM price_quotes
+        WHERE (CAST($1 AS TEXT) IS NULL OR ticker = $1) AND (CAST($2 AS TEXT) IS NULL OR date >= $2) AND (CAST($3 AS TEXT) IS NULL OR date <= $3)
+        "#,
+        range.ticker,
+        range.from,
+        range.to
+    )
+    .execute(&mut tx)
+    .await?;
+    let deleted = prices.len();
+    let id =
+        operation::record_operation(&mut tx, &Operation::DeletePrices { prices, quotes }).await?;
+    tx.commit().await?;
+    Ok((id, deleted))
+}
+
+pub async fn get_latest_price(pool: &DbPool, ticker: &str) -> Result<Option<String>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT price FROM prices WHERE ticker = $1 ORDER BY date desc LIMIT 1
+        "#,
+        ticker
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| row.price))
+}
+
+/// The `count` most recent prices of `ticker`, newest first.
+pub async fn get_latest_prices(
+    pool: &DbPool,
+    ticker: &str,
+    count: i64,
+) -> Result<Vec<StoredPrice>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredPrice,
+        r#"
+        SELECT date, price FROM prices WHERE ticker = $1 ORDER BY date desc LIMIT $2
+        "#,
+        ticker,
+        count
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub struct NormalizePrices {
+    pub ticker: String,
+    pub from: String,
+    pub to: String,
+    pub factor: BigDecimal,
+    pub preview: bool,
+}
+
+pub struct NormalizedPrice {
+    pub id: i64,
+    pub date: String,
+    pub old_price: String,
+    pub new_price: String,
+}
+
+fn normalize_price(price: &str, factor: &BigDecimal) -> Result<String, sqlx::Error> {
+    let price = BigDecimal::from_str(price).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+    Ok(round_half_up(price * factor, NORMALIZED_PRICE_SCALE)
+        .normalized()
+        .to_string())
+}
+
+fn normalize_optional_price(
+    price: &Option<String>,
+    factor: &BigDecimal,
+) -> Result<Option<String>, sqlx::Error> {
+    price
+        .as_deref()
+        .map(|price| normalize_price(price, factor))
+        .transpose()
+}
+
+/// Multiplies the prices of a ticker from `from` to `to` by the factor: the
+/// close, adjusted close, NAV, bid and ask of the stored prices and of the
+/// quotes providers fetched for them. Unless previewing, the rows as they were
+/// are recorded to be put back with `operation::undo_operation`.
+pub async fn normalize_prices(
+    pool: &DbPool,
+    normalization: NormalizePrices,
+) -> Result<Vec<NormalizedPrice>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let factor = &normalization.factor;
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT id as "id!", ticker, date, price, preliminary as "preliminary: bool", source,
+               adjusted_price, split_coefficient, pinned as "pinned: bool", nav, bid, ask
+        FROM prices
+        WHERE ticker = $1 AND date >= $2 AND date <= $3
+        ORDER BY date asc
+        "#,
+        normalization.ticker,
+        normalization.from,
+        normalization.to,
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let mut normalized_prices = Vec::with_capacity(rows.len());
+    let mut prices = Vec::with_capacity(rows.len());
+    for row in rows {
+        let new_price = normalize_price(&row.price, factor)?;
+        let adjusted_price = normalize_optional_price(&row.adjusted_price, factor)?;
+        let nav = normalize_optional_price(&row.nav, factor)?;
+        let bid = normalize_optional_price(&row.bid, factor)?;
+        let ask = normalize_optional_price(&row.ask, factor)?;
+
+        if !normalization.preview {
+            sqlx::query!(
+                r#"
+                UPDATE prices SET price = $1, adjusted_price = $2, nav = $3, bid = $4, ask = $5
+                WHERE id = $6
+                "#,
+                new_price,
+                adjusted_price,
+                nav,
+                bid,
+                ask,
+                row.id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        normalized_prices.push(NormalizedPrice {
+            id: row.id,
+            date: row.date.clone(),
+            old_price: row.price.clone(),
+            new_price,
+        });
+        prices.push(DeletedPrice {
+            ticker: row.ticker,
+            date: row.date,
+            price: row.price,
+            preliminary: row.preliminary,
+            source: row.source,
+            adjusted_price: row.adjusted_price,
+            split_coefficient: row.split_coefficient,
+            pinned: row.pinned,
+            nav: row.nav,
+            bid: row.bid,
+            ask: row.ask,
+        });
+    }
+    if normalization.preview {
+        return Ok(normalized_prices);
+    }
+
+    let quotes: Vec<DeletedQuote> = sqlx::query!(
+        r#"
+        SELECT ticker, date, source, price, preliminary as "preliminary: bool", adjusted_price,
+               split_coefficient
+        FROM price_quotes
+        WHERE ticker = $1 AND date >= $2 AND date <= $3
+        ORDER BY date asc, source asc
+        "#,
+        normalization.ticker,
+        normalization.from,
+        normalization.to,
+    )
+    .fetch_all(&mut tx)
+    .await?
+    .into_iter()
+    .map(|row| DeletedQuote {
+        ticker: row.ticker,
+        date: row.date,
+        source: row.source,
+        price: row.price,
+        preliminary: row.preliminary,
+        adjusted_price: row.adjusted_price,
+        split_coefficient: row.split_coefficient,
+    })
+    .collect();
+    for quote in &quotes {
+        let price = normalize_price(&quote.price, factor)?;
+        let adjusted_price = normalize_optional_price(&quote.adjusted_price, factor)?;
+        sqlx::query!(
+            r#"
+            UPDATE price_quotes SET price = $1, adjusted_price = $2
+            WHERE ticker = $3 AND date = $4 AND source = $5
+            "#,
+            price,
+            adjusted_price,
+            quote.ticker,
+            quote.date,
+            quote.source
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    operation::record_operation(&mut tx, &Operation::NormalizePrices { prices, quotes }).await?;
+    tx.commit().await?;
+    Ok(normalized_prices)
+}
+
+pub enum PriceProviderError {
+    Request(reqwest::Error),
+    InvalidResponse(String),
+    /// The response is not laid out like any of the layouts the provider is
+    /// known to use, like after it renamed a field.
+    ParserMismatch(String),
+}
+
+impl From<reqwest::Error> for PriceProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        // The URL carries the API key of some providers.
+        PriceProviderError::Request(e.without_url())
+    }
+}
+
+impl From<AlphaVantageError> for PriceProviderError {
+    fn from(e: AlphaVantageError) -> Self {
+        match e {
+            AlphaVantageError::Request(e) => PriceProviderError::Request(e),
+            e => PriceProviderError::InvalidResponse(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for PriceProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceProviderError::Request(e) => write!(f, "request failed: {}", e),
+            PriceProviderError::InvalidResponse(message) => {
+                write!(f, "invalid response: {}", message)
+            }
+            PriceProviderError::ParserMismatch(message) => {
+                write!(f, "parser mismatch: {}", message)
+            }
+        }
+    }
+}
+
+/// Source of daily closing prices.
+#[async_trait]
+pub trait PriceProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Closes of `symbol` after `since`, sorted by date, with `ticker` set to
+    /// `symbol`. A price of today fetched before the close is preliminary.
+    async fn fetch_daily(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyPrice>, PriceProviderError>;
+}
+
+/// Fields are also read without their number, as Alpha Vantage has served
+/// them in some of its layouts.
+#[derive(Deserialize)]
+struct AlphaVantageDailyPriceResponse {
+    #[serde(rename(deserialize = "4. close"), alias = "close")]
+    price: String,
+    /// Only in the adjusted time series, like the fields below.
+    #[serde(rename(deserialize = "5. adjusted close"), alias = "adjusted close")]
+    adjusted_price: Option<String>,
+    #[serde(rename(deserialize = "7. dividend amount"), alias = "dividend amount")]
+    dividend: Option<String>,
+    #[serde(
+        rename(deserialize = "8. split coefficient"),
+        alias = "split coefficient"
+    )]
+    split_coefficient: Option<String>,
+}
+
+impl AlphaVantageDailyPriceResponse {
+    /// Whether a split or a dividend on this day changed the adjusted closes
+    /// before it.
+    fn adjusts_history(&self) -> bool {
+        let differs_from = |value: &Option<String>, unchanged: i32| {
+            value
+                .as_deref()
+                .and_then(|value| BigDecimal::from_str(value).ok())
+                .is_some_and(|value| value != BigDecimal::from(unchanged))
+        };
+        differs_from(&self.split_coefficient, 1) || differs_from(&self.dividend, 0)
+    }
+}
+
+/// Keys the daily time series has been served under, the current one first.
+const ALPHA_VANTAGE_TIME_SERIES_KEYS: &[&str] =
+    &["Time Series (Daily)", "Time Series (Daily Adjusted)"];
+
+/// Reads the daily time series out of an Alpha Vantage response, trying each
+/// layout it is known to use before reporting what it got instead.
+fn parse_alpha_vantage_time_series(
+    body: serde_json::Value,
+) -> Result<HashMap<String, AlphaVantageDailyPriceResponse>, PriceProviderError> {
+    let mut body = match body {
+        serde_json::Value::Object(body) => body,
+        _ => {
+            return Err(PriceProviderError::ParserMismatch(
+                "the response is not an object".to_string(),
+            ))
+        }
+    };
+    let time_series = match ALPHA_VANTAGE_TIME_SERIES_KEYS
+        .iter()
+        .find_map(|key| body.remove(*key))
+    {
+        Some(time_series) => time_series,
+        None => {
+            let keys: Vec<&str> = body.keys().map(String::as_str).collect();
+            return Err(PriceProviderError::ParserMismatch(format!(
+                "no time series among the keys {}",
+                keys.join(", ")
+            )));
+        }
+    };
+    serde_json::from_value(time_series)
+        .map_err(|e| PriceProviderError::ParserMismatch(format!("time series: {}", e)))
+}
+
+/// Alpha Vantage TIME_SERIES_DAILY or, when adjusted, the premium
+/// TIME_SERIES_DAILY_ADJUSTED with adjusted closes and split coefficients.
+pub struct AlphaVantagePriceProvider {
+    api_key: String,
+    adjusted: bool,
+}
+
+impl AlphaVantagePriceProvider {
+    pub fn new(api_key: String) -> Self {
+        AlphaVantagePriceProvider {
+            api_key,
+            adjusted: false,
+        }
+    }
+
+    pub fn adjusted(mut self, adjusted: bool) -> Self {
+        self.adjusted = adjusted;
+        self
+    }
+
+    async fn time_series(
+        &self,
+        symbol: &str,
+        output_size: &str,
+    ) -> Result<HashMap<String, AlphaVantageDailyPriceResponse>, PriceProviderError> {
+        let function = if self.adjusted {
+            "TIME_SERIES_DAILY_ADJUSTED"
+        } else {
+            "TIME_SERIES_DAILY"
+        };
+        let url = format!(
+            "https://www.alphavantage.co/query?function={}&symbol={}&apikey={}&outputsize={}",
+            function, symbol, self.api_key, output_size
+        );
+        parse_alpha_vantage_time_series(alpha_vantage::query(&url).await?)
+    }
+}
+
+#[async_trait]
+impl PriceProvider for AlphaVantagePriceProvider {
+    fn name(&self) -> &'static str {
+        "alpha_vantage"
+    }
+
+    async fn fetch_daily(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        // The compact output has the last 100 closes.
+        let output_size = if since > Utc::today().naive_utc() + Duration::days(-100) {
+            "compact"
+        } else {
+            "full"
+        };
+        let mut time_series = self.time_series(symbol, output_size).await?;
+        let mut since = since;
+        // Adjusted closes before a new split or dividend have changed, so the
+        // whole history is returned to replace the stored ones.
+        let adjusts_history = time_series.iter().any(|(date, price)| {
+            price.adjusts_history()
+                && NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok_and(|date| date > since)
+        });
+        if adjusts_history {
+            if output_size != "full" {
+                time_series = self.time_series(symbol, "full").await?;
+            }
+            since = chrono::naive::MIN_DATE;
+        }
+
+        let decimal = |value: &str| {
+            BigDecimal::from_str(value)
+                .map_err(|_| PriceProviderError::InvalidResponse(value.to_string()))
+        };
+        let fetched_at = Utc::now();
+        let mut prices = Vec::new();
+        for (date, price) in time_series {
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| PriceProviderError::InvalidResponse(date.clone()))?;
+            if date > since {
+                prices.push(DailyPrice {
+                    date,
+                    price: decimal(&price.price)?,
+                    ticker: symbol.to_string(),
+                    preliminary: is_preliminary(date, fetched_at),
+                    adjusted_price: price.adjusted_price.as_deref().map(decimal).transpose()?,
+                    split_coefficient: price
+                        .split_coefficient
+                        .as_deref()
+                        .map(decimal)
+                        .transpose()?,
+                    marks: PriceMarks::default(),
+                });
+            }
+        }
+        prices.sort_by_key(|price| price.date);
+        Ok(prices)
+    }
+}
+
+/// Stooq's daily history download, a CSV that needs no API key. Symbols are
+/// looked up lowercased.
+pub struct StooqPriceProvider;
+
+#[async_trait]
+impl PriceProvider for StooqPriceProvider {
+    fn name(&self) -> &'static str {
+        "stooq"
+    }
+
+    async fn fetch_daily(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        let url = format!(
+            "https://stooq.com/q/d/l/?s={}&i=d&d1={}",
+            symbol.to_lowercase(),
+            since.succ().format("%Y%m%d")
+        );
+        let body = reqwest::get(url).await?.text().await?;
+        // Unknown symbols and dates without closes are answered with a body
+        // that isn't CSV.
+        let mut lines = body.lines();
+        if lines.next().map(str::trim) != Some("Date,Open,High,Low,Close,Volume") {
+            return Ok(Vec::new());
+        }
+
+        let fetched_at = Utc::now();
+        let mut prices = Vec::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.trim().split(',').collect();
+            let (date, close) = match fields.as_slice() {
+                [date, _, _, _, close, ..] => (*date, *close),
+                _ => return Err(PriceProviderError::ParserMismatch(line.to_string())),
+            };
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| PriceProviderError::InvalidResponse(date.to_string()))?;
+            if date > since {
+                prices.push(DailyPrice {
+                    date,
+                    price: BigDecimal::from_str(close)
+                        .map_err(|_| PriceProviderError::InvalidResponse(close.to_string()))?,
+                    ticker: symbol.to_string(),
+                    preliminary: is_preliminary(date, fetched_at),
+                    adjusted_price: None,
+                    split_coefficient: None,
+                    marks: PriceMarks::default(),
+                });
+            }
+        }
+        prices.sort_by_key(|price| price.date);
+        Ok(prices)
+    }
+}
+
+/// Deterministic synthetic closes on weekdays, for development and demos
+/// without network access or API keys. Every symbol gets its own random walk,
+/// and the close of a date is the same on every call.
+pub struct MockPriceProvider;
+
+#[async_trait]
+impl PriceProvider for MockPriceProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn fetch_daily(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        let fetched_at = Utc::now();
+        let today = fetched_at.naive_utc().date();
+        let mut prices = Vec::new();
+        for (date, close) in mock::random_walk(symbol, today, false, (20.0, 200.0), 0.02) {
+            if date <= since {
+                continue;
+            }
+            let close = format!("{:.4}", close);
+            prices.push(DailyPrice {
+                date,
+                price: BigDecimal::from_str(&close)
+                    .map_err(|_| PriceProviderError::InvalidResponse(close))?,
+                ticker: symbol.to_string(),
+                preliminary: is_preliminary(date, fetched_at),
+                adjusted_price: None,
+                split_coefficient: None,
+                marks: PriceMarks::default(),
+            });
+        }
+        Ok(prices)
+    }
+}
+
+#[derive(Deserialize)]
+struct CoinGeckoMarketChartResponse {
+    /// Pairs of a Unix timestamp in milliseconds and a price.
+    prices: Vec<(i64, f64)>,
+}
+
+/// CoinGecko market chart of a cryptocurrency, in the currency its ticker is
+/// quoted in. Symbols are CoinGecko coin ids, like `bitcoin`. Cryptocurrencies
+/// trade around the clock, so the price of today is preliminary until the day
+/// is over in UTC.
+pub struct CoinGeckoPriceProvider {
+    vs_currency: String,
+}
+
+impl CoinGeckoPriceProvider {
+    /// Prices quoted in `currency`, an ISO 4217 code like `EUR`.
+    pub fn new(currency: &str) -> Self {
+        CoinGeckoPriceProvider {
+            vs_currency: currency.to_lowercase(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceProvider for CoinGeckoPriceProvider {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    async fn fetch_daily(
+        &self,
+        symbol: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<DailyPrice>, PriceProviderError> {
+        let fetched_at = Utc::now();
+        let today = fetched_at.naive_utc().date();
+        let days = if since == chrono::naive::MIN_DATE {
+            "max".to_string()
+        } else {
+            (today - since).num_days().max(1).to_string()
+        };
+        let url = format!(
+            "https://api.coingecko.com/api/v3/coins/{}/market_chart?vs_currency={}&days={}&interval=daily",
+            symbol, self.vs_currency, days
+        );
+        let resp = reqwest::get(url)
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| PriceProviderError::InvalidResponse(e.to_string()))?;
+        let resp = serde_json::from_value::<CoinGeckoMarketChartResponse>(resp)
+            .map_err(|e| PriceProviderError::ParserMismatch(e.to_string()))?;
+
+        // The last point is the current price, on the same day as the daily
+        // point of today; later points replace earlier ones of their day.
+        let mut prices: Vec<DailyPrice> = Vec::new();
+        for (timestamp, price) in resp.prices {
+            let date = NaiveDateTime::from_timestamp(timestamp / 1000, 0).date();
+            if date <= since {
+                continue;
+            }
+            let price = BigDecimal::from_str(&price.to_string())
+                .map_err(|_| PriceProviderError::InvalidResponse(price.to_string()))?;
+            if prices.last().is_some_and(|last| last.date == date) {
+                prices.pop();
+            }
+            prices.push(DailyPrice {
+                date,
+                price,
+                ticker: symbol.to_string(),
+                preliminary: date >= today,
+                adjusted_price: None,
+                split_coefficient: None,
+                marks: PriceMarks::default(),
+            });
+        }
+        Ok(prices)
+    }
+}
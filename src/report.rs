@@ -0,0 +1,1123 @@
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use crate::dividend::DividendForCalculation;
+use crate::portfolio::Portfolio;
+use crate::trade::TradeForCalculation;
+use crate::xirr::CashFlow;
+use bigdecimal::{BigDecimal, ToPrimitive, Zero};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+pub struct RealizedGain {
+    pub trade_id: i64,
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub units: BigDecimal,
+    pub proceeds: BigDecimal,
+    pub cost: BigDecimal,
+    pub gain: BigDecimal,
+    /// Units sold beyond the open buys, like when earlier buys were never
+    /// recorded. Zero when the sell is covered.
+    pub unmatched_units: BigDecimal,
+}
+
+struct Lot {
+    units: BigDecimal,
+    /// Cost of one unit, including its share of the buy fee.
+    unit_cost: BigDecimal,
+}
+
+/// Matches every sell against the oldest buys still open for the same ticker
+/// (FIFO). Trades must be sorted by date. Units sold beyond what was bought
+/// have no known cost, so they are costed at their share of the proceeds and
+/// reported as unmatched rather than counted as gain. Buy fees are part of the
+/// cost and sell fees reduce the proceeds.
+pub fn realized_gains(trades: &[TradeForCalculation]) -> Vec<RealizedGain> {
+    let mut open_lots: HashMap<&str, VecDeque<Lot>> = HashMap::new();
+    let mut realized_gains = Vec::new();
+
+    for trade in trades {
+        let lots = open_lots.entry(&trade.ticker).or_default();
+        if trade.amount > BigDecimal::zero() {
+            lots.push_back(Lot {
+                units: trade.amount.clone(),
+                unit_cost: trade.cash_flow() / &trade.amount,
+            });
+            continue;
+        }
+        if trade.amount.is_zero() {
+            continue;
+        }
+
+        let units_sold = -&trade.amount;
+        let mut units_to_match = units_sold.clone();
+        let mut cost = BigDecimal::zero();
+        while units_to_match > BigDecimal::zero() {
+            let lot = match lots.front_mut() {
+                Some(lot) => lot,
+                None => break,
+            };
+            let matched_units = units_to_match.clone().min(lot.units.clone());
+            cost += &lot.unit_cost * &matched_units;
+            lot.units -= &matched_units;
+            units_to_match -= &matched_units;
+            if lot.units.is_zero() {
+                lots.pop_front();
+            }
+        }
+
+        let proceeds = -trade.cash_flow();
+        if units_to_match > BigDecimal::zero() {
+            cost += &proceeds * &units_to_match / &units_sold;
+        }
+        let gain = &proceeds - &cost;
+        realized_gains.push(RealizedGain {
+            trade_id: trade.id,
+            ticker: trade.ticker.clone(),
+            date: trade.date,
+            units: units_sold,
+            proceeds: round_half_up(proceeds, AMOUNT_SCALE),
+            cost: round_half_up(cost, AMOUNT_SCALE),
+            gain: round_half_up(gain, AMOUNT_SCALE),
+            unmatched_units: units_to_match,
+        });
+    }
+
+    realized_gains
+}
+
+pub fn realized_gains_per_year(realized_gains: &[RealizedGain]) -> BTreeMap<i32, BigDecimal> {
+    let mut per_year: BTreeMap<i32, BigDecimal> = BTreeMap::new();
+    for realized_gain in realized_gains {
+        *per_year.entry(realized_gain.date.year()).or_default() += &realized_gain.gain;
+    }
+    per_year
+}
+
+/// Net dividends received per year of the pay date.
+pub fn dividends_per_year(dividends: &[DividendForCalculation]) -> BTreeMap<i32, BigDecimal> {
+    let mut per_year: BTreeMap<i32, BigDecimal> = BTreeMap::new();
+    for dividend in dividends {
+        *per_year.entry(dividend.pay_date.year()).or_default() += &dividend.net_amount;
+    }
+    per_year
+}
+
+/// Net dividends as cash flows into the investor's pocket (positive).
+pub fn dividend_cash_flows(dividends: &[DividendForCalculation]) -> Vec<CashFlow> {
+    dividends
+        .iter()
+        .map(|dividend| CashFlow {
+            date: dividend.pay_date,
+            amount: dividend.net_amount.to_f64().unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub struct ExpectedIncome {
+    pub ticker: String,
+    /// Units held today.
+    pub units: BigDecimal,
+    /// Net dividends paid over the last year.
+    pub received: BigDecimal,
+    /// Net dividends the units held today would be paid over a year, at the
+    /// dividends per unit of the last one.
+    pub expected: BigDecimal,
+}
+
+/// Dividend income of every ticker held today or paid dividends over the last
+/// year, sorted by ticker. The dividend per unit is the net amount over the
+/// units held before the ex-date. Trades must be sorted by date.
+pub fn expected_income(
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+    today: NaiveDate,
+) -> Vec<ExpectedIncome> {
+    let units_before = |ticker: &str, date: NaiveDate| -> BigDecimal {
+        trades
+            .iter()
+            .take_while(|trade| trade.date < date)
+            .filter(|trade| trade.ticker == ticker)
+            .map(|trade| &trade.amount)
+            .sum()
+    };
+    let mut incomes: BTreeMap<&str, (BigDecimal, BigDecimal)> = BTreeMap::new();
+    for trade in trades {
+        incomes.entry(&trade.ticker).or_default();
+    }
+    let since = years_before(today, 1);
+    for dividend in dividends
+        .iter()
+        .filter(|dividend| dividend.pay_date > since && dividend.pay_date <= today)
+    {
+        let (received, per_unit) = incomes.entry(&dividend.ticker).or_default();
+        *received += &dividend.net_amount;
+        let units = units_before(&dividend.ticker, dividend.ex_date);
+        if units > BigDecimal::zero() {
+            *per_unit += &dividend.net_amount / units;
+        }
+    }
+
+    incomes
+        .into_iter()
+        .map(|(ticker, (received, per_unit))| {
+            let units = units_before(ticker, today.succ());
+            ExpectedIncome {
+                ticker: ticker.to_string(),
+                expected: round_half_up(&per_unit * &units, AMOUNT_SCALE),
+                received: round_half_up(received, AMOUNT_SCALE),
+                units,
+            }
+        })
+        .filter(|income| !income.units.is_zero() || !income.received.is_zero())
+        .collect()
+}
+
+/// Cash flows implied by trades from the investor's point of view: buys take
+/// money out of the pocket (negative), sells put it back (positive).
+pub fn trade_cash_flows(trades: &[TradeForCalculation]) -> Vec<CashFlow> {
+    trades
+        .iter()
+        .map(|trade| CashFlow {
+            date: trade.date,
+            amount: -trade.cash_flow().to_f64().unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub enum StatementEntryKind {
+    Buy,
+    Sell,
+    Dividend,
+}
+
+pub struct StatementEntry {
+    pub date: NaiveDate,
+    pub kind: StatementEntryKind,
+    pub ticker: String,
+    /// Money into the investor's pocket (positive) or out of it (negative).
+    pub amount: BigDecimal,
+}
+
+/// Every external cash flow of the portfolio in chronological order, with the
+/// same signs used for the XIRR.
+pub fn cash_flow_statement(
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+) -> Vec<StatementEntry> {
+    let mut entries: Vec<StatementEntry> = trades
+        .iter()
+        .map(|trade| StatementEntry {
+            date: trade.date,
+            kind: if trade.amount >= BigDecimal::zero() {
+                StatementEntryKind::Buy
+            } else {
+                StatementEntryKind::Sell
+            },
+            ticker: trade.ticker.clone(),
+            amount: round_half_up(-trade.cash_flow(), AMOUNT_SCALE),
+        })
+        .chain(dividends.iter().map(|dividend| StatementEntry {
+            date: dividend.pay_date,
+            kind: StatementEntryKind::Dividend,
+            ticker: dividend.ticker.clone(),
+            amount: round_half_up(dividend.net_amount.clone(), AMOUNT_SCALE),
+        }))
+        .collect();
+    entries.sort_by_key(|entry| entry.date);
+    entries
+}
+
+/// Fewest months with buys a contribution schedule is detected from.
+const MIN_CONTRIBUTION_MONTHS: usize = 3;
+
+pub enum ContributionStatus {
+    OnSchedule,
+    Skipped,
+    Deviated,
+}
+
+pub struct MonthlyContribution {
+    /// First day of the month, on the month start day of the schedule.
+    pub month: NaiveDate,
+    pub contributed: BigDecimal,
+    pub status: ContributionStatus,
+}
+
+/// How money usually goes into the portfolio: about `amount` a month, around
+/// `day_of_month`.
+pub struct ContributionSchedule {
+    pub amount: BigDecimal,
+    pub day_of_month: u32,
+    pub months: Vec<MonthlyContribution>,
+}
+
+/// Latest day numbered `start_day` on or before `date`. Days past the 28th
+/// don't exist in every month, so `start_day` must be at most 28.
+fn month_start(date: NaiveDate, start_day: u32) -> NaiveDate {
+    if date.day() >= start_day {
+        NaiveDate::from_ymd(date.year(), date.month(), start_day)
+    } else {
+        previous_month(NaiveDate::from_ymd(date.year(), date.month(), start_day))
+    }
+}
+
+fn next_month(month: NaiveDate) -> NaiveDate {
+    if month.month() == 12 {
+        NaiveDate::from_ymd(month.year() + 1, 1, month.day())
+    } else {
+        NaiveDate::from_ymd(month.year(), month.month() + 1, month.day())
+    }
+}
+
+fn previous_month(month: NaiveDate) -> NaiveDate {
+    if month.month() == 1 {
+        NaiveDate::from_ymd(month.year() - 1, 12, month.day())
+    } else {
+        NaiveDate::from_ymd(month.year(), month.month() - 1, month.day())
+    }
+}
+
+fn median<T: Clone + Ord>(mut values: Vec<T>) -> Option<T> {
+    values.sort();
+    values.get(values.len() / 2).cloned()
+}
+
+/// Detects the monthly contribution schedule followed by the buys, with
+/// months starting on `month_start_day` (see `month_start`): the median
+/// monthly amount bought and the median day of the first buy of a month.
+/// Every month from the first buy until `until` is then flagged as skipped
+/// when nothing was bought, or deviated when the amount is more than
+/// `tolerance_percent` away from the usual one. The month of `until` is left
+/// out while its usual day hasn't come. `None` with too few months of buys to
+/// tell a pattern.
+pub fn contribution_schedule(
+    trades: &[TradeForCalculation],
+    tolerance_percent: &BigDecimal,
+    month_start_day: u32,
+    until: NaiveDate,
+) -> Option<ContributionSchedule> {
+    let mut per_month: BTreeMap<NaiveDate, (BigDecimal, NaiveDate)> = BTreeMap::new();
+    for trade in trades
+        .iter()
+        .filter(|trade| trade.amount > BigDecimal::zero())
+    {
+        let (contributed, first_date) = per_month
+            .entry(month_start(trade.date, month_start_day))
+            .or_insert_with(|| (BigDecimal::zero(), trade.date));
+        *contributed += trade.cash_flow();
+        *first_date = (*first_date).min(trade.date);
+    }
+    if per_month.len() < MIN_CONTRIBUTION_MONTHS {
+        return None;
+    }
+
+    let amount = median(
+        per_month
+            .values()
+            .map(|(amount, _)| amount.clone())
+            .collect(),
+    )?;
+    let day_of_month = median(
+        per_month
+            .values()
+            .map(|(_, first_date)| first_date.day())
+            .collect(),
+    )?;
+    let days_into_month = median(
+        per_month
+            .iter()
+            .map(|(month, (_, first_date))| (*first_date - *month).num_days())
+            .collect(),
+    )?;
+    let tolerance = &amount * tolerance_percent / BigDecimal::from(100);
+
+    let mut months = Vec::new();
+    let mut month = *per_month.keys().next()?;
+    let until_month = month_start(until, month_start_day);
+    let last_month = if until >= until_month + Duration::days(days_into_month) {
+        until_month
+    } else {
+        previous_month(until_month)
+    };
+    while month <= last_month || per_month.contains_key(&month) {
+        let (contributed, status) = match per_month.remove(&month) {
+            None => (BigDecimal::zero(), ContributionStatus::Skipped),
+            Some((contributed, _)) if (&contributed - &amount).abs() > tolerance => {
+                (contributed, ContributionStatus::Deviated)
+            }
+            Some((contributed, _)) => (contributed, ContributionStatus::OnSchedule),
+        };
+        months.push(MonthlyContribution {
+            month,
+            contributed: round_half_up(contributed, AMOUNT_SCALE),
+            status,
+        });
+        month = next_month(month);
+    }
+
+    Some(ContributionSchedule {
+        amount: round_half_up(amount, AMOUNT_SCALE),
+        day_of_month,
+        months,
+    })
+}
+
+/// Growth of one unit invested at the first non-zero point of the series,
+/// ignoring contributions and withdrawals: every step between two points is a
+/// sub-period whose return excludes the money moved by trades in it, and the
+/// sub-period returns are chained geometrically. Dividends paid out count as
+/// part of the return of their sub-period.
+pub fn growth_index(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+) -> Vec<(NaiveDate, f64)> {
+    let mut cash_flows: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+    for trade in trades {
+        *cash_flows.entry(trade.date).or_default() +=
+            trade.cash_flow().to_f64().unwrap_or_default();
+    }
+    for dividend in dividends {
+        *cash_flows.entry(dividend.pay_date).or_default() -=
+            dividend.net_amount.to_f64().unwrap_or_default();
+    }
+
+    let mut index: Vec<(NaiveDate, f64)> = Vec::new();
+    for window in series.windows(2) {
+        let start_value = window[0]
+            .amount_in_base_currency
+            .to_f64()
+            .unwrap_or_default();
+        let end_value = window[1]
+            .amount_in_base_currency
+            .to_f64()
+            .unwrap_or_default();
+        let cash_flow: f64 = cash_flows
+            .range(window[0].date.succ()..=window[1].date)
+            .map(|(_, amount)| amount)
+            .sum();
+        if start_value == 0.0 {
+            continue;
+        }
+        let growth = match index.last() {
+            Some((_, growth)) => *growth,
+            None => {
+                index.push((window[0].date, 1.0));
+                1.0
+            }
+        };
+        index.push((
+            window[1].date,
+            growth * (end_value - cash_flow) / start_value,
+        ));
+    }
+    index
+}
+
+/// Time-weighted return of a daily value series, see `growth_index`.
+pub fn time_weighted_return(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+) -> Option<f64> {
+    let index = growth_index(series, trades, dividends);
+    if index.len() < 2 {
+        return None;
+    }
+    index.last().map(|(_, growth)| growth - 1.0)
+}
+
+/// Money put into the portfolio between the two dates: trades minus dividends
+/// paid out.
+fn cash_flow_between(
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> BigDecimal {
+    let trade_flows: BigDecimal = trades
+        .iter()
+        .filter(|trade| trade.date > from && trade.date <= to)
+        .map(|trade| trade.cash_flow())
+        .sum();
+    let dividend_flows: BigDecimal = dividends
+        .iter()
+        .filter(|dividend| dividend.pay_date > from && dividend.pay_date <= to)
+        .map(|dividend| &dividend.net_amount)
+        .sum();
+    trade_flows - dividend_flows
+}
+
+pub struct PeriodReturn {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub absolute_return: BigDecimal,
+    pub time_weighted_return: Option<f64>,
+}
+
+/// Return of the series between the last point on or before `from` and its last
+/// point. The absolute return excludes money added or withdrawn by trades in
+/// the period and includes dividends paid in it. `None` when the series doesn't
+/// reach back to `from`.
+pub fn period_return(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+    from: NaiveDate,
+) -> Option<PeriodReturn> {
+    let start_index = series.iter().rposition(|day| day.date <= from)?;
+    let period = &series[start_index..];
+    let start = period.first()?;
+    let end = period.last()?;
+
+    let absolute_return = &end.amount_in_base_currency
+        - &start.amount_in_base_currency
+        - cash_flow_between(trades, dividends, start.date, end.date);
+    Some(PeriodReturn {
+        from: start.date,
+        to: end.date,
+        absolute_return: round_half_up(absolute_return, AMOUNT_SCALE),
+        time_weighted_return: time_weighted_return(period, trades, dividends),
+    })
+}
+
+/// Change of the portfolio value over a period split into what caused it. The
+/// components add up from the starting to the ending value: dividends are
+/// paid out, so they count as withdrawals too, and fees are money put in that
+/// didn't buy units.
+pub struct Waterfall {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub starting_value: BigDecimal,
+    /// Money put in by buys, fees included.
+    pub contributions: BigDecimal,
+    /// Money taken out by sells, net of fees, and by dividends (negative).
+    pub withdrawals: BigDecimal,
+    pub market_gains: BigDecimal,
+    pub dividends: BigDecimal,
+    /// Negative.
+    pub fees: BigDecimal,
+    pub ending_value: BigDecimal,
+}
+
+/// Waterfall of the series between its last point on or before `from`, zero
+/// when there is none, and its last point on or before `to`. `None` when the
+/// series has no point on or before `to`.
+pub fn waterfall(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Option<Waterfall> {
+    let end = series.iter().rev().find(|day| day.date <= to)?;
+    let starting_value = series
+        .iter()
+        .rev()
+        .find(|day| day.date <= from)
+        .map(|day| day.amount_in_base_currency.clone())
+        .unwrap_or_default();
+    let in_period = |date: NaiveDate| date > from && date <= end.date;
+
+    let mut contributions = BigDecimal::zero();
+    let mut withdrawals = BigDecimal::zero();
+    let mut fees = BigDecimal::zero();
+    for trade in trades.iter().filter(|trade| in_period(trade.date)) {
+        if trade.amount >= BigDecimal::zero() {
+            contributions += trade.cash_flow();
+        } else {
+            withdrawals += trade.cash_flow();
+        }
+        fees -= &trade.fee;
+    }
+    let dividends: BigDecimal = dividends
+        .iter()
+        .filter(|dividend| in_period(dividend.pay_date))
+        .map(|dividend| &dividend.net_amount)
+        .sum();
+    withdrawals -= &dividends;
+
+    let market_gains = &end.amount_in_base_currency
+        - &starting_value
+        - &contributions
+        - &withdrawals
+        - &dividends
+        - &fees;
+    Some(Waterfall {
+        from,
+        to: end.date,
+        starting_value: round_half_up(starting_value, AMOUNT_SCALE),
+        contributions: round_half_up(contributions, AMOUNT_SCALE),
+        withdrawals: round_half_up(withdrawals, AMOUNT_SCALE),
+        market_gains: round_half_up(market_gains, AMOUNT_SCALE),
+        dividends: round_half_up(dividends, AMOUNT_SCALE),
+        fees: round_half_up(fees, AMOUNT_SCALE),
+        ending_value: round_half_up(end.amount_in_base_currency.clone(), AMOUNT_SCALE),
+    })
+}
+
+/// Compound annual growth rate of the time-weighted return over the whole
+/// series.
+pub fn cagr(
+    series: &[Portfolio],
+    trades: &[TradeForCalculation],
+    dividends: &[DividendForCalculation],
+) -> Option<f64> {
+    let years = (series.last()?.date - series.first()?.date).num_days() as f64 / 365.25;
+    if years <= 0.0 {
+        return None;
+    }
+    let twr = time_weighted_return(series, trades, dividends)?;
+    Some((1.0 + twr).powf(1.0 / years) - 1.0)
+}
+
+/// Same calendar day `years` years earlier, falling back to Feb 28th for leap
+/// days.
+pub fn years_before(date: NaiveDate, years: i32) -> NaiveDate {
+    date.with_year(date.year() - years)
+        .unwrap_or_else(|| date - Duration::days(1) - Duration::days(365 * years as i64))
+}
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+/// Returns between consecutive weekdays of a growth index. Weekends only hold
+/// forward-filled values, so they are left out to not dilute the volatility.
+pub fn daily_returns(index: &[(NaiveDate, f64)]) -> Vec<f64> {
+    let weekdays: Vec<f64> = index
+        .iter()
+        .filter(|(date, _)| date.weekday().number_from_monday() <= 5)
+        .map(|(_, growth)| *growth)
+        .collect();
+    weekdays
+        .windows(2)
+        .map(|window| window[1] / window[0] - 1.0)
+        .collect()
+}
+
+pub struct Drawdown {
+    pub max_drawdown: f64,
+    pub peak_date: NaiveDate,
+    pub trough_date: NaiveDate,
+    /// Longest time, in days, spent below a previous peak.
+    pub longest_duration_days: i64,
+}
+
+pub fn drawdown(index: &[(NaiveDate, f64)]) -> Option<Drawdown> {
+    let (first_date, first_growth) = *index.first()?;
+    let mut peak = (first_date, first_growth);
+    let mut drawdown = Drawdown {
+        max_drawdown: 0.0,
+        peak_date: first_date,
+        trough_date: first_date,
+        longest_duration_days: 0,
+    };
+
+    for (date, growth) in index {
+        if *growth >= peak.1 {
+            peak = (*date, *growth);
+            continue;
+        }
+        let current_drawdown = growth / peak.1 - 1.0;
+        if current_drawdown < drawdown.max_drawdown {
+            drawdown.max_drawdown = current_drawdown;
+            drawdown.peak_date = peak.0;
+            drawdown.trough_date = *date;
+        }
+        drawdown.longest_duration_days = drawdown
+            .longest_duration_days
+            .max((*date - peak.0).num_days());
+    }
+    Some(drawdown)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation, `None` with fewer than two values.
+fn standard_deviation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values);
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / (values.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+/// Annualized standard deviation of daily returns.
+pub fn annualized_volatility(returns: &[f64]) -> Option<f64> {
+    Some(standard_deviation(returns)? * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Daily returns in excess of the annual `risk_free_rate`.
+fn excess_returns(returns: &[f64], risk_free_rate: f64) -> Vec<f64> {
+    let daily_risk_free_rate = risk_free_rate / TRADING_DAYS_PER_YEAR;
+    returns
+        .iter()
+        .map(|daily_return| daily_return - daily_risk_free_rate)
+        .collect()
+}
+
+/// Annualized mean excess return over the annualized volatility of the excess
+/// returns. `None` when the volatility is zero.
+pub fn sharpe_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    let excess_returns = excess_returns(returns, risk_free_rate);
+    let deviation = standard_deviation(&excess_returns)?;
+    if deviation == 0.0 {
+        return None;
+    }
+    Some(mean(&excess_returns) / deviation * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+/// Like the Sharpe ratio, but only days below the risk-free rate count as
+/// risk. `None` when there is no such day.
+pub fn sortino_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    let excess_returns = excess_returns(returns, risk_free_rate);
+    if excess_returns.len() < 2 {
+        return None;
+    }
+    let downside_deviation = (excess_returns
+        .iter()
+        .map(|excess_return| excess_return.min(0.0).powi(2))
+        .sum::<f64>()
+        / excess_returns.len() as f64)
+        .sqrt();
+    if downside_deviation == 0.0 {
+        return None;
+    }
+    Some(mean(&excess_returns) / downside_deviation * TRADING_DAYS_PER_YEAR.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal, point, trade};
+
+    #[test]
+    fn realized_gains_match_the_oldest_lots_first() {
+        let trades = vec![
+            trade(1, "2024-01-10", "IWDA.AMS", "10", "100", "5"),
+            trade(2, "2024-02-10", "IWDA.AMS", "10", "120", "0"),
+            trade(3, "2024-03-10", "IWDA.AMS", "-15", "130", "5"),
+        ];
+
+        let realized_gains = realized_gains(&trades);
+
+        assert_eq!(realized_gains.len(), 1);
+        let realized_gain = &realized_gains[0];
+        assert_eq!(realized_gain.trade_id, 3);
+        assert_eq!(realized_gain.units, decimal("15"));
+        // 10 units at 100.5 each, fee included, and 5 units at 120.
+        assert_eq!(realized_gain.cost, decimal("1605"));
+        assert_eq!(realized_gain.proceeds, decimal("1945"));
+        assert_eq!(realized_gain.gain, decimal("340"));
+        assert_eq!(realized_gain.unmatched_units, BigDecimal::zero());
+    }
+
+    #[test]
+    fn realized_gains_keep_the_rest_of_a_partly_sold_lot_open() {
+        let trades = vec![
+            trade(1, "2024-01-10", "IWDA.AMS", "10", "100", "0"),
+            trade(2, "2024-02-10", "IWDA.AMS", "-4", "110", "0"),
+            trade(3, "2024-03-10", "IWDA.AMS", "-6", "90", "0"),
+        ];
+
+        let gains: Vec<BigDecimal> = realized_gains(&trades)
+            .into_iter()
+            .map(|realized_gain| realized_gain.gain)
+            .collect();
+
+        assert_eq!(gains, vec![decimal("40"), decimal("-60")]);
+    }
+
+    #[test]
+    fn realized_gains_match_lots_of_the_same_ticker_only() {
+        let trades = vec![
+            trade(1, "2024-01-10", "IWDA.AMS", "10", "100", "0"),
+            trade(2, "2024-01-11", "NQSE.DEX", "10", "50", "0"),
+            trade(3, "2024-03-10", "NQSE.DEX", "-10", "60", "0"),
+        ];
+
+        let realized_gains = realized_gains(&trades);
+
+        assert_eq!(realized_gains.len(), 1);
+        assert_eq!(realized_gains[0].cost, decimal("500"));
+        assert_eq!(realized_gains[0].gain, decimal("100"));
+    }
+
+    #[test]
+    fn realized_gains_cost_a_sell_without_a_buy_at_its_proceeds() {
+        let trades = vec![trade(1, "2024-03-10", "IWDA.AMS", "-5", "130", "0")];
+
+        let realized_gains = realized_gains(&trades);
+
+        assert_eq!(realized_gains[0].unmatched_units, decimal("5"));
+        assert_eq!(realized_gains[0].cost, decimal("650"));
+        assert_eq!(realized_gains[0].gain, BigDecimal::zero());
+    }
+
+    #[test]
+    fn realized_gains_only_count_the_covered_part_of_a_sell() {
+        let trades = vec![
+            trade(1, "2024-01-10", "IWDA.AMS", "4", "100", "0"),
+            trade(2, "2024-03-10", "IWDA.AMS", "-10", "130", "0"),
+        ];
+
+        let realized_gains = realized_gains(&trades);
+
+        assert_eq!(realized_gains[0].unmatched_units, decimal("6"));
+        // 4 units at 100, the 6 unmatched ones at their proceeds of 130.
+        assert_eq!(realized_gains[0].cost, decimal("1180"));
+        assert_eq!(realized_gains[0].gain, decimal("120"));
+    }
+
+    #[test]
+    fn growth_index_leaves_out_money_moved_by_trades() {
+        let series = vec![
+            point("2024-05-01", "1000"),
+            point("2024-05-02", "1600"),
+            point("2024-05-03", "1760"),
+        ];
+        // 500 bought on the second day, so the value only grew 100 by itself.
+        let trades = vec![trade(1, "2024-05-02", "IWDA.AMS", "5", "100", "0")];
+
+        assert_eq!(
+            growth_index(&series, &trades, &[]),
+            vec![
+                (date("2024-05-01"), 1.0),
+                (date("2024-05-02"), 1.1),
+                (date("2024-05-03"), 1.1 * 1.1),
+            ]
+        );
+    }
+
+    #[test]
+    fn expected_income_projects_last_years_dividends_per_unit_onto_current_units() {
+        let trades = vec![
+            trade(1, "2023-01-10", "IWDA.AMS", "10", "70", "0"),
+            trade(2, "2024-06-10", "IWDA.AMS", "10", "80", "0"),
+            trade(3, "2024-01-10", "NQSE.DE", "5", "20", "0"),
+            trade(4, "2024-02-10", "NQSE.DE", "-5", "22", "0"),
+        ];
+        let dividend = |ex_date: &str, pay_date: &str, net_amount: &str| DividendForCalculation {
+            ex_date: date(ex_date),
+            pay_date: date(pay_date),
+            net_amount: decimal(net_amount),
+            currency: None,
+            ticker: "IWDA.AMS".to_string(),
+        };
+        let dividends = vec![
+            dividend("2023-09-01", "2023-09-10", "4"),
+            dividend("2024-03-01", "2024-03-10", "5"),
+            dividend("2024-09-01", "2024-09-10", "10"),
+        ];
+
+        let incomes = expected_income(&trades, &dividends, date("2024-12-31"));
+
+        assert_eq!(incomes.len(), 1);
+        assert_eq!(incomes[0].ticker, "IWDA.AMS");
+        assert_eq!(incomes[0].units, decimal("20"));
+        assert_eq!(incomes[0].received, decimal("15"));
+        assert_eq!(incomes[0].expected, decimal("20"));
+    }
+
+    fn paid(pay_date: &str, net_amount: &str) -> DividendForCalculation {
+        DividendForCalculation {
+            ex_date: date(pay_date),
+            pay_date: date(pay_date),
+            net_amount: decimal(net_amount),
+            currency: None,
+            ticker: "IWDA.AMS".to_string(),
+        }
+    }
+
+    #[test]
+    fn dividends_per_year_sum_by_the_year_of_the_pay_date() {
+        let dividends = vec![
+            paid("2023-12-28", "10"),
+            paid("2024-01-03", "12.5"),
+            paid("2024-06-03", "7.5"),
+        ];
+
+        assert_eq!(
+            dividends_per_year(&dividends)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(2023, decimal("10")), (2024, decimal("20"))]
+        );
+    }
+
+    #[test]
+    fn dividend_cash_flows_go_into_the_pocket_on_the_pay_date() {
+        let cash_flows = dividend_cash_flows(&[paid("2024-06-03", "7.5")]);
+
+        assert_eq!(cash_flows.len(), 1);
+        assert_eq!(cash_flows[0].date, date("2024-06-03"));
+        assert_eq!(cash_flows[0].amount, 7.5);
+    }
+
+    #[test]
+    fn growth_index_counts_dividends_paid_out_as_return() {
+        let series = vec![point("2024-05-01", "1000"), point("2024-05-02", "1000")];
+        let dividends = vec![DividendForCalculation {
+            ex_date: date("2024-04-25"),
+            pay_date: date("2024-05-02"),
+            net_amount: decimal("50"),
+            currency: None,
+            ticker: "IWDA.AMS".to_string(),
+        }];
+
+        assert_eq!(
+            growth_index(&series, &[], &dividends),
+            vec![(date("2024-05-01"), 1.0), (date("2024-05-02"), 1.05)]
+        );
+    }
+
+    #[test]
+    fn growth_index_starts_at_the_first_non_zero_point() {
+        let series = vec![
+            point("2024-05-01", "0"),
+            point("2024-05-02", "1000"),
+            point("2024-05-03", "900"),
+        ];
+        let trades = vec![trade(1, "2024-05-02", "IWDA.AMS", "10", "100", "0")];
+
+        assert_eq!(
+            growth_index(&series, &trades, &[]),
+            vec![(date("2024-05-02"), 1.0), (date("2024-05-03"), 0.9)]
+        );
+    }
+
+    #[test]
+    fn growth_index_is_empty_without_two_points() {
+        assert!(growth_index(&[point("2024-05-01", "1000")], &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn contribution_schedule_flags_skipped_and_deviated_months() {
+        let trades = vec![
+            trade(1, "2024-01-05", "IWDA.AMS", "5", "100", "0"),
+            trade(2, "2024-02-05", "IWDA.AMS", "5", "100", "0"),
+            trade(3, "2024-04-05", "IWDA.AMS", "3", "100", "0"),
+            trade(4, "2024-04-20", "NQSE.DE", "4", "50", "0"),
+            trade(5, "2024-04-25", "NQSE.DE", "-4", "55", "0"),
+            trade(6, "2024-05-06", "IWDA.AMS", "8", "100", "0"),
+        ];
+
+        let schedule =
+            contribution_schedule(&trades, &decimal("10"), 1, date("2024-05-20")).unwrap();
+
+        assert_eq!(schedule.amount, decimal("500"));
+        assert_eq!(schedule.day_of_month, 5);
+        let months: Vec<(NaiveDate, BigDecimal, &ContributionStatus)> = schedule
+            .months
+            .iter()
+            .map(|month| (month.month, month.contributed.clone(), &month.status))
+            .collect();
+        assert_eq!(months.len(), 5);
+        assert!(matches!(months[0], (_, _, ContributionStatus::OnSchedule)));
+        assert!(matches!(months[1], (_, _, ContributionStatus::OnSchedule)));
+        assert_eq!(months[2].0, date("2024-03-01"));
+        assert_eq!(months[2].1, decimal("0"));
+        assert!(matches!(months[2].2, ContributionStatus::Skipped));
+        assert!(matches!(months[3], (_, _, ContributionStatus::OnSchedule)));
+        assert_eq!(months[4].1, decimal("800"));
+        assert!(matches!(months[4].2, ContributionStatus::Deviated));
+    }
+
+    #[test]
+    fn contribution_schedule_leaves_out_the_month_before_its_usual_day() {
+        let trades = vec![
+            trade(1, "2024-01-15", "IWDA.AMS", "5", "100", "0"),
+            trade(2, "2024-02-15", "IWDA.AMS", "5", "100", "0"),
+            trade(3, "2024-03-15", "IWDA.AMS", "5", "100", "0"),
+        ];
+
+        let before = contribution_schedule(&trades, &decimal("10"), 1, date("2024-04-10")).unwrap();
+        let after = contribution_schedule(&trades, &decimal("10"), 1, date("2024-04-15")).unwrap();
+
+        assert_eq!(before.months.len(), 3);
+        assert_eq!(after.months.len(), 4);
+        assert!(matches!(
+            after.months[3].status,
+            ContributionStatus::Skipped
+        ));
+    }
+
+    #[test]
+    fn contribution_schedule_starts_months_on_the_month_start_day() {
+        let trades = vec![
+            trade(1, "2024-01-26", "IWDA.AMS", "5", "100", "0"),
+            trade(2, "2024-02-03", "IWDA.AMS", "5", "100", "0"),
+            trade(3, "2024-02-26", "IWDA.AMS", "5", "100", "0"),
+            trade(4, "2024-03-27", "IWDA.AMS", "10", "100", "0"),
+        ];
+
+        let schedule =
+            contribution_schedule(&trades, &decimal("10"), 25, date("2024-04-20")).unwrap();
+
+        assert_eq!(schedule.amount, decimal("1000"));
+        assert_eq!(
+            schedule
+                .months
+                .iter()
+                .map(|month| month.month)
+                .collect::<Vec<_>>(),
+            vec![date("2024-01-25"), date("2024-02-25"), date("2024-03-25")]
+        );
+    }
+
+    #[test]
+    fn contribution_schedule_needs_three_months_of_buys() {
+        let trades = vec![
+            trade(1, "2024-01-05", "IWDA.AMS", "5", "100", "0"),
+            trade(2, "2024-02-05", "IWDA.AMS", "5", "100", "0"),
+            trade(3, "2024-03-05", "IWDA.AMS", "-5", "100", "0"),
+        ];
+
+        assert!(contribution_schedule(&trades, &decimal("10"), 1, date("2024-06-01")).is_none());
+    }
+
+    #[test]
+    fn waterfall_components_add_up_to_the_ending_value() {
+        let series = vec![
+            point("2023-12-29", "1000"),
+            point("2024-06-28", "1500"),
+            point("2024-12-31", "1800"),
+            point("2025-01-02", "1900"),
+        ];
+        let trades = vec![
+            trade(1, "2023-06-01", "IWDA.AMS", "10", "90", "1"),
+            trade(2, "2024-03-01", "IWDA.AMS", "5", "100", "2"),
+            trade(3, "2024-09-02", "IWDA.AMS", "-2", "120", "1"),
+            trade(4, "2025-01-02", "IWDA.AMS", "1", "125", "1"),
+        ];
+        let dividends = vec![DividendForCalculation {
+            ex_date: date("2024-09-20"),
+            pay_date: date("2024-10-01"),
+            net_amount: decimal("30"),
+            currency: None,
+            ticker: "IWDA.AMS".to_string(),
+        }];
+
+        let waterfall = waterfall(
+            &series,
+            &trades,
+            &dividends,
+            date("2023-12-31"),
+            date("2024-12-31"),
+        )
+        .unwrap();
+
+        assert_eq!(waterfall.to, date("2024-12-31"));
+        assert_eq!(waterfall.starting_value, decimal("1000"));
+        assert_eq!(waterfall.contributions, decimal("502"));
+        assert_eq!(waterfall.withdrawals, decimal("-269"));
+        assert_eq!(waterfall.dividends, decimal("30"));
+        assert_eq!(waterfall.fees, decimal("-3"));
+        assert_eq!(waterfall.market_gains, decimal("540"));
+        assert_eq!(waterfall.ending_value, decimal("1800"));
+    }
+
+    #[test]
+    fn waterfall_ends_at_the_last_point_of_the_year() {
+        let series = vec![point("2024-03-01", "500"), point("2024-11-29", "600")];
+        let trades = vec![
+            trade(1, "2024-03-01", "IWDA.AMS", "5", "100", "0"),
+            trade(2, "2024-12-02", "IWDA.AMS", "5", "100", "0"),
+        ];
+
+        let waterfall = waterfall(
+            &series,
+            &trades,
+            &[],
+            date("2023-12-31"),
+            date("2024-12-31"),
+        )
+        .unwrap();
+
+        assert_eq!(waterfall.to, date("2024-11-29"));
+        assert_eq!(waterfall.starting_value, decimal("0"));
+        assert_eq!(waterfall.contributions, decimal("500"));
+        assert_eq!(waterfall.market_gains, decimal("100"));
+    }
+
+    #[test]
+    fn waterfall_is_none_before_the_series() {
+        assert!(waterfall(
+            &[point("2024-03-01", "500")],
+            &[],
+            &[],
+            date("2022-12-31"),
+            date("2023-12-31"),
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn drawdown_measures_the_deepest_fall_from_a_peak() {
+        let index = vec![
+            (date("2024-01-01"), 1.0),
+            (date("2024-01-10"), 1.2),
+            (date("2024-01-20"), 0.9),
+            (date("2024-02-09"), 1.0),
+            (date("2024-02-20"), 1.3),
+            (date("2024-02-21"), 1.2),
+        ];
+
+        let drawdown = drawdown(&index).unwrap();
+
+        assert!((drawdown.max_drawdown + 0.25).abs() < 1e-12);
+        assert_eq!(drawdown.peak_date, date("2024-01-10"));
+        assert_eq!(drawdown.trough_date, date("2024-01-20"));
+        assert_eq!(drawdown.longest_duration_days, 30);
+    }
+
+    #[test]
+    fn drawdown_is_zero_without_a_fall() {
+        let index = vec![(date("2024-01-01"), 1.0), (date("2024-01-02"), 1.1)];
+
+        let drawdown = drawdown(&index).unwrap();
+
+        assert_eq!(drawdown.max_drawdown, 0.0);
+        assert_eq!(drawdown.longest_duration_days, 0);
+    }
+
+    #[test]
+    fn drawdown_is_none_without_points() {
+        assert!(drawdown(&[]).is_none());
+    }
+
+    #[test]
+    fn sharpe_ratio_annualizes_the_mean_excess_return_over_its_deviation() {
+        let returns = [0.01, -0.01, 0.02, 0.0];
+
+        let sharpe = sharpe_ratio(&returns, 0.0).unwrap();
+        let with_risk_free_rate = sharpe_ratio(&returns, 0.252).unwrap();
+
+        assert!((sharpe - 37.8_f64.sqrt()).abs() < 1e-9);
+        let expected = 0.004 / (0.0005_f64 / 3.0).sqrt() * 252_f64.sqrt();
+        assert!((with_risk_free_rate - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sortino_ratio_only_counts_days_below_the_risk_free_rate_as_risk() {
+        let returns = [0.01, -0.01, 0.02, 0.0];
+
+        let sortino = sortino_ratio(&returns, 0.0).unwrap();
+
+        assert!((sortino - 252_f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ratios_are_none_without_risk() {
+        assert!(sharpe_ratio(&[0.01, 0.01, 0.01], 0.0).is_none());
+        assert!(sharpe_ratio(&[0.01], 0.0).is_none());
+        assert!(sortino_ratio(&[0.01, 0.02, 0.03], 0.0).is_none());
+        assert!(sortino_ratio(&[-0.01], 0.0).is_none());
+    }
+}
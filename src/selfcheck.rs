@@ -0,0 +1,170 @@
+use crate::db::{DbPool, MIGRATOR};
+use serde::Serialize;
+use sqlx::Row;
+use std::collections::HashSet;
+use std::time::Duration;
+
+const PROVIDER_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// Works, but something will fail or be missing later.
+    Warning,
+    Error,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SelfCheckReport {
+    pub ok: bool,
+    pub checks: Vec<Check>,
+}
+
+impl SelfCheckReport {
+    pub fn new(checks: Vec<Check>) -> Self {
+        SelfCheckReport {
+            ok: checks
+                .iter()
+                .all(|check| check.status != CheckStatus::Error),
+            checks,
+        }
+    }
+}
+
+/// Compares the migrations shipped with the binary with the ones sqlx-cli
+/// recorded as applied.
+pub async fn check_migrations(pool: &DbPool) -> Check {
+    const NAME: &str = "migrations";
+    let applied: HashSet<i64> =
+        match sqlx::query("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => rows.iter().map(|row| row.get("version")).collect(),
+            Err(e) => {
+                return Check::new(
+                    NAME,
+                    CheckStatus::Error,
+                    format!("no migrations recorded: {}", e),
+                )
+            }
+        };
+    let pending: Vec<String> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| format!("{} {}", migration.version, migration.description))
+        .collect();
+    if pending.is_empty() {
+        Check::new(NAME, CheckStatus::Ok, "all applied")
+    } else {
+        Check::new(
+            NAME,
+            CheckStatus::Error,
+            format!("pending: {}", pending.join(", ")),
+        )
+    }
+}
+
+/// Takes the write lock and rolls back, leaving the database untouched.
+pub async fn check_database_writable(pool: &DbPool) -> Check {
+    const NAME: &str = "database_writable";
+    let result = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("CREATE TABLE selfcheck_probe ( id INTEGER )")
+            .execute(&mut tx)
+            .await?;
+        tx.rollback().await
+    }
+    .await;
+    match result {
+        Ok(()) => Check::new(NAME, CheckStatus::Ok, "writable"),
+        Err(e) => Check::new(NAME, CheckStatus::Error, e.to_string()),
+    }
+}
+
+/// Checks the Alpha Vantage key with a quote request, which costs one call of
+/// the daily quota.
+pub async fn check_alpha_vantage_key(api_key: Option<String>, symbol: &str) -> Check {
+    const NAME: &str = "alpha_vantage_key";
+    let api_key = match api_key {
+        Some(api_key) => api_key,
+        None => return Check::new(NAME, CheckStatus::Error, "ALPHA_VANTAGE_API_KEY is not set"),
+    };
+    let url = format!(
+        "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
+        symbol, api_key
+    );
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(PROVIDER_TIMEOUT_SECONDS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return Check::new(NAME, CheckStatus::Error, e.to_string()),
+    };
+    let body = match client.get(url).send().await {
+        Ok(resp) => resp.json::<serde_json::Value>().await,
+        Err(e) => {
+            return Check::new(
+                NAME,
+                CheckStatus::Warning,
+                format!("Alpha Vantage unreachable: {}", e.without_url()),
+            )
+        }
+    };
+    match body {
+        Ok(body) if body.get("Global Quote").is_some() => {
+            Check::new(NAME, CheckStatus::Ok, "accepted")
+        }
+        // Invalid keys and exhausted quotas are reported in the body.
+        Ok(body) => Check::new(NAME, CheckStatus::Error, body.to_string()),
+        Err(e) => Check::new(NAME, CheckStatus::Warning, e.to_string()),
+    }
+}
+
+/// Checks every ticker has stored prices and, when it isn't quoted in the
+/// base currency, FX rates to convert them.
+pub async fn check_tickers(pool: &DbPool, tickers: &[(&str, &str)], base_currency: &str) -> Check {
+    const NAME: &str = "tickers";
+    let mut problems = Vec::new();
+    for (ticker, currency) in tickers {
+        match crate::price::get_latest_price(pool, ticker).await {
+            Ok(Some(_)) => {}
+            Ok(None) => problems.push(format!("{} has no prices", ticker)),
+            Err(e) => return Check::new(NAME, CheckStatus::Error, e.to_string()),
+        }
+        if *currency != base_currency {
+            match crate::fx::get_last_fx_rate_date(pool, currency, base_currency).await {
+                Ok(Some(_)) => {}
+                Ok(None) => problems.push(format!(
+                    "{} is quoted in {} and there are no {} rates in {}",
+                    ticker, currency, currency, base_currency
+                )),
+                Err(e) => return Check::new(NAME, CheckStatus::Error, e.to_string()),
+            }
+        }
+    }
+    if problems.is_empty() {
+        Check::new(NAME, CheckStatus::Ok, format!("{} tickers", tickers.len()))
+    } else {
+        Check::new(NAME, CheckStatus::Warning, problems.join("; "))
+    }
+}
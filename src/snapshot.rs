@@ -0,0 +1,269 @@
+use crate::db::{Db, DbPool};
+use crate::portfolio::{total_portfolio, Portfolio};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use futures::TryStreamExt;
+use sqlx::Transaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Ticker the total series is stored under, next to the series of each
+/// ticker.
+const TOTAL: &str = "*";
+
+/// Stores the value series of every ticker and their total, computed without
+/// forward fill nor tag, in place of the stored ones.
+pub async fn replace_snapshots(
+    pool: &DbPool,
+    portfolios: &HashMap<String, Vec<Portfolio>>,
+    total: &[Portfolio],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM portfolio_snapshots
+        "#
+    )
+    .execute(&mut tx)
+    .await?;
+    let series = portfolios
+        .iter()
+        .map(|(ticker, series)| (ticker.as_str(), series.as_slice()))
+        .chain(std::iter::once((TOTAL, total)));
+    for (ticker, series) in series {
+        insert_series(&mut tx, ticker, series).await?;
+    }
+    tx.commit().await
+}
+
+async fn insert_series(
+    tx: &mut Transaction<'_, Db>,
+    ticker: &str,
+    series: &[Portfolio],
+) -> Result<(), sqlx::Error> {
+    for day in series {
+        let date = day.date.to_string();
+        let amount = day.amount_in_base_currency.to_string();
+        sqlx::query!(
+            r#"
+            INSERT INTO portfolio_snapshots ( date, ticker, amount_in_base_currency, preliminary )
+            VALUES ( $1, $2, $3, $4 )
+            "#,
+            date,
+            ticker,
+            amount,
+            day.preliminary
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Whether the series of the last price update are stored.
+pub async fn has_snapshots(pool: &DbPool) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT ticker FROM portfolio_snapshots WHERE ticker = $1 LIMIT 1
+        "#,
+        TOTAL
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_some())
+}
+
+/// Stores the days of `ticker` from `from` on, in place of the stored ones,
+/// and sums the total of those days again. The days before `from` are left as
+/// they are, so `series` must only differ from the stored one on or after it.
+pub async fn replace_snapshots_from(
+    pool: &DbPool,
+    ticker: &str,
+    from: NaiveDate,
+    series: &[Portfolio],
+) -> Result<(), sqlx::Error> {
+    let from = from.to_string();
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM portfolio_snapshots WHERE date >= $1 AND (ticker = $2 OR ticker = $3)
+        "#,
+        from,
+        ticker,
+        TOTAL
+    )
+    .execute(&mut tx)
+    .await?;
+    insert_series(&mut tx, ticker, series).await?;
+
+    let mut portfolios: HashMap<String, Vec<Portfolio>> = HashMap::new();
+    {
+        let mut rows = sqlx::query!(
+            r#"
+            SELECT date, ticker, amount_in_base_currency, preliminary as "preliminary: bool"
+            FROM portfolio_snapshots
+            WHERE date >= $1
+            ORDER BY date asc
+            "#,
+            from
+        )
+        .fetch(&mut tx);
+        while let Some(row) = rows.try_next().await? {
+            let day = parse_day(&row.date, &row.amount_in_base_currency, row.preliminary)?;
+            portfolios.entry(row.ticker).or_default().push(day);
+        }
+    }
+    insert_series(&mut tx, TOTAL, &total_portfolio(portfolios)).await?;
+    tx.commit().await
+}
+
+fn parse_day(date: &str, amount: &str, preliminary: bool) -> Result<Portfolio, sqlx::Error> {
+    Ok(Portfolio {
+        date: NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        amount_in_base_currency: BigDecimal::from_str(amount)
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))?,
+        preliminary,
+    })
+}
+
+/// Drops the stored series, once the trades or prices they were computed from
+/// changed.
+pub async fn clear_snapshots(pool: &DbPool) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM portfolio_snapshots
+        "#
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Stored series of every ticker and their total, `None` when there are none.
+#[allow(clippy::type_complexity)]
+pub async fn load_snapshots(
+    pool: &DbPool,
+) -> Result<Option<(HashMap<String, Vec<Portfolio>>, Vec<Portfolio>)>, sqlx::Error> {
+    let mut rows = sqlx::query!(
+        r#"
+        SELECT date, ticker, amount_in_base_currency, preliminary as "preliminary: bool"
+        FROM portfolio_snapshots
+        ORDER BY date asc
+        "#
+    )
+    .fetch(pool);
+
+    let mut portfolios: HashMap<String, Vec<Portfolio>> = HashMap::new();
+    let mut total = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        let day = parse_day(&row.date, &row.amount_in_base_currency, row.preliminary)?;
+        if row.ticker == TOTAL {
+            total.push(day);
+        } else {
+            portfolios.entry(row.ticker).or_default().push(day);
+        }
+    }
+    if total.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((portfolios, total)))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal, point, pool};
+
+    fn amounts(series: &[Portfolio]) -> Vec<(NaiveDate, BigDecimal)> {
+        series
+            .iter()
+            .map(|day| (day.date, day.amount_in_base_currency.clone()))
+            .collect()
+    }
+
+    fn portfolios() -> HashMap<String, Vec<Portfolio>> {
+        HashMap::from([
+            (
+                "IWDA.AMS".to_string(),
+                vec![
+                    point("2024-05-01", "100"),
+                    point("2024-05-02", "110"),
+                    point("2024-05-03", "120"),
+                ],
+            ),
+            (
+                "BTC".to_string(),
+                vec![
+                    point("2024-05-01", "50"),
+                    point("2024-05-02", "50"),
+                    point("2024-05-03", "60"),
+                ],
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn stored_series_load_back() {
+        let pool = pool().await;
+        let total = total_portfolio(portfolios());
+
+        replace_snapshots(&pool, &portfolios(), &total)
+            .await
+            .unwrap();
+        let (loaded, loaded_total) = load_snapshots(&pool).await.unwrap().unwrap();
+
+        assert!(has_snapshots(&pool).await.unwrap());
+        assert_eq!(amounts(&loaded_total), amounts(&total));
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(amounts(&loaded["BTC"]), amounts(&portfolios()["BTC"]));
+    }
+
+    #[tokio::test]
+    async fn replacing_a_ticker_from_a_date_sums_the_total_again() {
+        let pool = pool().await;
+        replace_snapshots(&pool, &portfolios(), &total_portfolio(portfolios()))
+            .await
+            .unwrap();
+
+        replace_snapshots_from(
+            &pool,
+            "IWDA.AMS",
+            date("2024-05-02"),
+            &[point("2024-05-02", "210"), point("2024-05-03", "220")],
+        )
+        .await
+        .unwrap();
+        let (loaded, total) = load_snapshots(&pool).await.unwrap().unwrap();
+
+        assert_eq!(
+            amounts(&loaded["IWDA.AMS"]),
+            vec![
+                (date("2024-05-01"), decimal("100")),
+                (date("2024-05-02"), decimal("210")),
+                (date("2024-05-03"), decimal("220")),
+            ]
+        );
+        assert_eq!(
+            amounts(&total),
+            vec![
+                (date("2024-05-01"), decimal("150")),
+                (date("2024-05-02"), decimal("260")),
+                (date("2024-05-03"), decimal("280")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cleared_series_are_not_loaded() {
+        let pool = pool().await;
+        replace_snapshots(&pool, &portfolios(), &total_portfolio(portfolios()))
+            .await
+            .unwrap();
+
+        clear_snapshots(&pool).await.unwrap();
+
+        assert!(!has_snapshots(&pool).await.unwrap());
+        assert!(load_snapshots(&pool).await.unwrap().is_none());
+    }
+}
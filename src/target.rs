@@ -0,0 +1,91 @@
+use crate::db::DbPool;
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+
+pub struct SaveTarget {
+    pub target_price: Option<String>,
+    pub exit_criteria: Option<String>,
+}
+
+/// Price at which, or condition under which, the position in a ticker should
+/// be reconsidered. A target price is reached once the price is at or above
+/// it.
+pub struct Target {
+    pub ticker: String,
+    pub target_price: Option<String>,
+    pub exit_criteria: Option<String>,
+    pub reached_on: Option<String>,
+}
+
+/// Sets the target of `ticker`, replacing the one it had. The new target
+/// starts unreached.
+pub async fn set_target(
+    pool: &DbPool,
+    ticker: &str,
+    target: SaveTarget,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO ticker_targets ( ticker, target_price, exit_criteria, reached_on )
+        VALUES ( $1, $2, $3, NULL )
+        ON CONFLICT ( ticker ) DO UPDATE SET
+            target_price = excluded.target_price,
+            exit_criteria = excluded.exit_criteria,
+            reached_on = NULL
+        "#,
+        ticker,
+        target.target_price,
+        target.exit_criteria
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn list_targets(pool: &DbPool) -> Result<Vec<Target>, sqlx::Error> {
+    sqlx::query_as!(
+        Target,
+        r#"
+        SELECT ticker as "ticker!", target_price, exit_criteria, reached_on FROM ticker_targets
+        ORDER BY ticker asc
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_target(pool: &DbPool, ticker: &str) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        DELETE FROM ticker_targets WHERE ticker = $1
+        "#,
+        ticker
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+pub async fn mark_reached(pool: &DbPool, ticker: &str, date: NaiveDate) -> Result<(), sqlx::Error> {
+    let date = date.to_string();
+    sqlx::query!(
+        r#"
+        UPDATE ticker_targets SET reached_on = $2 WHERE ticker = $1
+        "#,
+        ticker,
+        date
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// How far `price` has to move to reach `target_price`, as a percentage of
+/// `price`. Negative once the target is passed.
+pub fn distance_percent(price: &BigDecimal, target_price: &BigDecimal) -> BigDecimal {
+    round_half_up(
+        (target_price - price) * BigDecimal::from(100) / price,
+        AMOUNT_SCALE,
+    )
+}
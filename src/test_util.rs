@@ -0,0 +1,58 @@
+//! Fixtures shared by the unit tests.
+
+#[cfg(feature = "sqlite")]
+use crate::db::{self, DbOptions, DbPool};
+use crate::portfolio::Portfolio;
+use crate::trade::TradeForCalculation;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use std::str::FromStr;
+
+pub fn date(date: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+}
+
+pub fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+/// A trade of `amount` units, negative for a sell.
+pub fn trade(
+    id: i64,
+    day: &str,
+    ticker: &str,
+    amount: &str,
+    price: &str,
+    fee: &str,
+) -> TradeForCalculation {
+    TradeForCalculation {
+        id,
+        date: date(day),
+        amount: decimal(amount),
+        price: decimal(price),
+        fee: decimal(fee),
+        ticker: ticker.to_string(),
+    }
+}
+
+/// A final point of a value series.
+pub fn point(day: &str, amount: &str) -> Portfolio {
+    Portfolio {
+        date: date(day),
+        amount_in_base_currency: decimal(amount),
+        preliminary: false,
+    }
+}
+
+/// A migrated database of its own, kept in memory.
+#[cfg(feature = "sqlite")]
+pub async fn pool() -> DbPool {
+    let options = DbOptions {
+        url: "sqlite::memory:".to_string(),
+        wal: false,
+        max_connections: 1,
+        ..DbOptions::default()
+    };
+    let pool = db::prepare_db_and_get_connection(&options).await.unwrap();
+    (*pool).clone()
+}
@@ -0,0 +1,249 @@
+use crate::db::{Db, DbPool};
+use sqlx::{Executor, Transaction};
+
+pub enum RenameTickerError {
+    NotFound,
+    /// The new ticker already has trades or prices.
+    Conflict,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for RenameTickerError {
+    fn from(e: sqlx::Error) -> Self {
+        RenameTickerError::Database(e)
+    }
+}
+
+async fn is_ticker_used(tx: &mut Transaction<'_, Db>, ticker: &str) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT ticker FROM trades WHERE ticker = $1
+        UNION ALL
+        SELECT ticker FROM prices WHERE ticker = $1
+        LIMIT 1
+        "#,
+        ticker
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some())
+}
+
+/// Renames `from` to `to` in every table that refers to tickers, at once.
+/// With `keep_alias`, prices of `to` keep being fetched under the symbol
+/// `from` was fetched under.
+pub async fn rename_ticker(
+    pool: &DbPool,
+    from: &str,
+    to: &str,
+    keep_alias: bool,
+) -> Result<(), RenameTickerError> {
+    let mut tx = pool.begin().await?;
+    if !is_ticker_used(&mut tx, from).await? {
+        return Err(RenameTickerError::NotFound);
+    }
+    if is_ticker_used(&mut tx, to).await? {
+        return Err(RenameTickerError::Conflict);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE trades SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE prices SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE price_quotes SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE dividends SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE journal_entries SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE corporate_actions SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE ticker_targets SET ticker = $2 WHERE ticker = $1
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let source = sqlx::query_as!(
+        PriceSource,
+        r#"
+        SELECT provider, symbol FROM ticker_aliases WHERE ticker = $1
+        "#,
+        from
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .unwrap_or_else(|| PriceSource {
+        provider: DEFAULT_PRICE_PROVIDER.to_string(),
+        symbol: from.to_string(),
+    });
+    sqlx::query!(
+        r#"
+        DELETE FROM ticker_aliases WHERE ticker = $1 OR ticker = $2
+        "#,
+        from,
+        to
+    )
+    .execute(&mut tx)
+    .await?;
+    if keep_alias && (source.symbol != to || source.provider != DEFAULT_PRICE_PROVIDER) {
+        sqlx::query!(
+            r#"
+            INSERT INTO ticker_aliases ( ticker, symbol, provider ) VALUES ( $1, $2, $3 )
+            "#,
+            to,
+            source.symbol,
+            source.provider
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Symbol to fetch prices of `ticker` under.
+pub async fn get_price_symbol(pool: &DbPool, ticker: &str) -> Result<String, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT symbol FROM ticker_aliases WHERE ticker = $1
+        "#,
+        ticker
+    )
+    .fetch_optional(pool)
+    .await?
+    .map_or_else(|| ticker.to_string(), |row| row.symbol))
+}
+
+/// Provider prices of tickers without a source of their own come from.
+pub const DEFAULT_PRICE_PROVIDER: &str = "alpha_vantage";
+
+/// Where prices of a ticker come from: a provider and the symbol it knows the
+/// ticker by.
+pub struct PriceSource {
+    pub provider: String,
+    pub symbol: String,
+}
+
+pub async fn get_price_source(pool: &DbPool, ticker: &str) -> Result<PriceSource, sqlx::Error> {
+    Ok(sqlx::query_as!(
+        PriceSource,
+        r#"
+        SELECT provider, symbol FROM ticker_aliases WHERE ticker = $1
+        "#,
+        ticker
+    )
+    .fetch_optional(pool)
+    .await?
+    .unwrap_or_else(|| PriceSource {
+        provider: DEFAULT_PRICE_PROVIDER.to_string(),
+        symbol: ticker.to_string(),
+    }))
+}
+
+/// Currency and exchange of the instrument a price source symbol stands for,
+/// as the provider describes it.
+pub struct Instrument {
+    pub currency: String,
+    pub exchange: Option<String>,
+}
+
+/// Instrument detected when the price source of `ticker` was set, if any.
+pub async fn get_instrument(
+    pool: &DbPool,
+    ticker: &str,
+) -> Result<Option<Instrument>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT currency, exchange FROM ticker_aliases WHERE ticker = $1
+        "#,
+        ticker
+    )
+    .fetch_optional(pool)
+    .await?
+    .and_then(|row| {
+        Some(Instrument {
+            currency: row.currency?,
+            exchange: row.exchange,
+        })
+    }))
+}
+
+/// Fetches prices of `ticker` from `source` from now on, recording the
+/// instrument the source stands for when it is known. Runs on a pool or
+/// within a transaction.
+pub async fn set_price_source(
+    executor: impl Executor<'_, Database = Db>,
+    ticker: &str,
+    source: &PriceSource,
+    instrument: Option<&Instrument>,
+) -> Result<(), sqlx::Error> {
+    let currency = instrument.map(|instrument| instrument.currency.as_str());
+    let exchange = instrument.and_then(|instrument| instrument.exchange.as_deref());
+    sqlx::query!(
+        r#"
+        INSERT INTO ticker_aliases ( ticker, symbol, provider, currency, exchange )
+        VALUES ( $1, $2, $3, $4, $5 )
+        ON CONFLICT ( ticker ) DO UPDATE SET
+            symbol = excluded.symbol,
+            provider = excluded.provider,
+            currency = excluded.currency,
+            exchange = excluded.exchange
+        "#,
+        ticker,
+        source.symbol,
+        source.provider,
+        currency,
+        exchange
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
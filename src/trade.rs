@@ -1,29 +1,114 @@
+use crate::corporate_action::{self, adjust_price, adjust_units, split_factor};
+use crate::db::{Db, DbPool};
+use crate::decimal::{round_half_up, AMOUNT_SCALE};
+use crate::fx;
+use crate::operation::{self, DeletedTrade, Operation};
+use bigdecimal::{BigDecimal, One, Zero};
 use chrono::NaiveDate;
-use sqlx::SqlitePool;
+use sqlx::Transaction;
+use std::collections::HashMap;
+use std::str::FromStr;
 
 pub struct CreateTrade {
     pub ticker: String,
     pub date: String,
     pub r#type: String,
-    pub amount: u32,
+    pub amount: String,
     pub price: String,
+    pub fee: Option<String>,
+    pub tags: Vec<String>,
 }
 
-pub async fn create_trade(pool: &SqlitePool, trade: CreateTrade) -> Result<i64, sqlx::Error> {
-    Ok(sqlx::query!(
+pub async fn create_trade(pool: &DbPool, trade: CreateTrade) -> Result<i64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let id = sqlx::query!(
         r#"
-        INSERT INTO trades ( ticker, date, type, amount, price )
-        VALUES ( ?1, ?2, ?3, ?4, ?5 )
+        INSERT INTO trades ( ticker, date, type, amount, price, fee )
+        VALUES ( $1, $2, $3, $4, $5, $6 )
+        RETURNING id as "id!"
         "#,
         trade.ticker,
         trade.date,
         trade.r#type,
         trade.amount,
-        trade.price
+        trade.price,
+        trade.fee
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .id;
+    insert_trade_tags(&mut tx, id, &trade.tags).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+async fn insert_trade_tags(
+    tx: &mut Transaction<'_, Db>,
+    trade_id: i64,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    for tag in tags {
+        sqlx::query!(
+            r#"
+            INSERT INTO trade_tags ( trade_id, tag ) VALUES ( $1, $2 )
+            ON CONFLICT DO NOTHING
+            "#,
+            trade_id,
+            tag
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the tags of a trade. Returns whether the trade exists.
+pub async fn set_trade_tags(
+    pool: &DbPool,
+    trade_id: i64,
+    tags: &[String],
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let exists = sqlx::query!(
+        r#"
+        SELECT id FROM trades WHERE id = $1
+        "#,
+        trade_id
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .is_some();
+    if !exists {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        DELETE FROM trade_tags WHERE trade_id = $1
+        "#,
+        trade_id
     )
-    .execute(pool)
+    .execute(&mut tx)
+    .await?;
+    insert_trade_tags(&mut tx, trade_id, tags).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Tags of every tagged trade, keyed by trade id.
+pub async fn list_trade_tags(pool: &DbPool) -> Result<HashMap<i64, Vec<String>>, sqlx::Error> {
+    let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in sqlx::query!(
+        r#"
+        SELECT trade_id, tag FROM trade_tags ORDER BY tag asc
+        "#,
+    )
+    .fetch_all(pool)
     .await?
-    .last_insert_rowid())
+    {
+        tags.entry(row.trade_id).or_default().push(row.tag);
+    }
+    Ok(tags)
 }
 
 pub struct ListTrade {
@@ -31,55 +116,662 @@ pub struct ListTrade {
     pub ticker: String,
     pub date: String,
     pub r#type: String,
-    pub amount: i64,
+    pub amount: String,
     pub price: String,
+    pub fee: Option<String>,
 }
 
-pub async fn list_trades(pool: &SqlitePool) -> Result<Vec<ListTrade>, sqlx::Error> {
-    Ok(sqlx::query_as!(
+pub async fn list_trades(pool: &DbPool) -> Result<Vec<ListTrade>, sqlx::Error> {
+    sqlx::query_as!(
         ListTrade,
         r#"
-        SELECT id, ticker, date, type, amount, price FROM trades
+        SELECT id, ticker, date, type, amount, price, fee FROM trades ORDER BY id asc
         "#,
     )
     .fetch_all(pool)
-    .await?)
+    .await
+}
+
+pub async fn get_trade(pool: &DbPool, trade_id: i64) -> Result<Option<ListTrade>, sqlx::Error> {
+    sqlx::query_as!(
+        ListTrade,
+        r#"
+        SELECT id, ticker, date, type, amount, price, fee FROM trades WHERE id = $1
+        "#,
+        trade_id
+    )
+    .fetch_optional(pool)
+    .await
 }
 
 #[derive(Clone)]
 pub struct TradeForCalculation {
+    pub id: i64,
     pub date: NaiveDate,
-    pub amount: i64,
+    /// Signed amount of units: positive for buys, negative for sells.
+    pub amount: BigDecimal,
+    pub price: BigDecimal,
+    /// Fees and commissions paid on the trade, zero when none were recorded.
+    pub fee: BigDecimal,
     pub ticker: String,
 }
 
+impl TradeForCalculation {
+    /// Money the trade put into the portfolio: the cost of a buy plus its fee,
+    /// or minus the proceeds of a sell net of its fee.
+    pub fn cash_flow(&self) -> BigDecimal {
+        &self.price * &self.amount + &self.fee
+    }
+}
+
+/// Trades sorted by date, optionally only those of `ticker` and carrying
+/// `tag`. Units and prices of trades before a split are adjusted to it.
 pub async fn list_trades_for_calculation(
-    pool: &SqlitePool,
+    pool: &DbPool,
+    ticker: Option<&str>,
+    tag: Option<&str>,
 ) -> Result<Vec<TradeForCalculation>, sqlx::Error> {
+    let splits = corporate_action::list_splits(pool, ticker).await?;
     Ok(sqlx::query!(
         r#"
-        SELECT date, amount, ticker FROM trades ORDER BY date asc
+        SELECT id as "id!", date, type, amount, price, fee, ticker FROM trades
+        WHERE (CAST($1 AS TEXT) IS NULL OR ticker = $1)
+          AND (CAST($2 AS TEXT) IS NULL OR id IN (SELECT trade_id FROM trade_tags WHERE tag = $2))
+        ORDER BY date asc, id asc
         "#,
+        ticker,
+        tag
     )
-    .fetch_all(&*pool)
+    .fetch_all(pool)
     .await?
     .iter()
-    .map(|row| TradeForCalculation {
-        amount: row.amount,
-        date: NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap(),
-        ticker: row.ticker.clone(),
+    .map(|row| {
+        let date = NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap();
+        let factor = split_factor(&splits, &row.ticker, date);
+        TradeForCalculation {
+            id: row.id,
+            amount: adjust_units(
+                if row.r#type == "SELL" {
+                    -BigDecimal::from_str(&row.amount).unwrap()
+                } else {
+                    BigDecimal::from_str(&row.amount).unwrap()
+                },
+                &factor,
+            ),
+            price: adjust_price(BigDecimal::from_str(&row.price).unwrap(), &factor),
+            fee: row
+                .fee
+                .as_deref()
+                .map(|fee| BigDecimal::from_str(fee).unwrap())
+                .unwrap_or_default(),
+            date,
+            ticker: row.ticker.clone(),
+        }
     })
     .collect())
 }
 
-pub async fn delete_trade(pool: &SqlitePool, trade_id: i64) -> Result<u64, sqlx::Error> {
+/// No FX rate was stored on or before the date of a trade quoted in another
+/// currency than the base one.
+#[derive(Debug)]
+pub struct MissingFxRate {
+    pub ticker: String,
+    pub currency: String,
+    pub date: NaiveDate,
+}
+
+/// Converts what trades cost into the base currency, with the rate in effect
+/// on each trade date.
+pub struct TradeCostConverter {
+    base_currency: String,
+    /// Currency of each ticker not quoted in `default_currency`.
+    ticker_currencies: HashMap<String, String>,
+    default_currency: String,
+    fx_rates: HashMap<String, Vec<(NaiveDate, BigDecimal)>>,
+}
+
+impl TradeCostConverter {
+    /// Loads the rates of every currency in `ticker_currencies`, the currency
+    /// each ticker is quoted in, and of `default_currency`, the one of the
+    /// tickers not listed.
+    pub async fn load(
+        pool: &DbPool,
+        base_currency: &str,
+        ticker_currencies: &[(&str, &str)],
+        default_currency: &str,
+    ) -> Result<Self, sqlx::Error> {
+        let mut fx_rates = HashMap::new();
+        let currencies = ticker_currencies
+            .iter()
+            .map(|(_, currency)| *currency)
+            .chain([default_currency]);
+        for currency in currencies {
+            if currency != base_currency && !fx_rates.contains_key(currency) {
+                fx_rates.insert(
+                    currency.to_string(),
+                    fx::list_fx_rates_for_calculation(pool, currency, base_currency).await?,
+                );
+            }
+        }
+        Ok(Self {
+            base_currency: base_currency.to_string(),
+            ticker_currencies: ticker_currencies
+                .iter()
+                .map(|(ticker, currency)| (ticker.to_string(), currency.to_string()))
+                .collect(),
+            default_currency: default_currency.to_string(),
+            fx_rates,
+        })
+    }
+
+    fn currency(&self, ticker: &str) -> &str {
+        self.ticker_currencies
+            .get(ticker)
+            .unwrap_or(&self.default_currency)
+    }
+
+    /// Units of the base currency a unit of the currency of `ticker` is worth
+    /// on `date`.
+    pub fn rate(&self, ticker: &str, date: NaiveDate) -> Result<BigDecimal, MissingFxRate> {
+        let currency = self.currency(ticker);
+        if currency == self.base_currency {
+            return Ok(BigDecimal::one());
+        }
+        self.fx_rates
+            .get(currency)
+            .and_then(|fx_rates| fx::rate_on(fx_rates, date))
+            .cloned()
+            .ok_or_else(|| MissingFxRate {
+                ticker: ticker.to_string(),
+                currency: currency.to_string(),
+                date,
+            })
+    }
+
+    /// Price times amount plus fee, in the base currency. `None` when there
+    /// is no rate for the trade date yet.
+    pub fn cost(&self, list_trade: &ListTrade) -> Option<BigDecimal> {
+        let fee = match &list_trade.fee {
+            Some(fee) => BigDecimal::from_str(fee).ok()?,
+            None => BigDecimal::zero(),
+        };
+        let cost = BigDecimal::from_str(&list_trade.price).ok()?
+            * BigDecimal::from_str(&list_trade.amount).ok()?
+            + fee;
+        let date = NaiveDate::parse_from_str(&list_trade.date, "%Y-%m-%d").ok()?;
+        let rate = self.rate(&list_trade.ticker, date).ok()?;
+        Some(round_half_up(cost * rate, AMOUNT_SCALE))
+    }
+
+    /// Expresses the prices and fees of `trades` in the base currency, so
+    /// their cash flows add up with values of the portfolio.
+    pub fn convert(
+        &self,
+        trades: Vec<TradeForCalculation>,
+    ) -> Result<Vec<TradeForCalculation>, MissingFxRate> {
+        trades
+            .into_iter()
+            .map(|trade| {
+                let rate = self.rate(&trade.ticker, trade.date)?;
+                if rate.is_one() {
+                    return Ok(trade);
+                }
+                Ok(TradeForCalculation {
+                    price: &trade.price * &rate,
+                    fee: &trade.fee * &rate,
+                    ..trade
+                })
+            })
+            .collect()
+    }
+}
+
+/// Units of `ticker` held at the end of `date`, in units of that date: splits
+/// after it are not applied.
+pub async fn holding_on(
+    pool: &DbPool,
+    ticker: &str,
+    date: NaiveDate,
+) -> Result<BigDecimal, sqlx::Error> {
+    let splits = corporate_action::list_splits(pool, Some(ticker)).await?;
+    let holding: BigDecimal = list_trades_for_calculation(pool, Some(ticker), None)
+        .await?
+        .into_iter()
+        .filter(|trade| trade.date <= date)
+        .map(|trade| trade.amount)
+        .sum();
+    let factor = split_factor(&splits, ticker, date);
+    if factor.is_one() {
+        return Ok(holding);
+    }
+    Ok((holding / factor).normalized())
+}
+
+pub async fn get_first_trade_date(pool: &DbPool) -> Result<Option<NaiveDate>, sqlx::Error> {
     Ok(sqlx::query!(
         r#"
-        DELETE FROM trades WHERE id = ?1
+        SELECT date as "date!" FROM trades WHERE date IS NOT NULL ORDER BY date asc LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| NaiveDate::parse_from_str(&row.date, "%Y-%m-%d").unwrap()))
+}
+
+/// The row of a trade and its tags, as an operation records them, and whether
+/// it reinvests a dividend.
+async fn fetch_trade(
+    tx: &mut Transaction<'_, Db>,
+    trade_id: i64,
+) -> Result<Option<(DeletedTrade, bool)>, sqlx::Error> {
+    let trade = match sqlx::query!(
+        r#"
+        SELECT id as "id!", ticker, date, type, amount, price, fee,
+               EXISTS ( SELECT 1 FROM dividends WHERE reinvestment_trade_id = $1 ) as "reinvestment!: bool"
+        FROM trades WHERE id = $1
+        "#,
+        trade_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        Some(trade) => trade,
+        None => return Ok(None),
+    };
+    let tags = sqlx::query!(
+        r#"
+        SELECT tag FROM trade_tags WHERE trade_id = $1 ORDER BY tag asc
+        "#,
+        trade_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.tag)
+    .collect();
+    Ok(Some((
+        DeletedTrade {
+            id: trade.id,
+            ticker: trade.ticker,
+            date: trade.date,
+            r#type: trade.r#type,
+            amount: trade.amount,
+            price: trade.price,
+            fee: trade.fee,
+            tags,
+        },
+        trade.reinvestment,
+    )))
+}
+
+/// Deletes a trade and its tags, and records them to be put back with
+/// `operation::undo_operation`. Returns the id of the operation. A trade
+/// reinvesting a dividend goes with the dividend instead.
+pub async fn delete_trade(pool: &DbPool, trade_id: i64) -> Result<i64, EditTradeError> {
+    let mut tx = pool.begin().await?;
+    let trade = match fetch_trade(&mut tx, trade_id).await? {
+        Some((_, true)) => return Err(EditTradeError::Reinvestment),
+        Some((trade, false)) => trade,
+        None => return Err(EditTradeError::NotFound),
+    };
+
+    sqlx::query!(
+        r#"
+        DELETE FROM trade_tags WHERE trade_id = $1
+        "#,
+        trade_id
+    )
+    .execute(&mut tx)
+    .await?;
+    sqlx::query!(
+        r#"
+        DELETE FROM trades WHERE id = $1
+        "#,
+        trade_id
+    )
+    .execute(&mut tx)
+    .await?;
+    let id = operation::record_operation(&mut tx, &Operation::DeleteTrade { trade }).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+const MERGED_PRICE_SCALE: i64 = 6;
+
+pub enum EditTradeError {
+    NotFound,
+    /// The lots or trades don't describe a valid split or merge.
+    Invalid,
+    /// The trade reinvests a dividend, so it follows the dividend.
+    Reinvestment,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for EditTradeError {
+    fn from(e: sqlx::Error) -> Self {
+        EditTradeError::Database(e)
+    }
+}
+
+pub struct Lot {
+    pub amount: BigDecimal,
+    pub price: String,
+}
+
+/// Shares `fee` among `lots` in proportion to their units, rounded to cents.
+/// The first lot takes the rounding difference, so the shares add up to `fee`.
+fn split_fee(fee: &BigDecimal, lots: &[Lot]) -> Vec<BigDecimal> {
+    let units: BigDecimal = lots.iter().map(|lot| &lot.amount).sum();
+    let mut shares: Vec<BigDecimal> = lots
+        .iter()
+        .map(|lot| round_half_up(fee * &lot.amount / &units, AMOUNT_SCALE))
+        .collect();
+    let rest: BigDecimal = shares[1..].iter().sum();
+    shares[0] = fee - rest;
+    shares
+}
+
+/// Splits a trade into lots with the same ticker, date, type and tags, and
+/// records it to be undone with `operation::undo_operation`. The first lot
+/// keeps the id of the trade; the ids of all lots are returned. The fee is
+/// shared among the lots by units.
+/// The lots must add up to the units of the trade, and a trade reinvesting a
+/// dividend can't be split.
+pub async fn split_trade(
+    pool: &DbPool,
+    trade_id: i64,
+    lots: &[Lot],
+) -> Result<Vec<i64>, EditTradeError> {
+    let mut tx = pool.begin().await?;
+    let trade = match fetch_trade(&mut tx, trade_id).await? {
+        Some((_, true)) => return Err(EditTradeError::Reinvestment),
+        Some((trade, false)) => trade,
+        None => return Err(EditTradeError::NotFound),
+    };
+
+    let units: BigDecimal = lots.iter().map(|lot| &lot.amount).sum();
+    if lots.len() < 2
+        || lots.iter().any(|lot| lot.amount <= BigDecimal::zero())
+        || units != BigDecimal::from_str(&trade.amount).unwrap()
+    {
+        return Err(EditTradeError::Invalid);
+    }
+    let fees: Vec<Option<String>> = match trade.fee.as_deref() {
+        Some(fee) => split_fee(&BigDecimal::from_str(fee).unwrap(), lots)
+            .into_iter()
+            .map(|fee| Some(fee.normalized().to_string()))
+            .collect(),
+        None => vec![None; lots.len()],
+    };
+
+    let first_amount = lots[0].amount.to_string();
+    sqlx::query!(
+        r#"
+        UPDATE trades SET amount = $1, price = $2, fee = $3 WHERE id = $4
+        "#,
+        first_amount,
+        lots[0].price,
+        fees[0],
+        trade_id
+    )
+    .execute(&mut tx)
+    .await?;
+
+    let mut ids = vec![trade_id];
+    for (lot, fee) in lots[1..].iter().zip(fees.into_iter().skip(1)) {
+        let amount = lot.amount.to_string();
+        let id = sqlx::query!(
+            r#"
+            INSERT INTO trades ( ticker, date, type, amount, price, fee )
+            VALUES ( $1, $2, $3, $4, $5, $6 )
+            RETURNING id as "id!"
+            "#,
+            trade.ticker,
+            trade.date,
+            trade.r#type,
+            amount,
+            lot.price,
+            fee
+        )
+        .fetch_one(&mut tx)
+        .await?
+        .id;
+        sqlx::query!(
+            r#"
+            INSERT INTO trade_tags ( trade_id, tag )
+            SELECT $1, tag FROM trade_tags WHERE trade_id = $2
+            "#,
+            id,
+            trade_id
+        )
+        .execute(&mut tx)
+        .await?;
+        ids.push(id);
+    }
+
+    let operation = Operation::SplitTrade {
+        trade,
+        lot_ids: ids[1..].to_vec(),
+    };
+    operation::record_operation(&mut tx, &operation).await?;
+    tx.commit().await?;
+    Ok(ids)
+}
+
+/// Moves a trade to `date` and returns the date it had.
+pub async fn amend_trade_date(
+    pool: &DbPool,
+    trade_id: i64,
+    date: NaiveDate,
+) -> Result<NaiveDate, EditTradeError> {
+    let mut tx = pool.begin().await?;
+    let trade = sqlx::query!(
+        r#"
+        SELECT date as "date!",
+               EXISTS ( SELECT 1 FROM dividends WHERE reinvestment_trade_id = $1 ) as "reinvestment!: bool"
+        FROM trades WHERE id = $1
         "#,
         trade_id
     )
-    .execute(pool)
+    .fetch_optional(&mut tx)
     .await?
-    .rows_affected())
+    .ok_or(EditTradeError::NotFound)?;
+    if trade.reinvestment {
+        return Err(EditTradeError::Reinvestment);
+    }
+
+    let new_date = date.to_string();
+    sqlx::query!(
+        r#"
+        UPDATE trades SET date = $1 WHERE id = $2
+        "#,
+        new_date,
+        trade_id
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(NaiveDate::parse_from_str(&trade.date, "%Y-%m-%d").unwrap())
+}
+
+/// Merges trades with the same ticker, date and type into the one with the
+/// lowest id, at the average price weighted by units and with the sum of their
+/// fees, and records it to be undone with `operation::undo_operation`. Tags of
+/// the merged trades move to the remaining one. Trades reinvesting a dividend
+/// can't be merged.
+pub async fn merge_trades(pool: &DbPool, trade_ids: &[i64]) -> Result<i64, EditTradeError> {
+    let mut trade_ids = trade_ids.to_vec();
+    trade_ids.sort_unstable();
+    trade_ids.dedup();
+    if trade_ids.len() < 2 {
+        return Err(EditTradeError::Invalid);
+    }
+
+    let mut tx = pool.begin().await?;
+    let mut trades = Vec::with_capacity(trade_ids.len());
+    for trade_id in &trade_ids {
+        match fetch_trade(&mut tx, *trade_id).await? {
+            Some((_, true)) => return Err(EditTradeError::Reinvestment),
+            Some((trade, false)) => trades.push(trade),
+            None => return Err(EditTradeError::NotFound),
+        }
+    }
+    let first = &trades[0];
+    if trades.iter().any(|trade| {
+        trade.ticker != first.ticker || trade.date != first.date || trade.r#type != first.r#type
+    }) {
+        return Err(EditTradeError::Invalid);
+    }
+
+    let amounts: Vec<BigDecimal> = trades
+        .iter()
+        .map(|trade| BigDecimal::from_str(&trade.amount).unwrap())
+        .collect();
+    let units: BigDecimal = amounts.iter().sum();
+    let cost: BigDecimal = trades
+        .iter()
+        .zip(&amounts)
+        .map(|(trade, amount)| BigDecimal::from_str(&trade.price).unwrap() * amount)
+        .sum();
+    let price = round_half_up(cost / &units, MERGED_PRICE_SCALE)
+        .normalized()
+        .to_string();
+    let units = units.normalized().to_string();
+    let fees: Vec<BigDecimal> = trades
+        .iter()
+        .filter_map(|trade| trade.fee.as_deref())
+        .map(|fee| BigDecimal::from_str(fee).unwrap())
+        .collect();
+    let fee = if fees.is_empty() {
+        None
+    } else {
+        Some(fees.into_iter().sum::<BigDecimal>().to_string())
+    };
+
+    let kept_id = trade_ids[0];
+    sqlx::query!(
+        r#"
+        UPDATE trades SET amount = $1, price = $2, fee = $3 WHERE id = $4
+        "#,
+        units,
+        price,
+        fee,
+        kept_id
+    )
+    .execute(&mut tx)
+    .await?;
+    for trade_id in &trade_ids[1..] {
+        sqlx::query!(
+            r#"
+            INSERT INTO trade_tags ( trade_id, tag )
+            SELECT $1, tag FROM trade_tags WHERE trade_id = $2
+            ON CONFLICT DO NOTHING
+            "#,
+            kept_id,
+            trade_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM trade_tags WHERE trade_id = $1
+            "#,
+            trade_id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM trades WHERE id = $1
+            "#,
+            trade_id
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    operation::record_operation(&mut tx, &Operation::MergeTrades { trades }).await?;
+    tx.commit().await?;
+    Ok(kept_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{date, decimal, trade};
+
+    fn converter() -> TradeCostConverter {
+        TradeCostConverter {
+            base_currency: "EUR".to_string(),
+            ticker_currencies: HashMap::from([("VOO".to_string(), "USD".to_string())]),
+            default_currency: "EUR".to_string(),
+            fx_rates: HashMap::from([(
+                "USD".to_string(),
+                vec![
+                    (date("2024-05-01"), decimal("0.9")),
+                    (date("2024-05-03"), decimal("0.8")),
+                ],
+            )]),
+        }
+    }
+
+    #[test]
+    fn converter_converts_trades_at_the_rate_of_their_date() {
+        let trades = vec![
+            trade(1, "2024-05-02", "VOO", "2", "500", "10"),
+            trade(2, "2024-05-06", "VOO", "-1", "550", "5"),
+        ];
+
+        let cash_flows: Vec<BigDecimal> = converter()
+            .convert(trades)
+            .unwrap()
+            .iter()
+            .map(TradeForCalculation::cash_flow)
+            .collect();
+
+        assert_eq!(cash_flows, vec![decimal("909"), decimal("-436")]);
+    }
+
+    #[test]
+    fn converter_keeps_trades_in_the_base_currency() {
+        let trades = vec![trade(1, "2020-01-02", "IWDA.AMS", "2", "50", "1")];
+
+        let converted = converter().convert(trades).unwrap();
+
+        assert_eq!(converted[0].cash_flow(), decimal("101"));
+    }
+
+    #[test]
+    fn converter_needs_a_rate_on_or_before_the_trade_date() {
+        let trades = vec![trade(1, "2024-04-30", "VOO", "2", "500", "0")];
+
+        let missing = match converter().convert(trades) {
+            Ok(_) => panic!("converted without a rate"),
+            Err(missing) => missing,
+        };
+
+        assert_eq!(missing.currency, "USD");
+        assert_eq!(missing.date, date("2024-04-30"));
+    }
+
+    fn lot(amount: &str) -> Lot {
+        Lot {
+            amount: decimal(amount),
+            price: "10".to_string(),
+        }
+    }
+
+    #[test]
+    fn split_fee_shares_the_fee_by_units() {
+        let shares = split_fee(&decimal("3"), &[lot("2"), lot("1")]);
+
+        assert_eq!(shares, vec![decimal("2"), decimal("1")]);
+    }
+
+    #[test]
+    fn split_fee_gives_the_rounding_difference_to_the_first_lot() {
+        let shares = split_fee(&decimal("1"), &[lot("1"), lot("1"), lot("1")]);
+
+        assert_eq!(shares, vec![decimal("0.34"), decimal("0.33"), decimal("0.33")]);
+    }
 }
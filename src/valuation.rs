@@ -0,0 +1,295 @@
+use crate::db::DbPool;
+use crate::price::{DailyPrice, MissingValues};
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+/// Convention the units held are valued by, set per portfolio with
+/// `set_portfolio_valuation` and overridden per request with the `valuation`
+/// query parameter. Returns add the dividends received on top of the value,
+/// so they are computed at the close.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Valuation {
+    #[default]
+    Close,
+    AdjustedClose,
+    Nav,
+    BidAsk,
+}
+
+impl Valuation {
+    pub fn strategy(self) -> Box<dyn ValuationStrategy> {
+        match self {
+            Valuation::Close => Box::new(CloseValuation),
+            Valuation::AdjustedClose => Box::new(AdjustedCloseValuation),
+            Valuation::Nav => Box::new(NavValuation),
+            Valuation::BidAsk => Box::new(BidAskValuation),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Valuation> {
+        [
+            Valuation::Close,
+            Valuation::AdjustedClose,
+            Valuation::Nav,
+            Valuation::BidAsk,
+        ]
+        .into_iter()
+        .find(|valuation| valuation.strategy().name() == name)
+    }
+
+    /// The valuation a ticker's series is computed with: this one, or the
+    /// close when some of its prices lack the value this one needs, so a
+    /// series never mixes two conventions.
+    pub fn for_series(self, missing: &MissingValues) -> Valuation {
+        let incomplete = match self {
+            Valuation::Close => false,
+            Valuation::AdjustedClose => missing.adjusted_price,
+            Valuation::Nav => missing.nav,
+            Valuation::BidAsk => missing.bid_ask,
+        };
+        if incomplete {
+            Valuation::Close
+        } else {
+            self
+        }
+    }
+}
+
+/// Price a unit held on a day is worth, from the prices stored for that day.
+pub trait ValuationStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn unit_price(&self, price: &DailyPrice) -> BigDecimal;
+}
+
+/// The market close, only adjusted for splits, for accounts that are paid
+/// their dividends out.
+pub struct CloseValuation;
+
+impl ValuationStrategy for CloseValuation {
+    fn name(&self) -> &'static str {
+        "close"
+    }
+
+    fn unit_price(&self, price: &DailyPrice) -> BigDecimal {
+        price.price.clone()
+    }
+}
+
+/// The close adjusted for splits and dividends, from providers that publish
+/// it, so that distributions show up as growth like in an accumulating fund.
+pub struct AdjustedCloseValuation;
+
+impl ValuationStrategy for AdjustedCloseValuation {
+    fn name(&self) -> &'static str {
+        "adjusted_close"
+    }
+
+    fn unit_price(&self, price: &DailyPrice) -> BigDecimal {
+        price
+            .adjusted_price
+            .clone()
+            .unwrap_or_else(|| price.price.clone())
+    }
+}
+
+/// The net asset value per unit the fund published, for accounts holding
+/// funds at the price they are redeemed at rather than traded at.
+pub struct NavValuation;
+
+impl ValuationStrategy for NavValuation {
+    fn name(&self) -> &'static str {
+        "nav"
+    }
+
+    fn unit_price(&self, price: &DailyPrice) -> BigDecimal {
+        price
+            .marks
+            .nav
+            .clone()
+            .unwrap_or_else(|| price.price.clone())
+    }
+}
+
+/// The middle of the bid and the ask at the close, for thinly traded
+/// instruments whose last trade can be far from where they could be sold.
+pub struct BidAskValuation;
+
+impl ValuationStrategy for BidAskValuation {
+    fn name(&self) -> &'static str {
+        "bid_ask"
+    }
+
+    fn unit_price(&self, price: &DailyPrice) -> BigDecimal {
+        match (&price.marks.bid, &price.marks.ask) {
+            (Some(bid), Some(ask)) => (bid + ask) / BigDecimal::from(2),
+            _ => price.price.clone(),
+        }
+    }
+}
+
+/// Key of the portfolio of every trade; the others are keyed by their tag.
+const ALL_TRADES: &str = "*";
+
+pub struct PortfolioValuation {
+    /// `None` for the portfolio of every trade.
+    pub tag: Option<String>,
+    pub valuation: Valuation,
+}
+
+/// Valuation set for the portfolio of the trades carrying `tag`, or of every
+/// trade, if one is.
+pub async fn get_portfolio_valuation(
+    pool: &DbPool,
+    tag: Option<&str>,
+) -> Result<Option<Valuation>, sqlx::Error> {
+    let portfolio = tag.unwrap_or(ALL_TRADES);
+    Ok(sqlx::query!(
+        r#"
+        SELECT valuation FROM portfolio_valuations WHERE portfolio = $1
+        "#,
+        portfolio
+    )
+    .fetch_optional(pool)
+    .await?
+    .and_then(|row| Valuation::from_name(&row.valuation)))
+}
+
+pub async fn set_portfolio_valuation(
+    pool: &DbPool,
+    tag: Option<&str>,
+    valuation: Valuation,
+) -> Result<(), sqlx::Error> {
+    let portfolio = tag.unwrap_or(ALL_TRADES);
+    let name = valuation.strategy().name();
+    sqlx::query!(
+        r#"
+        INSERT INTO portfolio_valuations ( portfolio, valuation ) VALUES ( $1, $2 )
+        ON CONFLICT ( portfolio ) DO UPDATE SET valuation = excluded.valuation
+        "#,
+        portfolio,
+        name
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn list_portfolio_valuations(
+    pool: &DbPool,
+) -> Result<Vec<PortfolioValuation>, sqlx::Error> {
+    Ok(sqlx::query!(
+        r#"
+        SELECT portfolio as "portfolio!", valuation FROM portfolio_valuations ORDER BY portfolio asc
+        "#
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(|row| {
+        Some(PortfolioValuation {
+            valuation: Valuation::from_name(&row.valuation)?,
+            tag: (row.portfolio != ALL_TRADES).then_some(row.portfolio),
+        })
+    })
+    .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::PriceMarks;
+    use crate::test_util::{date, decimal};
+
+    const VALUATIONS: [Valuation; 4] = [
+        Valuation::Close,
+        Valuation::AdjustedClose,
+        Valuation::Nav,
+        Valuation::BidAsk,
+    ];
+
+    fn daily_price(marks: PriceMarks) -> DailyPrice {
+        DailyPrice {
+            date: date("2024-05-02"),
+            price: decimal("80"),
+            ticker: "IWDA.AMS".to_string(),
+            preliminary: false,
+            adjusted_price: Some(decimal("78")),
+            split_coefficient: None,
+            marks,
+        }
+    }
+
+    #[test]
+    fn strategies_value_units_at_their_price() {
+        let price = daily_price(PriceMarks {
+            nav: Some(decimal("79.5")),
+            bid: Some(decimal("79.8")),
+            ask: Some(decimal("80.4")),
+        });
+
+        let unit_prices: Vec<BigDecimal> = VALUATIONS
+            .iter()
+            .map(|valuation| valuation.strategy().unit_price(&price))
+            .collect();
+
+        assert_eq!(
+            unit_prices,
+            vec![
+                decimal("80"),
+                decimal("78"),
+                decimal("79.5"),
+                decimal("80.1")
+            ]
+        );
+    }
+
+    #[test]
+    fn strategies_fall_back_to_the_close_without_their_price() {
+        let mut price = daily_price(PriceMarks {
+            bid: Some(decimal("79.8")),
+            ..PriceMarks::default()
+        });
+        price.adjusted_price = None;
+
+        for valuation in VALUATIONS {
+            assert_eq!(valuation.strategy().unit_price(&price), decimal("80"));
+        }
+    }
+
+    #[test]
+    fn series_missing_values_are_valued_at_the_close() {
+        let missing = MissingValues {
+            adjusted_price: true,
+            nav: false,
+            bid_ask: true,
+        };
+
+        let valuations: Vec<Valuation> = VALUATIONS
+            .iter()
+            .map(|valuation| valuation.for_series(&missing))
+            .collect();
+
+        assert!(
+            valuations
+                == vec![
+                    Valuation::Close,
+                    Valuation::Close,
+                    Valuation::Nav,
+                    Valuation::Close
+                ]
+        );
+    }
+
+    #[test]
+    fn valuations_are_stored_by_the_name_they_are_requested_with() {
+        for valuation in VALUATIONS {
+            let name = valuation.strategy().name();
+
+            assert!(Valuation::from_name(name) == Some(valuation));
+            assert_eq!(serde_json::to_value(valuation).unwrap(), name);
+        }
+        assert!(Valuation::from_name("mid").is_none());
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::NaiveDate;
+
+const DAYS_PER_YEAR: f64 = 365.0;
+const TOLERANCE: f64 = 1e-9;
+const MAX_ITERATIONS: usize = 100;
+const MIN_RATE: f64 = -0.999_999;
+const MAX_RATE: f64 = 100.0;
+
+pub struct CashFlow {
+    pub date: NaiveDate,
+    /// Negative for money going into the portfolio, positive for money coming out.
+    pub amount: f64,
+}
+
+fn years_between(from: NaiveDate, to: NaiveDate) -> f64 {
+    (to - from).num_days() as f64 / DAYS_PER_YEAR
+}
+
+fn npv(cash_flows: &[CashFlow], rate: f64, start: NaiveDate) -> f64 {
+    cash_flows
+        .iter()
+        .map(|flow| flow.amount / (1.0 + rate).powf(years_between(start, flow.date)))
+        .sum()
+}
+
+fn npv_derivative(cash_flows: &[CashFlow], rate: f64, start: NaiveDate) -> f64 {
+    cash_flows
+        .iter()
+        .map(|flow| {
+            let years = years_between(start, flow.date);
+            -years * flow.amount / (1.0 + rate).powf(years + 1.0)
+        })
+        .sum()
+}
+
+/// Annualized internal rate of return of irregularly spaced cash flows.
+/// Tries Newton-Raphson first and falls back to bisection when it doesn't
+/// converge. Returns `None` when the flows don't contain both an outflow and an
+/// inflow, or no rate in `MIN_RATE..MAX_RATE` zeroes the NPV.
+pub fn xirr(cash_flows: &[CashFlow]) -> Option<f64> {
+    let has_outflow = cash_flows.iter().any(|flow| flow.amount < 0.0);
+    let has_inflow = cash_flows.iter().any(|flow| flow.amount > 0.0);
+    if !has_outflow || !has_inflow {
+        return None;
+    }
+    let start = cash_flows.iter().map(|flow| flow.date).min()?;
+
+    let mut rate = 0.1;
+    for _ in 0..MAX_ITERATIONS {
+        let value = npv(cash_flows, rate, start);
+        if value.abs() < TOLERANCE {
+            return Some(rate);
+        }
+        let derivative = npv_derivative(cash_flows, rate, start);
+        if derivative == 0.0 || !derivative.is_finite() {
+            break;
+        }
+        let next_rate = rate - value / derivative;
+        if !next_rate.is_finite() || next_rate <= MIN_RATE || next_rate >= MAX_RATE {
+            break;
+        }
+        if (next_rate - rate).abs() < TOLERANCE {
+            return Some(next_rate);
+        }
+        rate = next_rate;
+    }
+
+    bisect(cash_flows, start)
+}
+
+fn bisect(cash_flows: &[CashFlow], start: NaiveDate) -> Option<f64> {
+    let mut low = MIN_RATE;
+    let mut high = MAX_RATE;
+    let mut npv_low = npv(cash_flows, low, start);
+    if npv_low.signum() == npv(cash_flows, high, start).signum() {
+        return None;
+    }
+    for _ in 0..MAX_ITERATIONS * 2 {
+        let middle = (low + high) / 2.0;
+        let npv_middle = npv(cash_flows, middle, start);
+        if npv_middle.abs() < TOLERANCE || (high - low) / 2.0 < TOLERANCE {
+            return Some(middle);
+        }
+        if npv_middle.signum() == npv_low.signum() {
+            low = middle;
+            npv_low = npv_middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flow(date: &str, amount: f64) -> CashFlow {
+        CashFlow {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            amount,
+        }
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("xirr should converge");
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn xirr_of_a_doubling_in_a_year() {
+        let cash_flows = vec![flow("2023-01-01", -1000.0), flow("2024-01-01", 2000.0)];
+
+        assert_close(xirr(&cash_flows), 1.0);
+    }
+
+    #[test]
+    fn xirr_zeroes_the_npv_of_irregular_flows() {
+        let cash_flows = vec![
+            flow("2023-01-01", -1000.0),
+            flow("2023-03-15", -500.0),
+            flow("2023-08-01", 200.0),
+            flow("2024-02-20", 1450.0),
+        ];
+
+        let rate = xirr(&cash_flows).expect("xirr should converge");
+
+        assert!(npv(&cash_flows, rate, cash_flows[0].date).abs() < 1e-6);
+    }
+
+    #[test]
+    fn xirr_falls_back_to_bisection_when_newton_overshoots() {
+        // Newton's first step from 10% lands far below -100%.
+        let cash_flows = vec![flow("2023-01-01", -1000.0), flow("2024-01-01", 5.0)];
+
+        assert_close(xirr(&cash_flows), -0.995);
+    }
+
+    #[test]
+    fn xirr_needs_an_outflow_and_an_inflow() {
+        let cash_flows = vec![flow("2023-01-01", -1000.0), flow("2024-01-01", -5.0)];
+
+        assert_eq!(xirr(&cash_flows), None);
+    }
+
+    #[test]
+    fn xirr_is_none_beyond_the_maximum_rate() {
+        let cash_flows = vec![flow("2023-01-01", -1000.0), flow("2023-01-31", 1500.0)];
+
+        assert_eq!(xirr(&cash_flows), None);
+    }
+}